@@ -0,0 +1,6 @@
+-- IDs handed out to benches announcing 0xFF, kept so they are never reused
+CREATE TABLE battery_ids (
+    battery_id INTEGER PRIMARY KEY NOT NULL,
+    port TEXT NOT NULL,
+    assigned_at TEXT NOT NULL
+);
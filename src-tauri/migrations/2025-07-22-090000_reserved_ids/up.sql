@@ -0,0 +1,8 @@
+-- IDs an operator set aside for a labelled cell before starting a test
+CREATE TABLE reserved_ids (
+    battery_id INTEGER PRIMARY KEY NOT NULL,
+    cell_label TEXT NOT NULL UNIQUE,
+    test_id INTEGER,
+    reserved_at TEXT NOT NULL,
+    FOREIGN KEY (test_id) REFERENCES tests(test_id) ON DELETE SET NULL
+);
@@ -0,0 +1,12 @@
+-- Every RequestCompletion frame a bench sent, decoded
+CREATE TABLE completion_events (
+    record_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    battery_id INTEGER NOT NULL,
+    port TEXT NOT NULL,
+    phase TEXT NOT NULL,
+    outcome TEXT NOT NULL,
+    flags INTEGER NOT NULL,
+    received_at TEXT NOT NULL,
+    test_id INTEGER,
+    FOREIGN KEY (test_id) REFERENCES tests(test_id) ON DELETE CASCADE
+);
@@ -0,0 +1,11 @@
+-- This file should undo anything in `up.sql`
+CREATE TABLE tests_without_profile (
+    test_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    test_name TEXT NOT NULL,
+    start_date TEXT NOT NULL
+);
+INSERT INTO tests_without_profile (test_id, test_name, start_date)
+    SELECT test_id, test_name, start_date FROM tests;
+DROP TABLE tests;
+ALTER TABLE tests_without_profile RENAME TO tests;
+DROP TABLE test_profiles;
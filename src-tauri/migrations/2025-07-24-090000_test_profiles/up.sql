@@ -0,0 +1,8 @@
+-- Step lists a test runs on every battery, stored as the JSON of a TestProfile
+CREATE TABLE test_profiles (
+    profile_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL UNIQUE,
+    definition TEXT NOT NULL,
+    created_at TEXT NOT NULL
+);
+ALTER TABLE tests ADD COLUMN profile_id INTEGER REFERENCES test_profiles(profile_id) ON DELETE SET NULL;
@@ -0,0 +1,25 @@
+-- Interlock limits of a test, checked against every sample. NULL leaves a
+-- quantity unchecked.
+CREATE TABLE safety_limits (
+    test_id INTEGER PRIMARY KEY NOT NULL,
+    max_battery_temperature_c REAL,
+    max_mosfet_temperature_c REAL,
+    max_resistor_temperature_c REAL,
+    min_voltage_mv REAL,
+    max_voltage_mv REAL,
+    max_current_ma REAL,
+    FOREIGN KEY (test_id) REFERENCES tests(test_id) ON DELETE CASCADE
+);
+-- Limit violations that forced a battery into standby
+CREATE TABLE alarms (
+    alarm_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    battery_id INTEGER NOT NULL,
+    port TEXT NOT NULL,
+    quantity TEXT NOT NULL,
+    value REAL NOT NULL,
+    threshold REAL NOT NULL,
+    state TEXT NOT NULL,
+    raised_at TEXT NOT NULL,
+    test_id INTEGER,
+    FOREIGN KEY (test_id) REFERENCES tests(test_id) ON DELETE CASCADE
+);
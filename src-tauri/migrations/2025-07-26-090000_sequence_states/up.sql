@@ -0,0 +1,17 @@
+-- Where each battery of a sequence is, written on every transition so a
+-- sequence interrupted by a crash or restart can be resumed
+CREATE TABLE sequence_states (
+    test_id INTEGER NOT NULL,
+    battery_id INTEGER NOT NULL,
+    port TEXT NOT NULL,
+    step INTEGER NOT NULL,
+    steps INTEGER NOT NULL,
+    step_label TEXT NOT NULL,
+    state TEXT NOT NULL,
+    outcome TEXT NOT NULL,
+    step_started_at TEXT NOT NULL,
+    charge_mah REAL NOT NULL,
+    updated_at TEXT NOT NULL,
+    PRIMARY KEY (test_id, battery_id),
+    FOREIGN KEY (test_id) REFERENCES tests(test_id) ON DELETE CASCADE
+);
@@ -0,0 +1,26 @@
+-- This file should undo anything in `up.sql`
+CREATE TABLE battery_logs_integer (
+    record_id integer primary key autoincrement,
+    id integer not null,
+    port TEXT NOT NULL,
+    battery_temperature INTEGER NOT NULL,
+    bench_temperature_mosfet INTEGER NOT NULL,
+    bench_temperature_resistor INTEGER NOT NULL,
+    load INTEGER NOT NULL,
+    voltage INTEGER NOT NULL,
+    current INTEGER NOT NULL,
+    state TEXT NOT NULL,
+    status TEXT NOT NULL,
+    start_date TEXT,
+    end_date TEXT,
+    test_id INTEGER NOT NULL,
+    FOREIGN KEY (test_id) REFERENCES tests(test_id) ON DELETE CASCADE
+);
+INSERT INTO battery_logs_integer
+    SELECT record_id, id, port, CAST(battery_temperature AS INTEGER),
+        CAST(bench_temperature_mosfet AS INTEGER), CAST(bench_temperature_resistor AS INTEGER),
+        CAST(ROUND(load) AS INTEGER), CAST(ROUND(voltage) AS INTEGER),
+        CAST(ROUND(current) AS INTEGER), state, status, start_date, end_date, test_id
+    FROM battery_logs;
+DROP TABLE battery_logs;
+ALTER TABLE battery_logs_integer RENAME TO battery_logs;
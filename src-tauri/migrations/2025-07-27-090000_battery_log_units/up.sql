@@ -0,0 +1,27 @@
+-- Store readings at the resolution the bench sends them: °C with 0.01
+-- resolution, ohms, mV and mA. Rows logged before this have whole-degree
+-- temperatures, and their voltage and current were read from the wrong bytes.
+CREATE TABLE battery_logs_real (
+    record_id integer primary key autoincrement,
+    id integer not null,
+    port TEXT NOT NULL,
+    battery_temperature REAL NOT NULL,
+    bench_temperature_mosfet REAL NOT NULL,
+    bench_temperature_resistor REAL NOT NULL,
+    load REAL NOT NULL,
+    voltage REAL NOT NULL,
+    current REAL NOT NULL,
+    state TEXT NOT NULL,
+    status TEXT NOT NULL,
+    start_date TEXT,
+    end_date TEXT,
+    test_id INTEGER NOT NULL,
+    FOREIGN KEY (test_id) REFERENCES tests(test_id) ON DELETE CASCADE
+);
+INSERT INTO battery_logs_real
+    SELECT record_id, id, port, battery_temperature, bench_temperature_mosfet,
+        bench_temperature_resistor, load, voltage, current, state, status,
+        start_date, end_date, test_id
+    FROM battery_logs;
+DROP TABLE battery_logs;
+ALTER TABLE battery_logs_real RENAME TO battery_logs;
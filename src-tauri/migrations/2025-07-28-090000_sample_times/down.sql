@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX battery_logs_test_elapsed;
+DROP INDEX battery_logs_recorded_at;
+ALTER TABLE battery_logs DROP COLUMN elapsed_s;
+ALTER TABLE battery_logs DROP COLUMN recorded_at;
@@ -0,0 +1,18 @@
+-- Wall-clock UTC time of each sample, and seconds since its test started on
+-- a monotonic clock. Earlier rows get their start date, or the test's, and
+-- the wall-clock difference to the test's start.
+ALTER TABLE battery_logs ADD COLUMN recorded_at TEXT NOT NULL DEFAULT '';
+ALTER TABLE battery_logs ADD COLUMN elapsed_s DOUBLE NOT NULL DEFAULT 0;
+UPDATE battery_logs SET recorded_at = COALESCE(
+    start_date,
+    (SELECT tests.start_date FROM tests WHERE tests.test_id = battery_logs.test_id),
+    ''
+);
+UPDATE battery_logs SET elapsed_s = MAX(0, COALESCE(
+    (julianday(recorded_at) - julianday(
+        (SELECT tests.start_date FROM tests WHERE tests.test_id = battery_logs.test_id)
+    )) * 86400,
+    0
+));
+CREATE INDEX battery_logs_recorded_at ON battery_logs (recorded_at);
+CREATE INDEX battery_logs_test_elapsed ON battery_logs (test_id, elapsed_s);
@@ -0,0 +1,20 @@
+-- Charge moved and energy of each charge or discharge phase of a test,
+-- recomputed from battery_logs whenever the test is analyzed
+CREATE TABLE phase_results (
+    test_id INTEGER NOT NULL,
+    battery_id INTEGER NOT NULL,
+    phase_index INTEGER NOT NULL,
+    cycle INTEGER NOT NULL,
+    state TEXT NOT NULL,
+    label TEXT NOT NULL,
+    outcome TEXT,
+    started_at TEXT NOT NULL,
+    ended_at TEXT NOT NULL,
+    duration_s DOUBLE NOT NULL,
+    samples INTEGER NOT NULL,
+    charge_mah DOUBLE NOT NULL,
+    energy_wh DOUBLE NOT NULL,
+    computed_at TEXT NOT NULL,
+    PRIMARY KEY (test_id, battery_id, phase_index),
+    FOREIGN KEY (test_id) REFERENCES tests(test_id) ON DELETE CASCADE
+);
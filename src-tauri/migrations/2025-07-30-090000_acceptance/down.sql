@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE criterion_results;
+DROP TABLE cell_verdicts;
+DROP TABLE acceptance_criteria;
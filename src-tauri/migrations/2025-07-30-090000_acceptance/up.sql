@@ -0,0 +1,33 @@
+-- Acceptance criteria a test's cells are evaluated against, each one
+-- skipped while NULL
+CREATE TABLE acceptance_criteria (
+    test_id INTEGER PRIMARY KEY NOT NULL,
+    min_discharge_capacity_mah REAL,
+    max_capacity_fade_pct REAL,
+    max_temperature_rise_c REAL,
+    min_voltage_mv REAL,
+    max_voltage_mv REAL,
+    max_internal_resistance_mohm REAL,
+    FOREIGN KEY (test_id) REFERENCES tests(test_id) ON DELETE CASCADE
+);
+-- Verdict of the last evaluation of each cell of a test
+CREATE TABLE cell_verdicts (
+    test_id INTEGER NOT NULL,
+    battery_id INTEGER NOT NULL,
+    verdict TEXT NOT NULL,
+    evaluated_at TEXT NOT NULL,
+    PRIMARY KEY (test_id, battery_id),
+    FOREIGN KEY (test_id) REFERENCES tests(test_id) ON DELETE CASCADE
+);
+-- What each criterion measured for the verdict
+CREATE TABLE criterion_results (
+    test_id INTEGER NOT NULL,
+    battery_id INTEGER NOT NULL,
+    criterion TEXT NOT NULL,
+    measured DOUBLE,
+    threshold DOUBLE NOT NULL,
+    outcome TEXT NOT NULL,
+    evidence TEXT NOT NULL,
+    PRIMARY KEY (test_id, battery_id, criterion),
+    FOREIGN KEY (test_id) REFERENCES tests(test_id) ON DELETE CASCADE
+);
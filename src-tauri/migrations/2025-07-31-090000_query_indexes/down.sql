@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX alarms_test;
+DROP INDEX completion_events_test;
+DROP INDEX battery_logs_test_battery_elapsed;
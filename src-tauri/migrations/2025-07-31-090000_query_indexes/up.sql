@@ -0,0 +1,5 @@
+-- Per-battery reads of a test in time order, and the per-test lookups of
+-- events and alarms.
+CREATE INDEX battery_logs_test_battery_elapsed ON battery_logs (test_id, id, elapsed_s);
+CREATE INDEX completion_events_test ON completion_events (test_id);
+CREATE INDEX alarms_test ON alarms (test_id);
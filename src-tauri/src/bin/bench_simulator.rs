@@ -0,0 +1,126 @@
+//! Runs a simulated bench for development without hardware.
+//!
+//! By default it opens a pseudo-terminal pair and prints the name of the end
+//! the GUI should connect to. With `--tcp ADDR` it listens on a socket
+//! instead, which the GUI reaches as `tcp://ADDR`.
+
+use std::{net::TcpListener, process, sync::atomic::AtomicBool};
+
+use battery_test_gui_lib::serial::{
+    simulator::{BenchSimulator, SimulatorConfig},
+    transport::{TcpTransport, Transport, READ_TIMEOUT},
+};
+
+const USAGE: &str = "usage: bench_simulator [--cells N] [--capacity MAH] [--time-scale X] [--no-heartbeat] [--tcp ADDR]";
+
+struct Args {
+    config: SimulatorConfig,
+    tcp: Option<String>,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut args = Args {
+        config: SimulatorConfig::default(),
+        tcp: None,
+    };
+    let mut iter = std::env::args().skip(1);
+
+    while let Some(arg) = iter.next() {
+        let mut value = || iter.next().ok_or(format!("{arg} needs a value"));
+
+        match arg.as_str() {
+            "--cells" => {
+                args.config.cells = value()?.parse().map_err(|e| format!("--cells: {e}"))?
+            }
+            "--capacity" => {
+                args.config.capacity_mah =
+                    value()?.parse().map_err(|e| format!("--capacity: {e}"))?
+            }
+            "--time-scale" => {
+                args.config.time_scale =
+                    value()?.parse().map_err(|e| format!("--time-scale: {e}"))?
+            }
+            "--no-heartbeat" => args.config.heartbeat_timeout = None,
+            "--tcp" => args.tcp = Some(value()?),
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                process::exit(0);
+            }
+            other => return Err(format!("unknown argument {other}")),
+        }
+    }
+
+    Ok(args)
+}
+
+fn serve(config: &SimulatorConfig, transport: Box<dyn Transport>) {
+    let running = AtomicBool::new(true);
+    match BenchSimulator::new(config.clone()).run(transport, &running) {
+        Ok(()) => println!("Connection closed"),
+        Err(error) => println!("Connection lost: {error}"),
+    }
+}
+
+fn serve_tcp(config: &SimulatorConfig, address: &str) -> std::io::Result<()> {
+    let listener = TcpListener::bind(address)?;
+    println!(
+        "Simulated bench listening on tcp://{}",
+        listener.local_addr()?
+    );
+
+    for stream in listener.incoming() {
+        let stream = stream?;
+        println!("GUI connected from {}", stream.peer_addr()?);
+
+        serve(config, Box::new(TcpTransport::from(stream)));
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn serve_pty(config: &SimulatorConfig) -> Result<(), serialport::Error> {
+    use std::time::Duration;
+
+    use battery_test_gui_lib::serial::transport::SerialTransport;
+    use serialport::{SerialPort, TTYPort};
+
+    let (mut master, slave) = TTYPort::pair()?;
+    master.set_timeout(READ_TIMEOUT)?;
+    println!(
+        "Simulated bench on {}",
+        slave.name().unwrap_or_else(|| "<unnamed pty>".to_string())
+    );
+
+    // `slave` stays open for as long as we run, otherwise the master reads
+    // EOF whenever the GUI is not connected
+    loop {
+        let port: Box<dyn SerialPort> = Box::new(master.try_clone_native()?);
+        serve(config, Box::new(SerialTransport::from(port)));
+        std::thread::sleep(Duration::from_secs(1));
+    }
+}
+
+#[cfg(not(unix))]
+fn serve_pty(_config: &SimulatorConfig) -> Result<(), serialport::Error> {
+    Err(serialport::Error::new(
+        serialport::ErrorKind::Unknown,
+        "pseudo-terminals need a unix host, use --tcp instead",
+    ))
+}
+
+fn main() {
+    let args = parse_args().unwrap_or_else(|error| {
+        eprintln!("{error}\n{USAGE}");
+        process::exit(2);
+    });
+
+    let result = match &args.tcp {
+        Some(address) => serve_tcp(&args.config, address).map_err(|e| e.to_string()),
+        None => serve_pty(&args.config).map_err(|e| e.to_string()),
+    };
+
+    if let Err(error) = result {
+        eprintln!("{error}");
+        process::exit(1);
+    }
+}
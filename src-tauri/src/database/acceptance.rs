@@ -0,0 +1,652 @@
+use std::sync::Mutex;
+
+use diesel::prelude::*;
+use diesel::SqliteConnection;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tauri::State;
+
+use crate::database::analysis::{analyze_test, test_logs};
+use crate::database::models::{
+    AcceptanceCriteria, BatteryLog, CellVerdict, CriterionResult, PhaseResult,
+};
+use crate::database::sqlite::connection;
+use crate::serial::pilot::{get_current_time, BatteryState};
+use crate::state::AppState;
+
+/// Smallest change in current between two samples that is used to estimate
+/// internal resistance, in mA.
+const MIN_CURRENT_STEP_MA: f64 = 100.0;
+
+/// Longest gap between the two samples of a current step, so the open
+/// circuit voltage hasn't moved much in between.
+const MAX_STEP_GAP_S: f64 = 30.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Type, Serialize, Deserialize)]
+pub enum Criterion {
+    DischargeCapacity,
+    CapacityFade,
+    TemperatureRise,
+    MinVoltage,
+    MaxVoltage,
+    InternalResistance,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Type, Serialize, Deserialize)]
+pub enum CriterionOutcome {
+    Pass,
+    Fail,
+    /// The test has no data to measure the criterion with.
+    NoData,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Type, Serialize, Deserialize)]
+pub enum Verdict {
+    Pass,
+    Fail,
+    /// Nothing failed, but a criterion could not be measured or none are set.
+    Inconclusive,
+}
+
+impl Verdict {
+    /// Reads back a stored verdict. Anything unknown is inconclusive.
+    pub fn parse(verdict: &str) -> Self {
+        match verdict {
+            "Pass" => Verdict::Pass,
+            "Fail" => Verdict::Fail,
+            _ => Verdict::Inconclusive,
+        }
+    }
+}
+
+/// Verdict of one cell with the evidence for it.
+#[derive(Debug, Clone, Type, Serialize, Deserialize)]
+pub struct BatteryVerdict {
+    pub battery_id: i32,
+    pub verdict: Verdict,
+    pub evaluated_at: String,
+    pub criteria: Vec<CriterionResult>,
+}
+
+/// One row per criterion of a cell, as written to exports.
+#[derive(Debug, Clone, Serialize)]
+pub struct VerdictRow {
+    pub test_id: i32,
+    pub battery_id: i32,
+    pub verdict: String,
+    pub evaluated_at: String,
+    pub criterion: String,
+    pub measured: Option<f64>,
+    pub threshold: f64,
+    pub outcome: String,
+    pub evidence: String,
+}
+
+impl AcceptanceCriteria {
+    /// Nothing is checked until the operator sets the criteria of the test.
+    pub fn unset(test_id: i32) -> Self {
+        AcceptanceCriteria {
+            test_id,
+            min_discharge_capacity_mah: None,
+            max_capacity_fade_pct: None,
+            max_temperature_rise_c: None,
+            min_voltage_mv: None,
+            max_voltage_mv: None,
+            max_internal_resistance_mohm: None,
+        }
+    }
+}
+
+/// A measurement and where it comes from, or why there is none.
+type Measurement = (Option<f64>, String);
+
+fn discharges(phases: &[PhaseResult]) -> Vec<&PhaseResult> {
+    phases
+        .iter()
+        .filter(|phase| phase.state == format!("{:?}", BatteryState::Discharge))
+        .collect()
+}
+
+fn discharge_capacity(phases: &[PhaseResult]) -> Measurement {
+    match discharges(phases).last() {
+        Some(last) => (
+            Some(last.charge_mah),
+            format!("discharge of cycle {} ({})", last.cycle, last.label),
+        ),
+        None => (None, "no discharge phase".to_string()),
+    }
+}
+
+fn capacity_fade(phases: &[PhaseResult]) -> Measurement {
+    let discharges = discharges(phases);
+    match (discharges.first(), discharges.last()) {
+        (Some(first), Some(last)) if discharges.len() >= 2 && first.charge_mah > 0.0 => (
+            Some((first.charge_mah - last.charge_mah) / first.charge_mah * 100.0),
+            format!(
+                "cycle {}: {:.0} mAh, cycle {}: {:.0} mAh",
+                first.cycle, first.charge_mah, last.cycle, last.charge_mah
+            ),
+        ),
+        _ => (None, "fewer than two discharge phases".to_string()),
+    }
+}
+
+fn temperature_rise(logs: &[BatteryLog]) -> Measurement {
+    let Some(first) = logs.first() else {
+        return (None, "no samples".to_string());
+    };
+    let hottest = logs
+        .iter()
+        .map(|log| log.battery_temperature)
+        .fold(f32::MIN, f32::max);
+
+    (
+        Some((hottest - first.battery_temperature) as f64),
+        format!(
+            "from {:.2} °C to {:.2} °C",
+            first.battery_temperature, hottest
+        ),
+    )
+}
+
+fn voltage_extreme(logs: &[BatteryLog], lowest: bool) -> Measurement {
+    let voltages = logs.iter().map(|log| log.voltage as f64);
+    let extreme = match lowest {
+        true => voltages.reduce(f64::min),
+        false => voltages.reduce(f64::max),
+    };
+    match extreme {
+        Some(voltage) => (Some(voltage), format!("over {} samples", logs.len())),
+        None => (None, "no samples".to_string()),
+    }
+}
+
+/// Median of ΔV/ΔI over consecutive samples where the current stepped, in
+/// mΩ. With mV and mA the ratio is in ohms.
+fn internal_resistance(logs: &[BatteryLog]) -> Measurement {
+    let mut estimates: Vec<f64> = logs
+        .windows(2)
+        .filter(|pair| pair[1].elapsed_s - pair[0].elapsed_s <= MAX_STEP_GAP_S)
+        .filter_map(|pair| {
+            let current_step = (pair[1].current - pair[0].current) as f64;
+            let voltage_step = (pair[1].voltage - pair[0].voltage) as f64;
+            (current_step.abs() >= MIN_CURRENT_STEP_MA)
+                .then(|| voltage_step / current_step * 1000.0)
+        })
+        .collect();
+
+    if estimates.is_empty() {
+        return (None, "no current step".to_string());
+    }
+    estimates.sort_by(f64::total_cmp);
+    let middle = estimates.len() / 2;
+    let median = match estimates.len() % 2 {
+        0 => (estimates[middle - 1] + estimates[middle]) / 2.0,
+        _ => estimates[middle],
+    };
+
+    (
+        Some(median),
+        format!("median of {} current steps", estimates.len()),
+    )
+}
+
+/// Evaluates one cell from its samples, in time order, and its phases.
+pub fn evaluate_battery(
+    criteria: &AcceptanceCriteria,
+    battery_id: i32,
+    logs: &[BatteryLog],
+    phases: &[PhaseResult],
+) -> (Verdict, Vec<CriterionResult>) {
+    // (criterion, threshold, whether the measurement has to stay below it)
+    let checks = [
+        (
+            Criterion::DischargeCapacity,
+            criteria.min_discharge_capacity_mah,
+            false,
+        ),
+        (
+            Criterion::CapacityFade,
+            criteria.max_capacity_fade_pct,
+            true,
+        ),
+        (
+            Criterion::TemperatureRise,
+            criteria.max_temperature_rise_c,
+            true,
+        ),
+        (Criterion::MinVoltage, criteria.min_voltage_mv, false),
+        (Criterion::MaxVoltage, criteria.max_voltage_mv, true),
+        (
+            Criterion::InternalResistance,
+            criteria.max_internal_resistance_mohm,
+            true,
+        ),
+    ];
+
+    let results: Vec<CriterionResult> = checks
+        .into_iter()
+        .filter_map(|(criterion, threshold, ceiling)| {
+            let threshold = threshold? as f64;
+            let (measured, evidence) = match criterion {
+                Criterion::DischargeCapacity => discharge_capacity(phases),
+                Criterion::CapacityFade => capacity_fade(phases),
+                Criterion::TemperatureRise => temperature_rise(logs),
+                Criterion::MinVoltage => voltage_extreme(logs, true),
+                Criterion::MaxVoltage => voltage_extreme(logs, false),
+                Criterion::InternalResistance => internal_resistance(logs),
+            };
+            let outcome = match measured {
+                None => CriterionOutcome::NoData,
+                Some(value)
+                    if (ceiling && value <= threshold) || (!ceiling && value >= threshold) =>
+                {
+                    CriterionOutcome::Pass
+                }
+                Some(_) => CriterionOutcome::Fail,
+            };
+
+            Some(CriterionResult {
+                test_id: criteria.test_id,
+                battery_id,
+                criterion: format!("{:?}", criterion),
+                measured,
+                threshold,
+                outcome: format!("{:?}", outcome),
+                evidence,
+            })
+        })
+        .collect();
+
+    let outcome = |wanted: CriterionOutcome| {
+        results
+            .iter()
+            .any(|result| result.outcome == format!("{:?}", wanted))
+    };
+    let verdict = if outcome(CriterionOutcome::Fail) {
+        Verdict::Fail
+    } else if results.is_empty() || outcome(CriterionOutcome::NoData) {
+        Verdict::Inconclusive
+    } else {
+        Verdict::Pass
+    };
+
+    (verdict, results)
+}
+
+/// The acceptance criteria of a test, or unset ones when it has none.
+pub fn criteria_for_test(
+    conn: &mut SqliteConnection,
+    target_test_id: i32,
+) -> QueryResult<AcceptanceCriteria> {
+    use crate::database::schema::acceptance_criteria::dsl::*;
+    Ok(acceptance_criteria
+        .filter(test_id.eq(target_test_id))
+        .first::<AcceptanceCriteria>(conn)
+        .optional()?
+        .unwrap_or_else(|| AcceptanceCriteria::unset(target_test_id)))
+}
+
+/// Analyzes the test again and evaluates each of its cells, replacing the
+/// stored verdicts.
+pub fn evaluate_test(
+    conn: &mut SqliteConnection,
+    target_test_id: i32,
+) -> QueryResult<Vec<BatteryVerdict>> {
+    use crate::database::schema::{cell_verdicts, criterion_results};
+
+    let criteria = criteria_for_test(conn, target_test_id)?;
+    let analysis = analyze_test(conn, target_test_id)?;
+    let logs = test_logs(conn, target_test_id)?;
+    let evaluated_at = get_current_time();
+
+    let verdicts: Vec<BatteryVerdict> = logs
+        .chunk_by(|a, b| a.id == b.id)
+        .map(|battery| {
+            let battery_id = battery[0].id;
+            let phases: Vec<PhaseResult> = analysis
+                .phases
+                .iter()
+                .filter(|phase| phase.battery_id == battery_id)
+                .cloned()
+                .collect();
+            let (verdict, criteria) = evaluate_battery(&criteria, battery_id, battery, &phases);
+
+            BatteryVerdict {
+                battery_id,
+                verdict,
+                evaluated_at: evaluated_at.clone(),
+                criteria,
+            }
+        })
+        .collect();
+
+    let cells: Vec<CellVerdict> = verdicts
+        .iter()
+        .map(|verdict| CellVerdict {
+            test_id: target_test_id,
+            battery_id: verdict.battery_id,
+            verdict: format!("{:?}", verdict.verdict),
+            evaluated_at: verdict.evaluated_at.clone(),
+        })
+        .collect();
+    let results: Vec<CriterionResult> = verdicts
+        .iter()
+        .flat_map(|verdict| verdict.criteria.iter().cloned())
+        .collect();
+
+    conn.transaction(|conn| {
+        diesel::delete(cell_verdicts::table.filter(cell_verdicts::test_id.eq(target_test_id)))
+            .execute(conn)?;
+        diesel::delete(
+            criterion_results::table.filter(criterion_results::test_id.eq(target_test_id)),
+        )
+        .execute(conn)?;
+        diesel::insert_into(cell_verdicts::table)
+            .values(&cells)
+            .execute(conn)?;
+        diesel::insert_into(criterion_results::table)
+            .values(&results)
+            .execute(conn)
+    })?;
+
+    Ok(verdicts)
+}
+
+/// The verdicts stored for `target_test_id` by its last evaluation.
+pub fn load_verdicts(
+    conn: &mut SqliteConnection,
+    target_test_id: i32,
+) -> QueryResult<Vec<BatteryVerdict>> {
+    use crate::database::schema::{cell_verdicts, criterion_results};
+
+    let cells = cell_verdicts::table
+        .filter(cell_verdicts::test_id.eq(target_test_id))
+        .order(cell_verdicts::battery_id)
+        .load::<CellVerdict>(conn)?;
+    let results = criterion_results::table
+        .filter(criterion_results::test_id.eq(target_test_id))
+        .load::<CriterionResult>(conn)?;
+
+    Ok(cells
+        .into_iter()
+        .map(|cell| BatteryVerdict {
+            battery_id: cell.battery_id,
+            verdict: Verdict::parse(&cell.verdict),
+            evaluated_at: cell.evaluated_at,
+            criteria: results
+                .iter()
+                .filter(|result| result.battery_id == cell.battery_id)
+                .cloned()
+                .collect(),
+        })
+        .collect())
+}
+
+/// The stored verdicts of a test, or of every test when `target_test_id`
+/// is `None`, one row per criterion. Cells without criteria get one row with
+/// an empty criterion.
+pub fn verdict_rows(
+    conn: &mut SqliteConnection,
+    target_test_id: Option<i32>,
+) -> QueryResult<Vec<VerdictRow>> {
+    use crate::database::schema::{cell_verdicts, criterion_results};
+
+    let mut query = cell_verdicts::table
+        .left_join(
+            criterion_results::table.on(criterion_results::test_id
+                .eq(cell_verdicts::test_id)
+                .and(criterion_results::battery_id.eq(cell_verdicts::battery_id))),
+        )
+        .order((
+            cell_verdicts::test_id,
+            cell_verdicts::battery_id,
+            criterion_results::criterion,
+        ))
+        .into_boxed();
+    if let Some(target) = target_test_id {
+        query = query.filter(cell_verdicts::test_id.eq(target));
+    }
+
+    let rows = query.load::<(CellVerdict, Option<CriterionResult>)>(conn)?;
+    Ok(rows
+        .into_iter()
+        .map(|(cell, result)| VerdictRow {
+            test_id: cell.test_id,
+            battery_id: cell.battery_id,
+            verdict: cell.verdict,
+            evaluated_at: cell.evaluated_at,
+            criterion: result
+                .as_ref()
+                .map(|r| r.criterion.clone())
+                .unwrap_or_default(),
+            measured: result.as_ref().and_then(|r| r.measured),
+            threshold: result.as_ref().map_or(0.0, |r| r.threshold),
+            outcome: result
+                .as_ref()
+                .map(|r| r.outcome.clone())
+                .unwrap_or_default(),
+            evidence: result.map(|r| r.evidence).unwrap_or_default(),
+        })
+        .collect())
+}
+
+#[tauri::command]
+#[specta::specta]
+pub fn get_acceptance_criteria(
+    state: State<'_, Mutex<AppState>>,
+    target_test_id: i32,
+) -> Result<AcceptanceCriteria, String> {
+    let mut state = state.lock().map_err(|e| e.to_string())?;
+    let conn = connection(&mut state).map_err(|e| e.to_string())?;
+
+    criteria_for_test(conn, target_test_id)
+        .map_err(|e| format!("Failed to load criteria of test {}: {}", target_test_id, e))
+}
+
+#[tauri::command]
+#[specta::specta]
+pub fn set_acceptance_criteria(
+    state: State<'_, Mutex<AppState>>,
+    criteria: AcceptanceCriteria,
+) -> Result<AcceptanceCriteria, String> {
+    let mut state = state.lock().map_err(|e| e.to_string())?;
+    let conn = connection(&mut state).map_err(|e| e.to_string())?;
+
+    diesel::replace_into(crate::database::schema::acceptance_criteria::table)
+        .values(&criteria)
+        .execute(conn)
+        .map_err(|e| format!("Failed to set criteria of test {}: {}", criteria.test_id, e))?;
+
+    Ok(criteria)
+}
+
+/// Evaluates every cell of the test against its acceptance criteria.
+#[tauri::command]
+#[specta::specta]
+pub fn evaluate_acceptance(
+    state: State<'_, Mutex<AppState>>,
+    target_test_id: i32,
+) -> Result<Vec<BatteryVerdict>, String> {
+    let mut state = state.lock().map_err(|e| e.to_string())?;
+    let conn = connection(&mut state).map_err(|e| e.to_string())?;
+
+    evaluate_test(conn, target_test_id)
+        .map_err(|e| format!("Failed to evaluate test {}: {}", target_test_id, e))
+}
+
+#[tauri::command]
+#[specta::specta]
+pub fn get_verdicts(
+    state: State<'_, Mutex<AppState>>,
+    target_test_id: i32,
+) -> Result<Vec<BatteryVerdict>, String> {
+    let mut state = state.lock().map_err(|e| e.to_string())?;
+    let conn = connection(&mut state).map_err(|e| e.to_string())?;
+
+    load_verdicts(conn, target_test_id)
+        .map_err(|e| format!("Failed to load verdicts of test {}: {}", target_test_id, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::analysis::battery_phases;
+    use crate::database::models::Test;
+    use crate::database::sqlite::{establish_connection, MIGRATIONS};
+    use diesel_migrations::MigrationHarness;
+
+    const START: &str = "2025-07-30T08:00:00+00:00";
+
+    /// Two cycles of a cell with a 50 mΩ internal resistance sampled every
+    /// 30 s: 1000 mAh in and out, then 1000 mAh in and only 500 mAh out.
+    /// The battery warms up by up to 10 °C while discharging.
+    fn two_cycles() -> Vec<BatteryLog> {
+        let start = chrono::DateTime::parse_from_rfc3339(START).unwrap();
+        let phases = [
+            ("Charge", "Cycle 1", 1000.0, 120),
+            ("Discharge", "Cycle 1", -1000.0, 120),
+            ("Charge", "Cycle 2", 1000.0, 120),
+            ("Discharge", "Cycle 2", -1000.0, 60),
+        ];
+
+        let mut logs = Vec::new();
+        let (mut charge_mah, mut elapsed_s) = (0.0f32, 0.0f64);
+        for (state, status, current, steps) in phases {
+            for step in 0..=steps {
+                let heating = match state {
+                    "Discharge" => 10.0 * step as f32 / 120.0,
+                    _ => 0.0,
+                };
+                logs.push(BatteryLog {
+                    record_id: None,
+                    id: 2,
+                    port: "COM3".to_string(),
+                    battery_temperature: 25.0 + heating,
+                    bench_temperature_mosfet: 30.0,
+                    bench_temperature_resistor: 30.0,
+                    load: 0.0,
+                    voltage: 3000.0 + 0.5 * charge_mah + current * 0.05,
+                    current,
+                    state: state.to_string(),
+                    status: status.to_string(),
+                    start_date: None,
+                    end_date: None,
+                    test_id: 1,
+                    recorded_at: (start + chrono::Duration::seconds(elapsed_s as i64)).to_rfc3339(),
+                    elapsed_s,
+                });
+                if step < steps {
+                    charge_mah += current * 30.0 / 3600.0;
+                }
+                elapsed_s += 30.0;
+            }
+        }
+        logs
+    }
+
+    fn criteria() -> AcceptanceCriteria {
+        AcceptanceCriteria {
+            min_discharge_capacity_mah: Some(400.0),
+            max_capacity_fade_pct: Some(60.0),
+            max_temperature_rise_c: Some(15.0),
+            min_voltage_mv: Some(2900.0),
+            max_voltage_mv: Some(3600.0),
+            max_internal_resistance_mohm: Some(80.0),
+            ..AcceptanceCriteria::unset(1)
+        }
+    }
+
+    fn measured(results: &[CriterionResult], criterion: Criterion) -> f64 {
+        results
+            .iter()
+            .find(|result| result.criterion == format!("{:?}", criterion))
+            .and_then(|result| result.measured)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_evaluate_battery() {
+        let logs = two_cycles();
+        let phases = battery_phases(1, 2, &logs, &[]);
+
+        let (verdict, results) = evaluate_battery(&criteria(), 2, &logs, &phases);
+        assert_eq!(verdict, Verdict::Pass, "{:#?}", results);
+        assert_eq!(results.len(), 6);
+        assert!((measured(&results, Criterion::DischargeCapacity) - 500.0).abs() < 1e-3);
+        assert!((measured(&results, Criterion::CapacityFade) - 50.0).abs() < 1e-3);
+        assert!((measured(&results, Criterion::TemperatureRise) - 10.0).abs() < 1e-3);
+        // the open circuit voltage moves a little between the two samples
+        assert!((measured(&results, Criterion::InternalResistance) - 50.0).abs() < 5.0);
+
+        let strict = AcceptanceCriteria {
+            max_capacity_fade_pct: Some(10.0),
+            ..criteria()
+        };
+        let (verdict, results) = evaluate_battery(&strict, 2, &logs, &phases);
+        assert_eq!(verdict, Verdict::Fail);
+        let fade = results
+            .iter()
+            .find(|result| result.criterion == "CapacityFade")
+            .unwrap();
+        assert_eq!(fade.outcome, "Fail");
+        assert_eq!(fade.evidence, "cycle 1: 1000 mAh, cycle 2: 500 mAh");
+    }
+
+    #[test]
+    fn test_inconclusive() {
+        let logs: Vec<BatteryLog> = two_cycles()
+            .into_iter()
+            .take_while(|log| log.state == "Charge")
+            .collect();
+        let phases = battery_phases(1, 2, &logs, &[]);
+
+        let (verdict, results) = evaluate_battery(&AcceptanceCriteria::unset(1), 2, &logs, &phases);
+        assert_eq!(verdict, Verdict::Inconclusive);
+        assert!(results.is_empty());
+
+        let (verdict, results) = evaluate_battery(&criteria(), 2, &logs, &phases);
+        assert_eq!(verdict, Verdict::Inconclusive);
+        let capacity = &results[0];
+        assert_eq!(capacity.outcome, "NoData");
+        assert_eq!(capacity.evidence, "no discharge phase");
+    }
+
+    #[test]
+    fn test_evaluate_test() {
+        let mut conn = establish_connection(":memory:").unwrap();
+        conn.run_pending_migrations(MIGRATIONS).unwrap();
+        diesel::insert_into(crate::database::schema::tests::table)
+            .values(&Test {
+                test_id: Some(1),
+                test_name: "Test 1".to_string(),
+                start_date: START.to_string(),
+                profile_id: None,
+            })
+            .execute(&mut conn)
+            .unwrap();
+        diesel::insert_into(crate::database::schema::battery_logs::table)
+            .values(&two_cycles())
+            .execute(&mut conn)
+            .unwrap();
+
+        // nothing to check until the criteria are set
+        let verdicts = evaluate_test(&mut conn, 1).unwrap();
+        assert_eq!(verdicts.len(), 1);
+        assert_eq!(verdicts[0].verdict, Verdict::Inconclusive);
+
+        diesel::replace_into(crate::database::schema::acceptance_criteria::table)
+            .values(&criteria())
+            .execute(&mut conn)
+            .unwrap();
+        evaluate_test(&mut conn, 1).unwrap();
+        let stored = load_verdicts(&mut conn, 1).unwrap();
+        assert_eq!(stored.len(), 1);
+        assert_eq!(stored[0].verdict, Verdict::Pass);
+        assert_eq!(stored[0].criteria.len(), 6);
+
+        let rows = verdict_rows(&mut conn, Some(1)).unwrap();
+        assert_eq!(rows.len(), 6);
+        assert!(rows.iter().all(|row| row.verdict == "Pass"));
+    }
+}
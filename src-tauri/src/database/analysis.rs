@@ -0,0 +1,448 @@
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+use diesel::prelude::*;
+use diesel::SqliteConnection;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tauri::State;
+
+use crate::database::models::{BatteryLog, CompletionEvent, PhaseResult};
+use crate::database::sqlite::connection;
+use crate::serial::pilot::{get_current_time, BatteryState};
+use crate::state::AppState;
+
+/// Efficiency of one charge followed by a discharge.
+#[derive(Debug, Clone, PartialEq, Type, Serialize, Deserialize)]
+pub struct CycleResult {
+    pub battery_id: i32,
+    pub cycle: i32,
+    pub charge_mah: f64,
+    pub discharge_mah: f64,
+    pub charge_wh: f64,
+    pub discharge_wh: f64,
+    /// Discharged over charged mAh, once the cycle has both phases.
+    pub coulombic_efficiency: Option<f64>,
+    /// Discharged over charged Wh, once the cycle has both phases.
+    pub energy_efficiency: Option<f64>,
+}
+
+#[derive(Debug, Clone, Type, Serialize, Deserialize)]
+pub struct TestAnalysis {
+    pub test_id: i32,
+    pub phases: Vec<PhaseResult>,
+    pub cycles: Vec<CycleResult>,
+}
+
+/// The phase a sample was logged in. Sequences log the [`BatteryState`]
+/// name, the development data the simulator's wording.
+fn phase_state(state: &str) -> Option<BatteryState> {
+    match state {
+        "Charge" | "charging" => Some(BatteryState::Charge),
+        "Discharge" | "discharging" => Some(BatteryState::Discharge),
+        _ => None,
+    }
+}
+
+/// Splits the samples of one battery, in time order, into phases: runs of
+/// charge or discharge samples logged under the same step label.
+fn segment(logs: &[BatteryLog]) -> Vec<&[BatteryLog]> {
+    let mut phases = Vec::new();
+    let mut start = 0;
+
+    for end in 1..=logs.len() {
+        let boundary = end == logs.len()
+            || logs[end].state != logs[start].state
+            || logs[end].status != logs[start].status;
+        if boundary {
+            if phase_state(&logs[start].state).is_some() {
+                phases.push(&logs[start..end]);
+            }
+            start = end;
+        }
+    }
+
+    phases
+}
+
+/// Charge in mAh and energy in Wh moved during `logs`, integrating the
+/// magnitude of current and power over elapsed time with the trapezoid rule.
+fn integrate(logs: &[BatteryLog]) -> (f64, f64) {
+    let mut charge_mah = 0.0;
+    let mut energy_wh = 0.0;
+
+    for pair in logs.windows(2) {
+        let hours = (pair[1].elapsed_s - pair[0].elapsed_s).max(0.0) / 3600.0;
+        let current = |log: &BatteryLog| (log.current as f64).abs();
+        // mV × mA = µW
+        let power = |log: &BatteryLog| current(log) * log.voltage as f64 / 1e6;
+
+        charge_mah += (current(&pair[0]) + current(&pair[1])) / 2.0 * hours;
+        energy_wh += (power(&pair[0]) + power(&pair[1])) / 2.0 * hours;
+    }
+
+    (charge_mah, energy_wh)
+}
+
+/// The outcome of the first completion event of the same kind the bench sent
+/// for the battery after the phase started and before the next one did.
+fn completion_outcome(
+    events: &[CompletionEvent],
+    state: BatteryState,
+    started_at: &str,
+    next_started_at: Option<&str>,
+) -> Option<String> {
+    events
+        .iter()
+        .filter(|event| event.phase == format!("{:?}", state))
+        .find(|event| {
+            event.received_at.as_str() >= started_at
+                && next_started_at.is_none_or(|next| event.received_at.as_str() < next)
+        })
+        .map(|event| event.outcome.clone())
+}
+
+/// Phases of the samples of one battery. A charge phase starts a new cycle.
+pub fn battery_phases(
+    test_id: i32,
+    battery_id: i32,
+    logs: &[BatteryLog],
+    events: &[CompletionEvent],
+) -> Vec<PhaseResult> {
+    let phases = segment(logs);
+    let computed_at = get_current_time();
+    let mut cycle = 0;
+
+    phases
+        .iter()
+        .enumerate()
+        .map(|(index, logs)| {
+            let (first, last) = (&logs[0], &logs[logs.len() - 1]);
+            let state = phase_state(&first.state).unwrap_or(BatteryState::Standby);
+            if state == BatteryState::Charge || cycle == 0 {
+                cycle += 1;
+            }
+            let next_started_at = phases
+                .get(index + 1)
+                .map(|next| next[0].recorded_at.as_str());
+            let (charge_mah, energy_wh) = integrate(logs);
+
+            PhaseResult {
+                test_id,
+                battery_id,
+                phase_index: index as i32,
+                cycle,
+                state: format!("{:?}", state),
+                label: first.status.clone(),
+                outcome: completion_outcome(events, state, &first.recorded_at, next_started_at),
+                started_at: first.recorded_at.clone(),
+                ended_at: last.recorded_at.clone(),
+                duration_s: last.elapsed_s - first.elapsed_s,
+                samples: logs.len() as i32,
+                charge_mah,
+                energy_wh,
+                computed_at: computed_at.clone(),
+            }
+        })
+        .collect()
+}
+
+/// Sums the phases of each cycle of each battery.
+pub fn cycle_results(phases: &[PhaseResult]) -> Vec<CycleResult> {
+    let mut cycles: BTreeMap<(i32, i32), CycleResult> = BTreeMap::new();
+
+    for phase in phases {
+        let cycle = cycles
+            .entry((phase.battery_id, phase.cycle))
+            .or_insert_with(|| CycleResult {
+                battery_id: phase.battery_id,
+                cycle: phase.cycle,
+                charge_mah: 0.0,
+                discharge_mah: 0.0,
+                charge_wh: 0.0,
+                discharge_wh: 0.0,
+                coulombic_efficiency: None,
+                energy_efficiency: None,
+            });
+
+        if phase.state == format!("{:?}", BatteryState::Charge) {
+            cycle.charge_mah += phase.charge_mah;
+            cycle.charge_wh += phase.energy_wh;
+        } else {
+            cycle.discharge_mah += phase.charge_mah;
+            cycle.discharge_wh += phase.energy_wh;
+        }
+    }
+
+    cycles
+        .into_values()
+        .map(|cycle| {
+            let ratio = |out: f64, into: f64| (out > 0.0 && into > 0.0).then(|| out / into);
+            CycleResult {
+                coulombic_efficiency: ratio(cycle.discharge_mah, cycle.charge_mah),
+                energy_efficiency: ratio(cycle.discharge_wh, cycle.charge_wh),
+                ..cycle
+            }
+        })
+        .collect()
+}
+
+/// The samples of a test, grouped by battery and in time order.
+pub fn test_logs(conn: &mut SqliteConnection, target_test_id: i32) -> QueryResult<Vec<BatteryLog>> {
+    use crate::database::schema::battery_logs::dsl;
+
+    dsl::battery_logs
+        .filter(dsl::test_id.eq(target_test_id))
+        .order((dsl::id, dsl::elapsed_s, dsl::record_id))
+        .load::<BatteryLog>(conn)
+}
+
+/// Recomputes the phases of `target_test_id` from its samples and completion
+/// events, replacing the stored ones.
+pub fn analyze_test(conn: &mut SqliteConnection, target_test_id: i32) -> QueryResult<TestAnalysis> {
+    use crate::database::schema::{completion_events, phase_results};
+
+    let logs = test_logs(conn, target_test_id)?;
+    let events = completion_events::table
+        .filter(completion_events::test_id.eq(target_test_id))
+        .order(completion_events::received_at)
+        .load::<CompletionEvent>(conn)?;
+
+    let mut phases = Vec::new();
+    for battery in logs.chunk_by(|a, b| a.id == b.id) {
+        let battery_id = battery[0].id;
+        let battery_events: Vec<CompletionEvent> = events
+            .iter()
+            .filter(|event| event.battery_id == battery_id)
+            .cloned()
+            .collect();
+        phases.extend(battery_phases(
+            target_test_id,
+            battery_id,
+            battery,
+            &battery_events,
+        ));
+    }
+
+    conn.transaction(|conn| {
+        diesel::delete(phase_results::table.filter(phase_results::test_id.eq(target_test_id)))
+            .execute(conn)?;
+        diesel::insert_into(phase_results::table)
+            .values(&phases)
+            .execute(conn)
+    })?;
+
+    Ok(TestAnalysis {
+        test_id: target_test_id,
+        cycles: cycle_results(&phases),
+        phases,
+    })
+}
+
+/// The phases stored for `target_test_id` by its last analysis.
+pub fn load_analysis(
+    conn: &mut SqliteConnection,
+    target_test_id: i32,
+) -> QueryResult<TestAnalysis> {
+    use crate::database::schema::phase_results::dsl;
+
+    let phases = dsl::phase_results
+        .filter(dsl::test_id.eq(target_test_id))
+        .order((dsl::battery_id, dsl::phase_index))
+        .load::<PhaseResult>(conn)?;
+
+    Ok(TestAnalysis {
+        test_id: target_test_id,
+        cycles: cycle_results(&phases),
+        phases,
+    })
+}
+
+/// Segments the test into phases and computes their capacity and energy.
+#[tauri::command]
+#[specta::specta]
+pub fn analyze_phases(
+    state: State<'_, Mutex<AppState>>,
+    target_test_id: i32,
+) -> Result<TestAnalysis, String> {
+    let mut state = state.lock().map_err(|e| e.to_string())?;
+    let conn = connection(&mut state).map_err(|e| e.to_string())?;
+
+    analyze_test(conn, target_test_id)
+        .map_err(|e| format!("Failed to analyze test {}: {}", target_test_id, e))
+}
+
+#[tauri::command]
+#[specta::specta]
+pub fn get_phase_results(
+    state: State<'_, Mutex<AppState>>,
+    target_test_id: i32,
+) -> Result<TestAnalysis, String> {
+    let mut state = state.lock().map_err(|e| e.to_string())?;
+    let conn = connection(&mut state).map_err(|e| e.to_string())?;
+
+    load_analysis(conn, target_test_id)
+        .map_err(|e| format!("Failed to load results of test {}: {}", target_test_id, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use diesel_migrations::MigrationHarness;
+
+    use super::*;
+    use crate::database::models::Test;
+    use crate::database::sqlite::{establish_connection, MIGRATIONS};
+
+    const START: &str = "2025-07-29T08:00:00+00:00";
+
+    fn at(elapsed_s: f64) -> String {
+        let start = chrono::DateTime::parse_from_rfc3339(START).unwrap();
+        (start + chrono::Duration::seconds(elapsed_s as i64)).to_rfc3339()
+    }
+
+    fn sample(elapsed_s: f64, state: &str, status: &str, voltage: f32, current: f32) -> BatteryLog {
+        BatteryLog {
+            record_id: None,
+            id: 4,
+            port: "COM3".to_string(),
+            battery_temperature: 25.0,
+            bench_temperature_mosfet: 30.0,
+            bench_temperature_resistor: 30.0,
+            load: 0.0,
+            voltage,
+            current,
+            state: state.to_string(),
+            status: status.to_string(),
+            start_date: None,
+            end_date: None,
+            test_id: 1,
+            recorded_at: at(elapsed_s),
+            elapsed_s,
+        }
+    }
+
+    /// An hour at 1000 mA and 4000 mV, then an hour at -900 mA and 3600 mV,
+    /// then a top-up charge of half an hour at 200 mA.
+    fn one_cycle() -> Vec<BatteryLog> {
+        let mut logs = Vec::new();
+        for minute in 0..=60 {
+            logs.push(sample(
+                minute as f64 * 60.0,
+                "Charge",
+                "Cycle 1",
+                4000.0,
+                1000.0,
+            ));
+        }
+        for minute in 61..=121 {
+            logs.push(sample(
+                minute as f64 * 60.0,
+                "Discharge",
+                "Cycle 1",
+                3600.0,
+                -900.0,
+            ));
+        }
+        logs.push(sample(7300.0, "Standby", "Cycle 1", 3700.0, 0.0));
+        for minute in 122..=152 {
+            logs.push(sample(
+                minute as f64 * 60.0,
+                "Charge",
+                "Top-up",
+                4000.0,
+                200.0,
+            ));
+        }
+        logs
+    }
+
+    fn completion(received_at: String, phase: &str, outcome: &str) -> CompletionEvent {
+        CompletionEvent {
+            record_id: None,
+            battery_id: 4,
+            port: "COM3".to_string(),
+            phase: phase.to_string(),
+            outcome: outcome.to_string(),
+            flags: 0,
+            received_at,
+            test_id: Some(1),
+        }
+    }
+
+    fn close(value: f64, expected: f64) -> bool {
+        (value - expected).abs() < 1e-6
+    }
+
+    #[test]
+    fn test_battery_phases() {
+        let events = vec![
+            completion(at(3605.0), "Charge", "Success"),
+            completion(at(7265.0), "Discharge", "Failed"),
+        ];
+        let phases = battery_phases(1, 4, &one_cycle(), &events);
+
+        assert_eq!(phases.len(), 3);
+        let (charge, discharge, top_up) = (&phases[0], &phases[1], &phases[2]);
+
+        assert_eq!(charge.cycle, 1);
+        assert_eq!(charge.samples, 61);
+        assert_eq!(charge.duration_s, 3600.0);
+        assert!(close(charge.charge_mah, 1000.0));
+        assert!(close(charge.energy_wh, 4.0));
+        assert_eq!(charge.outcome.as_deref(), Some("Success"));
+
+        assert_eq!(discharge.cycle, 1);
+        assert_eq!(discharge.state, "Discharge");
+        assert!(close(discharge.charge_mah, 900.0));
+        assert!(close(discharge.energy_wh, 3.24));
+        assert_eq!(discharge.outcome.as_deref(), Some("Failed"));
+
+        assert_eq!(top_up.cycle, 2);
+        assert_eq!(top_up.label, "Top-up");
+        assert!(close(top_up.charge_mah, 100.0));
+        assert_eq!(top_up.outcome, None);
+
+        let cycles = cycle_results(&phases);
+        assert_eq!(cycles.len(), 2);
+        assert!(close(cycles[0].coulombic_efficiency.unwrap(), 0.9));
+        assert!(close(cycles[0].energy_efficiency.unwrap(), 0.81));
+        assert_eq!(cycles[1].coulombic_efficiency, None);
+    }
+
+    #[test]
+    fn test_analyze_test() {
+        let mut conn = establish_connection(":memory:").unwrap();
+        conn.run_pending_migrations(MIGRATIONS).unwrap();
+        diesel::insert_into(crate::database::schema::tests::table)
+            .values(&Test {
+                test_id: Some(1),
+                test_name: "Test 1".to_string(),
+                start_date: START.to_string(),
+                profile_id: None,
+            })
+            .execute(&mut conn)
+            .unwrap();
+
+        let mut logs = one_cycle();
+        // a second battery that only charged
+        logs.extend((0..=30).map(|minute| BatteryLog {
+            id: 5,
+            ..sample(minute as f64 * 60.0, "charging", "ok", 4000.0, 500.0)
+        }));
+        diesel::insert_into(crate::database::schema::battery_logs::table)
+            .values(&logs)
+            .execute(&mut conn)
+            .unwrap();
+
+        let analysis = analyze_test(&mut conn, 1).unwrap();
+        assert_eq!(analysis.phases.len(), 4);
+        assert_eq!(analysis.cycles.len(), 3);
+
+        // analyzing again replaces the stored phases
+        analyze_test(&mut conn, 1).unwrap();
+        let stored = load_analysis(&mut conn, 1).unwrap();
+        assert_eq!(stored.phases.len(), 4);
+        assert_eq!(stored.cycles, analysis.cycles);
+        assert!(close(stored.phases[3].charge_mah, 250.0));
+    }
+}
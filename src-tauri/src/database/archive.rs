@@ -0,0 +1,405 @@
+use std::fs::File;
+use std::io::{Read, Seek, Write};
+use std::sync::Mutex;
+
+use diesel::SqliteConnection;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use specta::Type;
+use tauri::State;
+use thiserror::Error;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::database::import::{
+    import_bundle, test_bundle, ImportedTest, TestBundle, BUNDLE_VERSION,
+};
+use crate::database::models::{
+    AcceptanceCriteria, AlarmRecord, BatteryLog, CompletionEvent, ProfileRecord, SafetyLimits, Test,
+};
+use crate::database::sqlite::own_connection;
+use crate::serial::pilot::get_current_time;
+use crate::state::AppState;
+
+/// Tells our archives apart from any other zip file.
+pub const ARCHIVE_FORMAT: &str = "battery-test-archive";
+
+const MANIFEST: &str = "manifest.json";
+const TEST: &str = "test.json";
+const BATTERY_LOGS: &str = "battery_logs.json";
+const COMPLETION_EVENTS: &str = "completion_events.json";
+const ALARMS: &str = "alarms.json";
+
+#[derive(Error, Debug)]
+pub enum ArchiveError {
+    #[error("Failed to access archive: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Invalid zip file: {0}")]
+    Zip(#[from] zip::result::ZipError),
+    #[error("Invalid archive content: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Database operation error: {0}")]
+    Database(#[from] diesel::result::Error),
+    #[error("Not a test archive")]
+    NotAnArchive,
+    #[error("Archive version {0} is newer than this application supports")]
+    UnsupportedVersion(u32),
+    #[error("Archive has no {0}")]
+    MissingEntry(String),
+    #[error("Checksum of {0} doesn't match, the archive is damaged")]
+    Checksum(String),
+    #[error("{0} is too large for an archive")]
+    TooLarge(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Type, Serialize, Deserialize)]
+pub struct ArchiveEntry {
+    pub name: String,
+    pub bytes: u32,
+    /// SHA-256 of the entry, hex encoded.
+    pub sha256: String,
+}
+
+/// Describes the archive, so it can be checked and understood without the
+/// application.
+#[derive(Debug, Clone, PartialEq, Type, Serialize, Deserialize)]
+pub struct Manifest {
+    pub format: String,
+    pub format_version: u32,
+    pub created_at: String,
+    pub app_version: String,
+    pub test_id: i32,
+    pub test_name: String,
+    pub battery_ids: Vec<i32>,
+    pub samples: u32,
+    /// Written by the operator when exporting.
+    pub notes: Option<String>,
+    pub entries: Vec<ArchiveEntry>,
+}
+
+/// The `tests` row and the settings that go with it.
+#[derive(Debug, Serialize, Deserialize)]
+struct TestEntry {
+    test: Test,
+    profile: Option<ProfileRecord>,
+    safety_limits: Option<SafetyLimits>,
+    acceptance_criteria: Option<AcceptanceCriteria>,
+}
+
+#[derive(Debug, Clone, PartialEq, Type, Serialize, Deserialize)]
+pub struct ArchiveImport {
+    pub test: ImportedTest,
+    pub notes: Option<String>,
+}
+
+/// `len` as the manifest's `u32`, or an error naming `what` when it doesn't fit.
+fn manifest_size(len: usize, what: &str) -> Result<u32, ArchiveError> {
+    u32::try_from(len).map_err(|_| ArchiveError::TooLarge(what.to_string()))
+}
+
+fn sha256(bytes: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(bytes))
+}
+
+/// Writes `bundle` as a zip of JSON files and a manifest with their
+/// checksums.
+pub fn write_archive<W: Write + Seek>(
+    writer: W,
+    bundle: TestBundle,
+    notes: Option<String>,
+    created_at: &str,
+) -> Result<Manifest, ArchiveError> {
+    let mut battery_ids: Vec<i32> = bundle.logs.iter().map(|log| log.id).collect();
+    battery_ids.sort_unstable();
+    battery_ids.dedup();
+
+    let entries: Vec<(&str, Vec<u8>)> = vec![
+        (
+            TEST,
+            serde_json::to_vec_pretty(&TestEntry {
+                test: bundle.test.clone(),
+                profile: bundle.profile,
+                safety_limits: bundle.safety_limits,
+                acceptance_criteria: bundle.acceptance_criteria,
+            })?,
+        ),
+        (BATTERY_LOGS, serde_json::to_vec(&bundle.logs)?),
+        (
+            COMPLETION_EVENTS,
+            serde_json::to_vec_pretty(&bundle.completion_events)?,
+        ),
+        (ALARMS, serde_json::to_vec_pretty(&bundle.alarms)?),
+    ];
+    let manifest = Manifest {
+        format: ARCHIVE_FORMAT.to_string(),
+        format_version: BUNDLE_VERSION,
+        created_at: created_at.to_string(),
+        app_version: env!("CARGO_PKG_VERSION").to_string(),
+        test_id: bundle.test.test_id.unwrap_or_default(),
+        test_name: bundle.test.test_name,
+        battery_ids,
+        samples: manifest_size(bundle.logs.len(), "sample count")?,
+        notes,
+        entries: entries
+            .iter()
+            .map(|(name, bytes)| {
+                Ok(ArchiveEntry {
+                    name: name.to_string(),
+                    bytes: manifest_size(bytes.len(), name)?,
+                    sha256: sha256(bytes),
+                })
+            })
+            .collect::<Result<_, ArchiveError>>()?,
+    };
+
+    let mut zip = ZipWriter::new(writer);
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    zip.start_file(MANIFEST, options)?;
+    zip.write_all(&serde_json::to_vec_pretty(&manifest)?)?;
+    for (name, bytes) in entries {
+        zip.start_file(name, options)?;
+        zip.write_all(&bytes)?;
+    }
+    zip.finish()?;
+
+    Ok(manifest)
+}
+
+fn read_entry<R: Read + Seek>(
+    zip: &mut ZipArchive<R>,
+    name: &str,
+) -> Result<Vec<u8>, ArchiveError> {
+    let mut file = match zip.by_name(name) {
+        Ok(file) => file,
+        Err(zip::result::ZipError::FileNotFound) => {
+            return Err(ArchiveError::MissingEntry(name.to_string()))
+        }
+        Err(error) => return Err(error.into()),
+    };
+    let mut bytes = Vec::new();
+    file.read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+fn read_checked<R: Read + Seek, T: DeserializeOwned>(
+    zip: &mut ZipArchive<R>,
+    manifest: &Manifest,
+    name: &str,
+) -> Result<T, ArchiveError> {
+    let entry = manifest
+        .entries
+        .iter()
+        .find(|entry| entry.name == name)
+        .ok_or_else(|| ArchiveError::MissingEntry(name.to_string()))?;
+    let bytes = read_entry(zip, name)?;
+    if sha256(&bytes) != entry.sha256 {
+        return Err(ArchiveError::Checksum(name.to_string()));
+    }
+    Ok(serde_json::from_slice(&bytes)?)
+}
+
+/// Reads an archive back, checking every entry against the manifest.
+pub fn read_archive<R: Read + Seek>(reader: R) -> Result<(Manifest, TestBundle), ArchiveError> {
+    let mut zip = ZipArchive::new(reader)?;
+    let manifest: Manifest = match read_entry(&mut zip, MANIFEST) {
+        Ok(bytes) => serde_json::from_slice(&bytes).map_err(|_| ArchiveError::NotAnArchive)?,
+        Err(ArchiveError::MissingEntry(_)) => return Err(ArchiveError::NotAnArchive),
+        Err(error) => return Err(error),
+    };
+    if manifest.format != ARCHIVE_FORMAT {
+        return Err(ArchiveError::NotAnArchive);
+    }
+    if manifest.format_version > BUNDLE_VERSION {
+        return Err(ArchiveError::UnsupportedVersion(manifest.format_version));
+    }
+
+    let test: TestEntry = read_checked(&mut zip, &manifest, TEST)?;
+    let logs: Vec<BatteryLog> = read_checked(&mut zip, &manifest, BATTERY_LOGS)?;
+    let completion_events: Vec<CompletionEvent> =
+        read_checked(&mut zip, &manifest, COMPLETION_EVENTS)?;
+    let alarms: Vec<AlarmRecord> = read_checked(&mut zip, &manifest, ALARMS)?;
+
+    let bundle = TestBundle {
+        format_version: manifest.format_version,
+        test: test.test,
+        profile: test.profile,
+        safety_limits: test.safety_limits,
+        acceptance_criteria: test.acceptance_criteria,
+        logs,
+        completion_events,
+        alarms,
+    };
+    Ok((manifest, bundle))
+}
+
+/// Merges an archive into the database as a new test.
+pub fn import_archive<R: Read + Seek>(
+    conn: &mut SqliteConnection,
+    reader: R,
+) -> Result<ArchiveImport, ArchiveError> {
+    let (manifest, bundle) = read_archive(reader)?;
+    Ok(ArchiveImport {
+        test: import_bundle(conn, bundle)?,
+        notes: manifest.notes,
+    })
+}
+
+/// Writes a test and everything recorded with it to a single archive.
+#[tauri::command]
+#[specta::specta]
+pub fn export_test_archive(
+    state: State<'_, Mutex<AppState>>,
+    file_path: String,
+    target_test_id: i32,
+    notes: Option<String>,
+) -> Result<Manifest, String> {
+    let conn = &mut own_connection(&state)?;
+
+    let bundle = test_bundle(conn, target_test_id)
+        .map_err(|e| format!("Failed to load test {}: {}", target_test_id, e))?;
+    let file =
+        File::create(&file_path).map_err(|e| format!("Failed to create {}: {}", file_path, e))?;
+    write_archive(file, bundle, notes, &get_current_time()).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+#[specta::specta]
+pub fn import_test_archive(
+    state: State<'_, Mutex<AppState>>,
+    file_path: String,
+) -> Result<ArchiveImport, String> {
+    let conn = &mut own_connection(&state)?;
+
+    let file =
+        File::open(&file_path).map_err(|e| format!("Failed to open {}: {}", file_path, e))?;
+    import_archive(conn, file).map_err(|e| format!("Failed to import {}: {}", file_path, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::analysis::test_logs;
+    use crate::database::sqlite::{establish_connection, MIGRATIONS};
+    use diesel::prelude::*;
+    use diesel_migrations::MigrationHarness;
+    use std::io::Cursor;
+
+    fn database() -> SqliteConnection {
+        let mut conn = establish_connection(":memory:").unwrap();
+        conn.run_pending_migrations(MIGRATIONS).unwrap();
+        diesel::insert_into(crate::database::schema::tests::table)
+            .values(&Test {
+                test_id: Some(1),
+                test_name: "Test 1".to_string(),
+                start_date: "2025-08-04T08:00:00+00:00".to_string(),
+                profile_id: None,
+            })
+            .execute(&mut conn)
+            .unwrap();
+        let logs: Vec<BatteryLog> = (0..10)
+            .map(|second| BatteryLog {
+                record_id: None,
+                id: 2 + second % 2,
+                port: "COM3".to_string(),
+                battery_temperature: 25.0,
+                bench_temperature_mosfet: 30.0,
+                bench_temperature_resistor: 30.0,
+                load: 3.0,
+                voltage: 3700.0,
+                current: -1250.0,
+                state: "Discharge".to_string(),
+                status: "Cycle 1".to_string(),
+                start_date: None,
+                end_date: None,
+                test_id: 1,
+                recorded_at: "2025-08-04T08:00:00+00:00".to_string(),
+                elapsed_s: second as f64,
+            })
+            .collect();
+        diesel::insert_into(crate::database::schema::battery_logs::table)
+            .values(&logs)
+            .execute(&mut conn)
+            .unwrap();
+        conn
+    }
+
+    fn archive(conn: &mut SqliteConnection) -> Vec<u8> {
+        let mut bytes = Cursor::new(Vec::new());
+        let bundle = test_bundle(conn, 1).unwrap();
+        let manifest =
+            write_archive(&mut bytes, bundle, Some("cell 3 dented".to_string()), "now").unwrap();
+        assert_eq!(manifest.battery_ids, [2, 3]);
+        assert_eq!(manifest.samples, 10);
+        bytes.into_inner()
+    }
+
+    #[test]
+    fn test_archive_round_trip() {
+        let mut conn = database();
+        let bytes = archive(&mut conn);
+
+        // into the same database, where test 1 is taken
+        let imported = import_archive(&mut conn, Cursor::new(&bytes)).unwrap();
+        assert_ne!(imported.test.test_id, 1);
+        assert_eq!(imported.test.source_test_id, Some(1));
+        assert_eq!(imported.notes.as_deref(), Some("cell 3 dented"));
+        assert_eq!(
+            test_logs(&mut conn, imported.test.test_id).unwrap().len(),
+            10
+        );
+
+        // into another instance, where it keeps its id
+        let mut other = establish_connection(":memory:").unwrap();
+        other.run_pending_migrations(MIGRATIONS).unwrap();
+        let imported = import_archive(&mut other, Cursor::new(&bytes)).unwrap();
+        assert_eq!(imported.test.test_id, 1);
+    }
+
+    #[test]
+    fn test_damaged_archive() {
+        let mut conn = database();
+        let bytes = archive(&mut conn);
+
+        // rewrite the samples without updating the manifest
+        let mut zip = ZipArchive::new(Cursor::new(&bytes)).unwrap();
+        let mut damaged = ZipWriter::new(Cursor::new(Vec::new()));
+        for index in 0..zip.len() {
+            let mut file = zip.by_index(index).unwrap();
+            let mut content = Vec::new();
+            file.read_to_end(&mut content).unwrap();
+            if file.name() == BATTERY_LOGS {
+                content = b"[]".to_vec();
+            }
+            damaged
+                .start_file(file.name(), SimpleFileOptions::default())
+                .unwrap();
+            damaged.write_all(&content).unwrap();
+        }
+        let damaged = damaged.finish().unwrap().into_inner();
+
+        assert!(matches!(
+            read_archive(Cursor::new(damaged)),
+            Err(ArchiveError::Checksum(name)) if name == BATTERY_LOGS
+        ));
+
+        let mut other = ZipWriter::new(Cursor::new(Vec::new()));
+        other
+            .start_file("readme.txt", SimpleFileOptions::default())
+            .unwrap();
+        let other = other.finish().unwrap().into_inner();
+        assert!(matches!(
+            read_archive(Cursor::new(other)),
+            Err(ArchiveError::NotAnArchive)
+        ));
+    }
+
+    #[test]
+    fn test_manifest_size() {
+        assert_eq!(manifest_size(4096, TEST).unwrap(), 4096);
+        assert!(matches!(
+            manifest_size(u32::MAX as usize + 1, BATTERY_LOGS),
+            Err(ArchiveError::TooLarge(name)) if name == BATTERY_LOGS
+        ));
+    }
+}
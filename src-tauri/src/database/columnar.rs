@@ -0,0 +1,491 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use arrow_array::{
+    ArrayRef, Float32Array, Float64Array, Int32Array, RecordBatch, StringArray,
+    TimestampMicrosecondArray,
+};
+use arrow_ipc::writer::FileWriter;
+use arrow_schema::{ArrowError, DataType, Field, Schema, SchemaRef, TimeUnit};
+use diesel::prelude::*;
+use diesel::SqliteConnection;
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::errors::ParquetError;
+use parquet::file::metadata::KeyValue;
+use parquet::file::properties::WriterProperties;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tauri::State;
+use thiserror::Error;
+
+use crate::database::export::file_stem;
+use crate::database::models::{AlarmRecord, BatteryLog, PhaseResult, ProfileRecord, Test};
+use crate::database::sqlite::own_connection;
+use crate::serial::pilot::get_current_time;
+use crate::state::AppState;
+
+/// Samples read from the database and written per record batch, so a
+/// multi-day test never has to fit in memory at once.
+const BATCH_ROWS: i64 = 65_536;
+
+#[derive(Error, Debug)]
+pub enum ColumnarError {
+    #[error("Failed to read test: {0}")]
+    Database(#[from] diesel::result::Error),
+    #[error("Failed to build columns: {0}")]
+    Arrow(#[from] ArrowError),
+    #[error("Failed to write Parquet: {0}")]
+    Parquet(#[from] ParquetError),
+    #[error("Failed to create file: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Type, Serialize, Deserialize)]
+pub enum ColumnarFormat {
+    Parquet,
+    /// Arrow IPC file format, also known as Feather v2.
+    ArrowIpc,
+}
+
+impl ColumnarFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            ColumnarFormat::Parquet => "parquet",
+            ColumnarFormat::ArrowIpc => "arrow",
+        }
+    }
+}
+
+/// Writes record batches to either format.
+enum BatchWriter {
+    Parquet(ArrowWriter<File>),
+    ArrowIpc(FileWriter<File>),
+}
+
+impl BatchWriter {
+    fn create(
+        path: &Path,
+        format: ColumnarFormat,
+        schema: SchemaRef,
+    ) -> Result<Self, ColumnarError> {
+        let file = File::create(path)?;
+        Ok(match format {
+            ColumnarFormat::Parquet => {
+                // the Arrow schema is embedded too, but not every reader looks
+                // at it, so the metadata is also in the Parquet footer
+                let metadata = schema
+                    .metadata()
+                    .iter()
+                    .map(|(key, value)| KeyValue::new(key.clone(), value.clone()))
+                    .collect();
+                let properties = WriterProperties::builder()
+                    .set_compression(Compression::SNAPPY)
+                    .set_key_value_metadata(Some(metadata))
+                    .build();
+                BatchWriter::Parquet(ArrowWriter::try_new(file, schema, Some(properties))?)
+            }
+            ColumnarFormat::ArrowIpc => BatchWriter::ArrowIpc(FileWriter::try_new(file, &schema)?),
+        })
+    }
+
+    fn write(&mut self, batch: &RecordBatch) -> Result<(), ColumnarError> {
+        match self {
+            BatchWriter::Parquet(writer) => writer.write(batch)?,
+            BatchWriter::ArrowIpc(writer) => writer.write(batch)?,
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> Result<(), ColumnarError> {
+        match self {
+            BatchWriter::Parquet(writer) => {
+                writer.close()?;
+            }
+            BatchWriter::ArrowIpc(mut writer) => writer.finish()?,
+        }
+        Ok(())
+    }
+}
+
+/// File-level metadata describing the test and the table.
+pub fn test_metadata(
+    test: &Test,
+    profile: Option<&ProfileRecord>,
+    table: &str,
+    exported_at: &str,
+) -> HashMap<String, String> {
+    let mut metadata = HashMap::from([
+        ("table".to_string(), table.to_string()),
+        (
+            "test_id".to_string(),
+            test.test_id.unwrap_or_default().to_string(),
+        ),
+        ("test_name".to_string(), test.test_name.clone()),
+        ("test_started".to_string(), test.start_date.clone()),
+        ("exported_at".to_string(), exported_at.to_string()),
+    ]);
+    if let Some(profile) = profile {
+        metadata.insert("profile_name".to_string(), profile.name.clone());
+        metadata.insert("profile".to_string(), profile.definition.clone());
+    }
+    metadata
+}
+
+fn timestamp_field(name: &str, nullable: bool) -> Field {
+    Field::new(
+        name,
+        DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into())),
+        nullable,
+    )
+}
+
+/// Microseconds since the epoch, `None` for a timestamp that doesn't parse,
+/// such as the empty ones of rows logged before samples were stamped.
+fn micros(timestamp: &str) -> Option<i64> {
+    chrono::DateTime::parse_from_rfc3339(timestamp)
+        .ok()
+        .map(|time| time.timestamp_micros())
+}
+
+fn timestamps<'a>(values: impl Iterator<Item = &'a str>) -> ArrayRef {
+    Arc::new(
+        TimestampMicrosecondArray::from(values.map(micros).collect::<Vec<_>>())
+            .with_timezone("UTC"),
+    )
+}
+
+fn strings<'a>(values: impl Iterator<Item = &'a str>) -> ArrayRef {
+    Arc::new(StringArray::from_iter_values(values))
+}
+
+pub fn battery_log_schema(metadata: HashMap<String, String>) -> SchemaRef {
+    Arc::new(
+        Schema::new(vec![
+            Field::new("test_id", DataType::Int32, false),
+            Field::new("battery_id", DataType::Int32, false),
+            Field::new("port", DataType::Utf8, false),
+            timestamp_field("recorded_at", true),
+            Field::new("elapsed_s", DataType::Float64, false),
+            Field::new("state", DataType::Utf8, false),
+            Field::new("status", DataType::Utf8, false),
+            Field::new("voltage_mv", DataType::Float32, false),
+            Field::new("current_ma", DataType::Float32, false),
+            Field::new("load_ohm", DataType::Float32, false),
+            Field::new("battery_temperature_c", DataType::Float32, false),
+            Field::new("mosfet_temperature_c", DataType::Float32, false),
+            Field::new("resistor_temperature_c", DataType::Float32, false),
+        ])
+        .with_metadata(metadata),
+    )
+}
+
+pub fn battery_log_batch(
+    schema: SchemaRef,
+    logs: &[BatteryLog],
+) -> Result<RecordBatch, ArrowError> {
+    let floats = |value: fn(&BatteryLog) -> f32| -> ArrayRef {
+        Arc::new(Float32Array::from_iter_values(logs.iter().map(value)))
+    };
+
+    RecordBatch::try_new(
+        schema,
+        vec![
+            Arc::new(Int32Array::from_iter_values(
+                logs.iter().map(|log| log.test_id),
+            )),
+            Arc::new(Int32Array::from_iter_values(logs.iter().map(|log| log.id))),
+            strings(logs.iter().map(|log| log.port.as_str())),
+            timestamps(logs.iter().map(|log| log.recorded_at.as_str())),
+            Arc::new(Float64Array::from_iter_values(
+                logs.iter().map(|log| log.elapsed_s),
+            )),
+            strings(logs.iter().map(|log| log.state.as_str())),
+            strings(logs.iter().map(|log| log.status.as_str())),
+            floats(|log| log.voltage),
+            floats(|log| log.current),
+            floats(|log| log.load),
+            floats(|log| log.battery_temperature),
+            floats(|log| log.bench_temperature_mosfet),
+            floats(|log| log.bench_temperature_resistor),
+        ],
+    )
+}
+
+pub fn phase_schema(metadata: HashMap<String, String>) -> SchemaRef {
+    Arc::new(
+        Schema::new(vec![
+            Field::new("test_id", DataType::Int32, false),
+            Field::new("battery_id", DataType::Int32, false),
+            Field::new("phase_index", DataType::Int32, false),
+            Field::new("cycle", DataType::Int32, false),
+            Field::new("state", DataType::Utf8, false),
+            Field::new("label", DataType::Utf8, false),
+            Field::new("outcome", DataType::Utf8, true),
+            timestamp_field("started_at", true),
+            timestamp_field("ended_at", true),
+            Field::new("duration_s", DataType::Float64, false),
+            Field::new("samples", DataType::Int32, false),
+            Field::new("charge_mah", DataType::Float64, false),
+            Field::new("energy_wh", DataType::Float64, false),
+        ])
+        .with_metadata(metadata),
+    )
+}
+
+pub fn phase_batch(schema: SchemaRef, phases: &[PhaseResult]) -> Result<RecordBatch, ArrowError> {
+    let ints = |value: fn(&PhaseResult) -> i32| -> ArrayRef {
+        Arc::new(Int32Array::from_iter_values(phases.iter().map(value)))
+    };
+    let doubles = |value: fn(&PhaseResult) -> f64| -> ArrayRef {
+        Arc::new(Float64Array::from_iter_values(phases.iter().map(value)))
+    };
+
+    RecordBatch::try_new(
+        schema,
+        vec![
+            ints(|phase| phase.test_id),
+            ints(|phase| phase.battery_id),
+            ints(|phase| phase.phase_index),
+            ints(|phase| phase.cycle),
+            strings(phases.iter().map(|phase| phase.state.as_str())),
+            strings(phases.iter().map(|phase| phase.label.as_str())),
+            Arc::new(StringArray::from_iter(
+                phases.iter().map(|phase| phase.outcome.as_deref()),
+            )),
+            timestamps(phases.iter().map(|phase| phase.started_at.as_str())),
+            timestamps(phases.iter().map(|phase| phase.ended_at.as_str())),
+            doubles(|phase| phase.duration_s),
+            ints(|phase| phase.samples),
+            doubles(|phase| phase.charge_mah),
+            doubles(|phase| phase.energy_wh),
+        ],
+    )
+}
+
+pub fn alarm_schema(metadata: HashMap<String, String>) -> SchemaRef {
+    Arc::new(
+        Schema::new(vec![
+            Field::new("battery_id", DataType::Int32, false),
+            Field::new("port", DataType::Utf8, false),
+            Field::new("quantity", DataType::Utf8, false),
+            Field::new("value", DataType::Float32, false),
+            Field::new("threshold", DataType::Float32, false),
+            Field::new("state", DataType::Utf8, false),
+            timestamp_field("raised_at", true),
+        ])
+        .with_metadata(metadata),
+    )
+}
+
+pub fn alarm_batch(schema: SchemaRef, alarms: &[AlarmRecord]) -> Result<RecordBatch, ArrowError> {
+    RecordBatch::try_new(
+        schema,
+        vec![
+            Arc::new(Int32Array::from_iter_values(
+                alarms.iter().map(|alarm| alarm.battery_id),
+            )),
+            strings(alarms.iter().map(|alarm| alarm.port.as_str())),
+            strings(alarms.iter().map(|alarm| alarm.quantity.as_str())),
+            Arc::new(Float32Array::from_iter_values(
+                alarms.iter().map(|alarm| alarm.value),
+            )),
+            Arc::new(Float32Array::from_iter_values(
+                alarms.iter().map(|alarm| alarm.threshold),
+            )),
+            strings(alarms.iter().map(|alarm| alarm.state.as_str())),
+            timestamps(alarms.iter().map(|alarm| alarm.raised_at.as_str())),
+        ],
+    )
+}
+
+/// Writes the samples, phases and alarms of a test to one file each in
+/// `base_path`, returning the files written.
+pub fn export_test(
+    conn: &mut SqliteConnection,
+    target_test_id: i32,
+    base_path: &Path,
+    format: ColumnarFormat,
+) -> Result<Vec<String>, ColumnarError> {
+    use crate::database::schema::{alarms, battery_logs, phase_results, test_profiles, tests};
+
+    let test = tests::table
+        .filter(tests::test_id.eq(target_test_id))
+        .first::<Test>(conn)?;
+    let profile = match test.profile_id {
+        Some(profile_id) => test_profiles::table
+            .filter(test_profiles::profile_id.eq(profile_id))
+            .first::<ProfileRecord>(conn)
+            .optional()?,
+        None => None,
+    };
+    let exported_at = get_current_time();
+    let metadata = |table| test_metadata(&test, profile.as_ref(), table, &exported_at);
+    let path = |table: &str| {
+        base_path.join(format!(
+            "{}_{}_{}.{}",
+            file_stem(&test.test_name),
+            target_test_id,
+            table,
+            format.extension()
+        ))
+    };
+    std::fs::create_dir_all(base_path)?;
+    let mut written = Vec::new();
+
+    let schema = battery_log_schema(metadata("battery_logs"));
+    let logs_path = path("battery_logs");
+    let mut writer = BatchWriter::create(&logs_path, format, schema.clone())?;
+    let mut last_record_id = 0;
+    loop {
+        // keyset pagination, record ids grow with time
+        let logs = battery_logs::table
+            .filter(battery_logs::test_id.eq(target_test_id))
+            .filter(battery_logs::record_id.gt(last_record_id))
+            .order(battery_logs::record_id)
+            .limit(BATCH_ROWS)
+            .load::<BatteryLog>(conn)?;
+        let Some(last) = logs.last() else {
+            break;
+        };
+        last_record_id = last.record_id.unwrap_or_default();
+        writer.write(&battery_log_batch(schema.clone(), &logs)?)?;
+    }
+    writer.finish()?;
+    written.push(logs_path.display().to_string());
+
+    let phases = phase_results::table
+        .filter(phase_results::test_id.eq(target_test_id))
+        .order((phase_results::battery_id, phase_results::phase_index))
+        .load::<PhaseResult>(conn)?;
+    let schema = phase_schema(metadata("phase_results"));
+    let phases_path = path("phases");
+    let mut writer = BatchWriter::create(&phases_path, format, schema.clone())?;
+    writer.write(&phase_batch(schema, &phases)?)?;
+    writer.finish()?;
+    written.push(phases_path.display().to_string());
+
+    let alarms = alarms::table
+        .filter(alarms::test_id.eq(target_test_id))
+        .order(alarms::alarm_id)
+        .load::<AlarmRecord>(conn)?;
+    let schema = alarm_schema(metadata("alarms"));
+    let alarms_path = path("alarms");
+    let mut writer = BatchWriter::create(&alarms_path, format, schema.clone())?;
+    writer.write(&alarm_batch(schema, &alarms)?)?;
+    writer.finish()?;
+    written.push(alarms_path.display().to_string());
+
+    Ok(written)
+}
+
+/// Exports a test as typed columns for pandas, polars and the like.
+#[tauri::command]
+#[specta::specta]
+pub fn export_test_columnar(
+    state: State<'_, Mutex<AppState>>,
+    base_path: String,
+    target_test_id: i32,
+    format: ColumnarFormat,
+) -> Result<Vec<String>, String> {
+    let conn = &mut own_connection(&state)?;
+
+    export_test(conn, target_test_id, Path::new(&base_path), format)
+        .map_err(|e| format!("Failed to export test {}: {}", target_test_id, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::sqlite::{establish_connection, MIGRATIONS};
+    use arrow_array::Array;
+    use diesel_migrations::MigrationHarness;
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+    fn sample(id: i32, elapsed_s: f64) -> BatteryLog {
+        BatteryLog {
+            record_id: None,
+            id,
+            port: "COM3".to_string(),
+            battery_temperature: 25.0,
+            bench_temperature_mosfet: 30.0,
+            bench_temperature_resistor: 30.0,
+            load: 3.0,
+            voltage: 3700.0,
+            current: -1250.0,
+            state: "Discharge".to_string(),
+            status: "Cycle 1".to_string(),
+            start_date: None,
+            end_date: None,
+            test_id: 1,
+            recorded_at: (chrono::DateTime::parse_from_rfc3339("2025-08-02T08:00:00+00:00")
+                .unwrap()
+                + chrono::Duration::seconds(elapsed_s as i64))
+            .to_rfc3339(),
+            elapsed_s,
+        }
+    }
+
+    #[test]
+    fn test_export_test() {
+        let mut conn = establish_connection(":memory:").unwrap();
+        conn.run_pending_migrations(MIGRATIONS).unwrap();
+        diesel::insert_into(crate::database::schema::tests::table)
+            .values(&Test {
+                test_id: Some(1),
+                test_name: "Bench A".to_string(),
+                start_date: "2025-08-02T08:00:00+00:00".to_string(),
+                profile_id: None,
+            })
+            .execute(&mut conn)
+            .unwrap();
+        let mut logs: Vec<BatteryLog> = (0..100).map(|second| sample(2, second as f64)).collect();
+        // a row from before samples were stamped
+        logs.push(BatteryLog {
+            recorded_at: String::new(),
+            ..sample(3, 0.0)
+        });
+        diesel::insert_into(crate::database::schema::battery_logs::table)
+            .values(&logs)
+            .execute(&mut conn)
+            .unwrap();
+
+        let dir = std::env::temp_dir().join(format!("columnar_{}", std::process::id()));
+        let parquet = export_test(&mut conn, 1, &dir, ColumnarFormat::Parquet).unwrap();
+        let ipc = export_test(&mut conn, 1, &dir, ColumnarFormat::ArrowIpc).unwrap();
+        assert_eq!(parquet.len(), 3);
+        assert!(parquet[0].ends_with("Bench_A_1_battery_logs.parquet"));
+
+        let builder =
+            ParquetRecordBatchReaderBuilder::try_new(File::open(&parquet[0]).unwrap()).unwrap();
+        let footer = builder
+            .metadata()
+            .file_metadata()
+            .key_value_metadata()
+            .unwrap()
+            .clone();
+        assert!(footer
+            .iter()
+            .any(|kv| kv.key == "test_name" && kv.value.as_deref() == Some("Bench A")));
+        let schema = builder.schema().clone();
+        assert_eq!(
+            schema.field_with_name("recorded_at").unwrap().data_type(),
+            &DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into()))
+        );
+        let batches: Vec<RecordBatch> = builder.build().unwrap().map(|b| b.unwrap()).collect();
+        let rows: usize = batches.iter().map(|batch| batch.num_rows()).sum();
+        assert_eq!(rows, 101);
+        let recorded_at = batches[0].column_by_name("recorded_at").unwrap();
+        assert_eq!(recorded_at.null_count(), 1);
+
+        let reader =
+            arrow_ipc::reader::FileReader::try_new(File::open(&ipc[0]).unwrap(), None).unwrap();
+        assert_eq!(reader.schema().metadata()["table"], "battery_logs");
+        let rows: usize = reader.map(|batch| batch.unwrap().num_rows()).sum();
+        assert_eq!(rows, 101);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,19 +1,167 @@
-use std::io::Write;
-use std::path::Path;
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
 use std::sync::Mutex;
 
-use crate::database::sqlite::get_all_battery_logs;
-use crate::database::{models::BatteryLog, sqlite::establish_connection};
+use crate::database::acceptance::verdict_rows;
+use crate::database::models::{BatteryLog, Test};
+use crate::database::query::LogFilter;
+use crate::database::sqlite::{connection, get_all_battery_logs, own_connection};
+use crate::serial::pilot::get_current_time;
 use crate::state::AppState;
 
 use csv::Writer;
 use diesel::prelude::*;
+use diesel::SqliteConnection;
+use serde::{Deserialize, Serialize};
+use specta::Type;
 use tauri::State;
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Type, Serialize, Deserialize)]
+pub enum ExportColumn {
+    RecordedAt,
+    Elapsed,
+    BatteryId,
+    Port,
+    State,
+    Status,
+    Voltage,
+    Current,
+    Load,
+    BatteryTemperature,
+    MosfetTemperature,
+    ResistorTemperature,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Type, Serialize, Deserialize)]
+pub enum VoltageUnit {
+    Millivolt,
+    Volt,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Type, Serialize, Deserialize)]
+pub enum CurrentUnit {
+    Milliampere,
+    Ampere,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Type, Serialize, Deserialize)]
+pub enum TimeUnit {
+    Second,
+    Minute,
+    Hour,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Type, Serialize, Deserialize)]
+pub struct ExportUnits {
+    pub voltage: VoltageUnit,
+    pub current: CurrentUnit,
+    /// Unit of the elapsed time column.
+    pub time: TimeUnit,
+}
+
+/// What [`export_test_csv`] writes.
+#[derive(Debug, Clone, Type, Serialize, Deserialize)]
+pub struct ExportOptions {
+    pub test_ids: Vec<i32>,
+    /// Every battery of the tests when `None`.
+    pub battery_ids: Option<Vec<i32>>,
+    /// Seconds since the start of the test, inclusive.
+    pub from_s: Option<f64>,
+    pub to_s: Option<f64>,
+    /// [`DEFAULT_COLUMNS`] when empty.
+    pub columns: Vec<ExportColumn>,
+    pub units: ExportUnits,
+}
+
+pub const DEFAULT_COLUMNS: [ExportColumn; 9] = [
+    ExportColumn::RecordedAt,
+    ExportColumn::Elapsed,
+    ExportColumn::State,
+    ExportColumn::Status,
+    ExportColumn::Voltage,
+    ExportColumn::Current,
+    ExportColumn::Load,
+    ExportColumn::BatteryTemperature,
+    ExportColumn::MosfetTemperature,
+];
+
+impl ExportUnits {
+    /// The units samples are stored in.
+    pub fn stored() -> Self {
+        ExportUnits {
+            voltage: VoltageUnit::Millivolt,
+            current: CurrentUnit::Milliampere,
+            time: TimeUnit::Second,
+        }
+    }
+}
+
+impl ExportColumn {
+    pub fn header(self, units: &ExportUnits) -> String {
+        let voltage = match units.voltage {
+            VoltageUnit::Millivolt => "mV",
+            VoltageUnit::Volt => "V",
+        };
+        let current = match units.current {
+            CurrentUnit::Milliampere => "mA",
+            CurrentUnit::Ampere => "A",
+        };
+        let time = match units.time {
+            TimeUnit::Second => "s",
+            TimeUnit::Minute => "min",
+            TimeUnit::Hour => "h",
+        };
+
+        match self {
+            ExportColumn::RecordedAt => "recorded_at".to_string(),
+            ExportColumn::Elapsed => format!("elapsed_{}", time),
+            ExportColumn::BatteryId => "battery_id".to_string(),
+            ExportColumn::Port => "port".to_string(),
+            ExportColumn::State => "state".to_string(),
+            ExportColumn::Status => "status".to_string(),
+            ExportColumn::Voltage => format!("voltage_{}", voltage),
+            ExportColumn::Current => format!("current_{}", current),
+            ExportColumn::Load => "load_ohm".to_string(),
+            ExportColumn::BatteryTemperature => "battery_temperature_c".to_string(),
+            ExportColumn::MosfetTemperature => "mosfet_temperature_c".to_string(),
+            ExportColumn::ResistorTemperature => "resistor_temperature_c".to_string(),
+        }
+    }
+
+    pub fn value(self, log: &BatteryLog, units: &ExportUnits) -> String {
+        let elapsed = match units.time {
+            TimeUnit::Second => log.elapsed_s,
+            TimeUnit::Minute => log.elapsed_s / 60.0,
+            TimeUnit::Hour => log.elapsed_s / 3600.0,
+        };
+        let milli = |value: f32, scaled: bool| match scaled {
+            true => (value / 1000.0).to_string(),
+            false => value.to_string(),
+        };
+
+        match self {
+            ExportColumn::RecordedAt => log.recorded_at.clone(),
+            ExportColumn::Elapsed => elapsed.to_string(),
+            ExportColumn::BatteryId => log.id.to_string(),
+            ExportColumn::Port => log.port.clone(),
+            ExportColumn::State => log.state.clone(),
+            ExportColumn::Status => log.status.clone(),
+            ExportColumn::Voltage => milli(log.voltage, units.voltage == VoltageUnit::Volt),
+            ExportColumn::Current => milli(log.current, units.current == CurrentUnit::Ampere),
+            ExportColumn::Load => log.load.to_string(),
+            ExportColumn::BatteryTemperature => log.battery_temperature.to_string(),
+            ExportColumn::MosfetTemperature => log.bench_temperature_mosfet.to_string(),
+            ExportColumn::ResistorTemperature => log.bench_temperature_resistor.to_string(),
+        }
+    }
+}
+
 #[tauri::command]
 #[specta::specta]
 pub fn export_csv(statee: State<'_, Mutex<AppState>>, base_path: String) -> Result<(), String> {
-    let all_logs = get_all_battery_logs(statee)?;
+    let all_logs = get_all_battery_logs(statee.clone())?;
 
     let mut grouped_logs: std::collections::HashMap<i32, Vec<BatteryLog>> =
         std::collections::HashMap::new();
@@ -35,5 +183,286 @@ pub fn export_csv(statee: State<'_, Mutex<AppState>>, base_path: String) -> Resu
         wtr.flush().map_err(|e| e.to_string())?;
     }
 
+    let verdicts = {
+        let mut state = statee.lock().map_err(|e| e.to_string())?;
+        let conn = connection(&mut state).map_err(|e| e.to_string())?;
+        verdict_rows(conn, None).map_err(|e| e.to_string())?
+    };
+    if !verdicts.is_empty() {
+        let mut wtr = Writer::from_path(Path::new(&base_path).join("verdicts.csv"))
+            .map_err(|e| e.to_string())?;
+        for verdict in verdicts {
+            wtr.serialize(verdict).map_err(|e| e.to_string())?;
+        }
+        wtr.flush().map_err(|e| e.to_string())?;
+    }
+
     Ok(())
 }
+
+/// The test name with anything that isn't safe in a file name replaced.
+pub(crate) fn file_stem(test_name: &str) -> String {
+    let stem: String = test_name
+        .trim()
+        .chars()
+        .map(|c| match c.is_alphanumeric() || c == '-' || c == '_' {
+            true => c,
+            false => '_',
+        })
+        .collect();
+    match stem.is_empty() {
+        true => "test".to_string(),
+        false => stem,
+    }
+}
+
+/// First line of every exported file, used to tell which test a file in the
+/// export folder belongs to.
+fn header_id_line(test_id: i32) -> String {
+    format!("# test_id: {}", test_id)
+}
+
+/// Whether `path` is free or an earlier export of the same test.
+fn writable_for(path: &Path, test_id: i32) -> bool {
+    let Ok(file) = File::open(path) else {
+        return true;
+    };
+    let mut first = String::new();
+    BufReader::new(file).read_line(&mut first).is_ok()
+        && first.trim_end() == header_id_line(test_id)
+}
+
+/// `<test_name>_battery_<id>.csv` in `base_path`. Test names aren't unique,
+/// so the test id is added when the name is already taken by another test.
+fn export_path(base_path: &Path, test: &Test, test_id: i32, battery_id: i32) -> PathBuf {
+    let stem = file_stem(&test.test_name);
+    let path = base_path.join(format!("{}_battery_{}.csv", stem, battery_id));
+    match writable_for(&path, test_id) {
+        true => path,
+        false => base_path.join(format!("{}_{}_battery_{}.csv", stem, test_id, battery_id)),
+    }
+}
+
+/// Writes the samples of one battery, after a block of `#` comment lines
+/// describing the test.
+pub fn write_battery_csv<W: std::io::Write>(
+    mut writer: W,
+    test: &Test,
+    battery_id: i32,
+    logs: &[BatteryLog],
+    columns: &[ExportColumn],
+    units: &ExportUnits,
+    exported_at: &str,
+) -> Result<(), String> {
+    let test_id = test.test_id.unwrap_or_default();
+    let (first, last) = match (logs.first(), logs.last()) {
+        (Some(first), Some(last)) => (first.recorded_at.as_str(), last.recorded_at.as_str()),
+        _ => ("-", "-"),
+    };
+    let ports: HashSet<&str> = logs.iter().map(|log| log.port.as_str()).collect();
+    let mut ports: Vec<&str> = ports.into_iter().collect();
+    ports.sort_unstable();
+
+    writeln!(
+        writer,
+        "{}\n# test_name: {}\n# test_started: {}\n# battery_id: {}\n# ports: {}\n\
+         # samples: {}\n# first_sample: {}\n# last_sample: {}\n# exported_at: {}",
+        header_id_line(test_id),
+        test.test_name.replace('\n', " "),
+        test.start_date,
+        battery_id,
+        ports.join(" "),
+        logs.len(),
+        first,
+        last,
+        exported_at,
+    )
+    .map_err(|e| e.to_string())?;
+
+    let mut wtr = Writer::from_writer(writer);
+    wtr.write_record(columns.iter().map(|column| column.header(units)))
+        .map_err(|e| e.to_string())?;
+    for log in logs {
+        wtr.write_record(columns.iter().map(|column| column.value(log, units)))
+            .map_err(|e| e.to_string())?;
+    }
+    wtr.flush().map_err(|e| e.to_string())
+}
+
+/// The samples of a test selected by `options`, grouped by battery and in
+/// time order.
+pub fn selected_logs(
+    conn: &mut SqliteConnection,
+    target_test_id: i32,
+    options: &ExportOptions,
+) -> QueryResult<Vec<BatteryLog>> {
+    use crate::database::schema::battery_logs::dsl;
+
+    LogFilter {
+        battery_ids: options.battery_ids.clone(),
+        from_s: options.from_s,
+        to_s: options.to_s,
+        ..LogFilter::test(target_test_id)
+    }
+    .query()
+    .order((dsl::id, dsl::elapsed_s, dsl::record_id))
+    .load::<BatteryLog>(conn)
+}
+
+/// Exports the selected samples of each test to one file per battery, and
+/// its verdicts if it was evaluated, returning the files written.
+#[tauri::command]
+#[specta::specta]
+pub fn export_test_csv(
+    state: State<'_, Mutex<AppState>>,
+    base_path: String,
+    options: ExportOptions,
+) -> Result<Vec<String>, String> {
+    let conn = &mut own_connection(&state)?;
+
+    let base_path = Path::new(&base_path);
+    std::fs::create_dir_all(base_path).map_err(|e| e.to_string())?;
+    let columns = match options.columns.is_empty() {
+        true => DEFAULT_COLUMNS.to_vec(),
+        false => options.columns.clone(),
+    };
+    let exported_at = get_current_time();
+
+    let mut written = Vec::new();
+    for &target_test_id in &options.test_ids {
+        use crate::database::schema::tests::dsl;
+        let test = dsl::tests
+            .filter(dsl::test_id.eq(target_test_id))
+            .first::<Test>(conn)
+            .map_err(|e| format!("Failed to load test {}: {}", target_test_id, e))?;
+        let logs = selected_logs(conn, target_test_id, &options)
+            .map_err(|e| format!("Failed to get logs for test {}: {}", target_test_id, e))?;
+
+        for battery in logs.chunk_by(|a, b| a.id == b.id) {
+            let battery_id = battery[0].id;
+            let path = export_path(base_path, &test, target_test_id, battery_id);
+            let file = File::create(&path).map_err(|e| e.to_string())?;
+            write_battery_csv(
+                file,
+                &test,
+                battery_id,
+                battery,
+                &columns,
+                &options.units,
+                &exported_at,
+            )?;
+            written.push(path.display().to_string());
+        }
+
+        let verdicts = verdict_rows(conn, Some(target_test_id)).map_err(|e| e.to_string())?;
+        if !verdicts.is_empty() {
+            let path = base_path.join(format!(
+                "{}_{}_verdicts.csv",
+                file_stem(&test.test_name),
+                target_test_id
+            ));
+            let mut wtr = Writer::from_path(&path).map_err(|e| e.to_string())?;
+            for verdict in verdicts {
+                wtr.serialize(verdict).map_err(|e| e.to_string())?;
+            }
+            wtr.flush().map_err(|e| e.to_string())?;
+            written.push(path.display().to_string());
+        }
+    }
+
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test() -> Test {
+        Test {
+            test_id: Some(7),
+            test_name: "Cells 3/4".to_string(),
+            start_date: "2025-08-01T08:00:00+00:00".to_string(),
+            profile_id: None,
+        }
+    }
+
+    fn sample(elapsed_s: f64, voltage: f32, current: f32) -> BatteryLog {
+        BatteryLog {
+            record_id: Some(1),
+            id: 2,
+            port: "COM3".to_string(),
+            battery_temperature: 25.5,
+            bench_temperature_mosfet: 30.0,
+            bench_temperature_resistor: 30.0,
+            load: 3.0,
+            voltage,
+            current,
+            state: "Discharge".to_string(),
+            status: "Cycle 1".to_string(),
+            start_date: None,
+            end_date: None,
+            test_id: 7,
+            recorded_at: "2025-08-01T09:00:00+00:00".to_string(),
+            elapsed_s,
+        }
+    }
+
+    #[test]
+    fn test_write_battery_csv() {
+        let logs = vec![
+            sample(3600.0, 3700.0, -1250.0),
+            sample(5400.0, 3650.0, -1250.0),
+        ];
+        let units = ExportUnits {
+            voltage: VoltageUnit::Volt,
+            current: CurrentUnit::Ampere,
+            time: TimeUnit::Hour,
+        };
+        let columns = [
+            ExportColumn::Elapsed,
+            ExportColumn::Voltage,
+            ExportColumn::Current,
+            ExportColumn::BatteryTemperature,
+        ];
+
+        let mut out = Vec::new();
+        write_battery_csv(&mut out, &test(), 2, &logs, &columns, &units, "now").unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+
+        assert_eq!(lines[0], "# test_id: 7");
+        assert!(lines.contains(&"# test_name: Cells 3/4"));
+        assert!(lines.contains(&"# samples: 2"));
+        let data: Vec<&str> = lines
+            .into_iter()
+            .filter(|line| !line.starts_with('#'))
+            .collect();
+        assert_eq!(
+            data,
+            [
+                "elapsed_h,voltage_V,current_A,battery_temperature_c",
+                "1,3.7,-1.25,25.5",
+                "1.5,3.65,-1.25,25.5",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_export_path() {
+        let dir = std::env::temp_dir().join(format!("export_path_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let path = export_path(&dir, &test(), 7, 2);
+        assert_eq!(path, dir.join("Cells_3_4_battery_2.csv"));
+        std::fs::write(&path, "# test_id: 7\n").unwrap();
+        // the same test exports over its own file
+        assert_eq!(export_path(&dir, &test(), 7, 2), path);
+        // another test with the same name doesn't
+        assert_eq!(
+            export_path(&dir, &test(), 8, 2),
+            dir.join("Cells_3_4_8_battery_2.csv")
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,870 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use diesel::prelude::*;
+use diesel::SqliteConnection;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tauri::State;
+use thiserror::Error;
+
+use crate::database::analysis::{analyze_test, test_logs};
+use crate::database::export::file_stem;
+use crate::database::models::{
+    AcceptanceCriteria, AlarmRecord, BatteryLog, CompletionEvent, ProfileRecord, SafetyLimits, Test,
+};
+use crate::database::sqlite::own_connection;
+use crate::serial::pilot::get_current_time;
+use crate::state::AppState;
+
+/// Version of the JSON bundle layout, bumped on incompatible changes.
+pub const BUNDLE_VERSION: u32 = 1;
+
+/// Rows per INSERT, well below SQLite's limit on bound variables.
+const INSERT_CHUNK: usize = 1000;
+
+#[derive(Error, Debug)]
+pub enum ImportError {
+    #[error("Failed to read {path}: {source}")]
+    Io {
+        path: String,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("Database operation error: {0}")]
+    Database(#[from] diesel::result::Error),
+}
+
+/// Everything stored about a test, written by [`export_test_bundle`] and read
+/// back without loss.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TestBundle {
+    pub format_version: u32,
+    pub test: Test,
+    pub profile: Option<ProfileRecord>,
+    pub safety_limits: Option<SafetyLimits>,
+    pub acceptance_criteria: Option<AcceptanceCriteria>,
+    pub logs: Vec<BatteryLog>,
+    pub completion_events: Vec<CompletionEvent>,
+    pub alarms: Vec<AlarmRecord>,
+}
+
+/// A row, or a whole file when `line` is `None`, that wasn't imported.
+#[derive(Debug, Clone, PartialEq, Type, Serialize, Deserialize)]
+pub struct RejectedRow {
+    pub file: String,
+    pub line: Option<u32>,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Type, Serialize, Deserialize)]
+pub struct ImportedTest {
+    /// The id the test had where it was exported, if the files tell.
+    pub source_test_id: Option<i32>,
+    /// The id it got here, different when the source id was taken.
+    pub test_id: i32,
+    pub test_name: String,
+    pub rows: u32,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Type, Serialize, Deserialize)]
+pub struct ImportReport {
+    pub tests: Vec<ImportedTest>,
+    pub rejected: Vec<RejectedRow>,
+    /// Files that hold no samples, such as verdicts and reports.
+    pub skipped: Vec<String>,
+}
+
+/// A `battery_logs` field and the factor from the file's unit to the stored
+/// one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Column {
+    RecordId,
+    BatteryId,
+    Port,
+    BatteryTemperature,
+    MosfetTemperature,
+    ResistorTemperature,
+    Load,
+    Voltage(f32),
+    Current(f32),
+    State,
+    Status,
+    StartDate,
+    EndDate,
+    TestId,
+    RecordedAt,
+    Elapsed(f64),
+}
+
+impl Column {
+    /// Reads the headers of [`export_csv`](crate::database::export::export_csv),
+    /// which are the `BatteryLog` field names, and the ones of
+    /// [`export_test_csv`](crate::database::export::export_test_csv).
+    fn from_header(header: &str) -> Option<Self> {
+        Some(match header.trim() {
+            "record_id" => Column::RecordId,
+            "id" | "battery_id" => Column::BatteryId,
+            "port" => Column::Port,
+            "battery_temperature" | "battery_temperature_c" => Column::BatteryTemperature,
+            "bench_temperature_mosfet" | "mosfet_temperature_c" => Column::MosfetTemperature,
+            "bench_temperature_resistor" | "resistor_temperature_c" => Column::ResistorTemperature,
+            "load" | "load_ohm" => Column::Load,
+            "voltage" | "voltage_mV" => Column::Voltage(1.0),
+            "voltage_V" => Column::Voltage(1000.0),
+            "current" | "current_mA" => Column::Current(1.0),
+            "current_A" => Column::Current(1000.0),
+            "state" => Column::State,
+            "status" => Column::Status,
+            "start_date" => Column::StartDate,
+            "end_date" => Column::EndDate,
+            "test_id" => Column::TestId,
+            "recorded_at" => Column::RecordedAt,
+            "elapsed_s" => Column::Elapsed(1.0),
+            "elapsed_min" => Column::Elapsed(60.0),
+            "elapsed_h" => Column::Elapsed(3600.0),
+            _ => return None,
+        })
+    }
+}
+
+/// Samples of one exported CSV file, by the test they were exported from.
+#[derive(Debug, Default)]
+struct CsvFile {
+    /// The `# key: value` lines at the top of test-scoped exports.
+    metadata: HashMap<String, String>,
+    rows: Vec<(i32, BatteryLog)>,
+    rejected: Vec<RejectedRow>,
+}
+
+fn parse_number<T: std::str::FromStr>(value: &str, name: &str) -> Result<T, String> {
+    value
+        .trim()
+        .parse::<T>()
+        .map_err(|_| format!("{} is not a number: {:?}", name, value))
+}
+
+fn parse_finite(value: &str, name: &str) -> Result<f32, String> {
+    let number: f32 = parse_number(value, name)?;
+    match number.is_finite() {
+        true => Ok(number),
+        false => Err(format!("{} is not finite: {:?}", name, value)),
+    }
+}
+
+fn optional(value: &str) -> Option<String> {
+    (!value.is_empty()).then(|| value.to_string())
+}
+
+fn parse_row(
+    columns: &[Column],
+    record: &csv::StringRecord,
+    default_battery: Option<i32>,
+    default_test: Option<i32>,
+) -> Result<(i32, BatteryLog), String> {
+    let mut log = BatteryLog {
+        record_id: None,
+        id: default_battery.unwrap_or(-1),
+        port: String::new(),
+        battery_temperature: 0.0,
+        bench_temperature_mosfet: 0.0,
+        bench_temperature_resistor: 0.0,
+        load: 0.0,
+        voltage: 0.0,
+        current: 0.0,
+        state: String::new(),
+        status: String::new(),
+        start_date: None,
+        end_date: None,
+        test_id: default_test.unwrap_or(-1),
+        recorded_at: String::new(),
+        elapsed_s: 0.0,
+    };
+
+    for (column, value) in columns.iter().zip(record.iter()) {
+        match *column {
+            // the database gives the rows new ids
+            Column::RecordId => {}
+            Column::BatteryId => log.id = parse_number(value, "battery id")?,
+            Column::Port => log.port = value.to_string(),
+            Column::BatteryTemperature => {
+                log.battery_temperature = parse_finite(value, "battery temperature")?
+            }
+            Column::MosfetTemperature => {
+                log.bench_temperature_mosfet = parse_finite(value, "mosfet temperature")?
+            }
+            Column::ResistorTemperature => {
+                log.bench_temperature_resistor = parse_finite(value, "resistor temperature")?
+            }
+            Column::Load => log.load = parse_finite(value, "load")?,
+            Column::Voltage(scale) => log.voltage = parse_finite(value, "voltage")? * scale,
+            Column::Current(scale) => log.current = parse_finite(value, "current")? * scale,
+            Column::State => log.state = value.to_string(),
+            Column::Status => log.status = value.to_string(),
+            Column::StartDate => log.start_date = optional(value),
+            Column::EndDate => log.end_date = optional(value),
+            Column::TestId => log.test_id = parse_number(value, "test id")?,
+            Column::RecordedAt => {
+                if !value.is_empty() {
+                    chrono::DateTime::parse_from_rfc3339(value)
+                        .map_err(|_| format!("recorded_at is not a timestamp: {:?}", value))?;
+                }
+                log.recorded_at = value.to_string();
+            }
+            Column::Elapsed(scale) => {
+                log.elapsed_s = parse_finite(value, "elapsed time")? as f64 * scale
+            }
+        }
+    }
+
+    if !(0..=u8::MAX as i32).contains(&log.id) {
+        return Err(format!("battery id {} is out of range", log.id));
+    }
+    Ok((log.test_id, log))
+}
+
+/// Reads a CSV file written by either CSV export.
+fn parse_csv(file: &str, text: &str) -> CsvFile {
+    let mut parsed = CsvFile::default();
+    let reject = |line: Option<u32>, reason: String| RejectedRow {
+        file: file.to_string(),
+        line,
+        reason,
+    };
+
+    for line in text.lines().take_while(|line| line.starts_with('#')) {
+        if let Some((key, value)) = line.trim_start_matches('#').split_once(':') {
+            parsed
+                .metadata
+                .insert(key.trim().to_string(), value.trim().to_string());
+        }
+    }
+    let header_number = |key: &str| {
+        parsed
+            .metadata
+            .get(key)
+            .and_then(|value| value.parse::<i32>().ok())
+    };
+    let (default_battery, default_test) = (header_number("battery_id"), header_number("test_id"));
+
+    let mut reader = csv::ReaderBuilder::new()
+        .comment(Some(b'#'))
+        .from_reader(text.as_bytes());
+    let headers = match reader.headers() {
+        Ok(headers) => headers.clone(),
+        Err(error) => {
+            parsed.rejected.push(reject(None, error.to_string()));
+            return parsed;
+        }
+    };
+    let mut columns = Vec::new();
+    for header in headers.iter() {
+        match Column::from_header(header) {
+            Some(column) => columns.push(column),
+            None => {
+                parsed
+                    .rejected
+                    .push(reject(None, format!("unknown column {:?}", header)));
+                return parsed;
+            }
+        }
+    }
+    for (required, present) in [
+        (
+            "battery id",
+            default_battery.is_some() || columns.contains(&Column::BatteryId),
+        ),
+        (
+            "test id",
+            default_test.is_some() || columns.contains(&Column::TestId),
+        ),
+        (
+            "voltage",
+            columns.iter().any(|c| matches!(c, Column::Voltage(_))),
+        ),
+        (
+            "current",
+            columns.iter().any(|c| matches!(c, Column::Current(_))),
+        ),
+    ] {
+        if !present {
+            parsed
+                .rejected
+                .push(reject(None, format!("no {} column", required)));
+            return parsed;
+        }
+    }
+
+    for record in reader.records() {
+        let row = record
+            .map_err(|error| (error.position().map(|p| p.line() as u32), error.to_string()))
+            .and_then(|record| {
+                let line = record.position().map(|p| p.line() as u32);
+                parse_row(&columns, &record, default_battery, default_test)
+                    .map_err(|reason| (line, reason))
+            });
+        match row {
+            Ok(row) => parsed.rows.push(row),
+            Err((line, reason)) => parsed.rejected.push(reject(line, reason)),
+        }
+    }
+    parsed
+}
+
+fn test_id_taken(conn: &mut SqliteConnection, target_test_id: i32) -> QueryResult<bool> {
+    use crate::database::schema::tests::dsl;
+    Ok(dsl::tests
+        .filter(dsl::test_id.eq(target_test_id))
+        .count()
+        .get_result::<i64>(conn)?
+        > 0)
+}
+
+/// The profile with the same name and definition, or a new one. Profile
+/// names are unique, so a different profile of the same name gets renamed.
+fn import_profile(conn: &mut SqliteConnection, profile: &ProfileRecord) -> QueryResult<i32> {
+    use crate::database::schema::test_profiles::dsl;
+
+    let same_name = dsl::test_profiles
+        .filter(dsl::name.eq(&profile.name))
+        .first::<ProfileRecord>(conn)
+        .optional()?;
+    let name = match same_name {
+        Some(existing) if existing.definition == profile.definition => {
+            return Ok(existing.profile_id.unwrap_or_default());
+        }
+        Some(_) => format!("{} (imported {})", profile.name, get_current_time()),
+        None => profile.name.clone(),
+    };
+
+    let inserted: ProfileRecord = diesel::insert_into(dsl::test_profiles)
+        .values(&ProfileRecord {
+            profile_id: None,
+            name,
+            ..profile.clone()
+        })
+        .get_result(conn)?;
+    Ok(inserted.profile_id.unwrap_or_default())
+}
+
+/// Stores `bundle` as a new test, keeping its id unless another test has it.
+pub fn import_bundle(conn: &mut SqliteConnection, bundle: TestBundle) -> QueryResult<ImportedTest> {
+    use crate::database::schema::{
+        acceptance_criteria, alarms, battery_logs, completion_events, safety_limits, tests,
+    };
+
+    let imported = conn.transaction(|conn| {
+        let source_test_id = bundle.test.test_id;
+        let keep_id = match source_test_id {
+            Some(id) => !test_id_taken(conn, id)?,
+            None => false,
+        };
+        let profile_id = match &bundle.profile {
+            Some(profile) => Some(import_profile(conn, profile)?),
+            None => None,
+        };
+        let test: Test = diesel::insert_into(tests::table)
+            .values(&Test {
+                test_id: source_test_id.filter(|_| keep_id),
+                profile_id,
+                ..bundle.test.clone()
+            })
+            .get_result(conn)?;
+        let test_id = test.test_id.unwrap_or_default();
+
+        let logs: Vec<BatteryLog> = bundle
+            .logs
+            .into_iter()
+            .map(|log| BatteryLog {
+                record_id: None,
+                test_id,
+                ..log
+            })
+            .collect();
+        for chunk in logs.chunks(INSERT_CHUNK) {
+            diesel::insert_into(battery_logs::table)
+                .values(chunk)
+                .execute(conn)?;
+        }
+
+        let events: Vec<CompletionEvent> = bundle
+            .completion_events
+            .into_iter()
+            .map(|event| CompletionEvent {
+                record_id: None,
+                test_id: Some(test_id),
+                ..event
+            })
+            .collect();
+        diesel::insert_into(completion_events::table)
+            .values(&events)
+            .execute(conn)?;
+        let alarm_records: Vec<AlarmRecord> = bundle
+            .alarms
+            .into_iter()
+            .map(|alarm| AlarmRecord {
+                alarm_id: None,
+                test_id: Some(test_id),
+                ..alarm
+            })
+            .collect();
+        diesel::insert_into(alarms::table)
+            .values(&alarm_records)
+            .execute(conn)?;
+        if let Some(limits) = bundle.safety_limits {
+            diesel::insert_into(safety_limits::table)
+                .values(&SafetyLimits { test_id, ..limits })
+                .execute(conn)?;
+        }
+        if let Some(criteria) = bundle.acceptance_criteria {
+            diesel::insert_into(acceptance_criteria::table)
+                .values(&AcceptanceCriteria {
+                    test_id,
+                    ..criteria
+                })
+                .execute(conn)?;
+        }
+
+        Ok::<_, diesel::result::Error>(ImportedTest {
+            source_test_id,
+            test_id,
+            test_name: test.test_name,
+            rows: logs.len() as u32,
+        })
+    })?;
+
+    analyze_test(conn, imported.test_id)?;
+    Ok(imported)
+}
+
+/// Rows from CSV exports that came from the same source test.
+#[derive(Default)]
+struct CsvTest {
+    /// Name and start from the file headers.
+    test: Option<Test>,
+    logs: Vec<BatteryLog>,
+    files: Vec<String>,
+}
+
+/// Imports every JSON bundle and CSV export in `dir`. CSV rows are grouped
+/// by the test they were exported from, and each group becomes a new test.
+/// Each test is imported in its own transaction; one that fails is rolled
+/// back and reported as rejected, and the rest are still imported.
+pub fn import_directory(
+    conn: &mut SqliteConnection,
+    dir: &Path,
+) -> Result<ImportReport, ImportError> {
+    let mut paths: Vec<PathBuf> = std::fs::read_dir(dir)
+        .map_err(|source| ImportError::Io {
+            path: dir.display().to_string(),
+            source,
+        })?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_file())
+        .collect();
+    paths.sort();
+
+    let mut report = ImportReport::default();
+    let mut csv_tests: BTreeMap<i32, CsvTest> = BTreeMap::new();
+
+    for path in paths {
+        let file = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let extension = path
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase());
+
+        let read = |path: &Path| {
+            std::fs::read_to_string(path).map_err(|e| RejectedRow {
+                file: file.clone(),
+                line: None,
+                reason: format!("not read: {}", e),
+            })
+        };
+
+        match extension.as_deref() {
+            Some("json") => {
+                let text = match read(&path) {
+                    Ok(text) => text,
+                    Err(rejected) => {
+                        report.rejected.push(rejected);
+                        continue;
+                    }
+                };
+                match serde_json::from_str::<TestBundle>(&text) {
+                    Ok(bundle) if bundle.format_version > BUNDLE_VERSION => {
+                        report.rejected.push(RejectedRow {
+                            file,
+                            line: None,
+                            reason: format!(
+                                "bundle version {} is newer than {}",
+                                bundle.format_version, BUNDLE_VERSION
+                            ),
+                        })
+                    }
+                    Ok(bundle) => match import_bundle(conn, bundle) {
+                        Ok(imported) => report.tests.push(imported),
+                        Err(e) => report.rejected.push(RejectedRow {
+                            file,
+                            line: None,
+                            reason: format!("not imported: {}", e),
+                        }),
+                    },
+                    Err(error) => report.rejected.push(RejectedRow {
+                        file,
+                        line: Some(error.line() as u32),
+                        reason: error.to_string(),
+                    }),
+                }
+            }
+            Some("csv") if !file.ends_with("verdicts.csv") => {
+                let text = match read(&path) {
+                    Ok(text) => text,
+                    Err(rejected) => {
+                        report.rejected.push(rejected);
+                        continue;
+                    }
+                };
+                let parsed = parse_csv(&file, &text);
+                report.rejected.extend(parsed.rejected);
+                for (source_test_id, log) in parsed.rows {
+                    let entry = csv_tests.entry(source_test_id).or_default();
+                    if entry.test.is_none() {
+                        entry.test = parsed.metadata.get("test_name").map(|name| Test {
+                            test_id: Some(source_test_id),
+                            test_name: name.clone(),
+                            start_date: parsed
+                                .metadata
+                                .get("test_started")
+                                .cloned()
+                                .unwrap_or_default(),
+                            profile_id: None,
+                        });
+                    }
+                    entry.logs.push(log);
+                    if entry.files.last() != Some(&file) {
+                        entry.files.push(file.clone());
+                    }
+                }
+            }
+            _ => report.skipped.push(file),
+        }
+    }
+
+    for (
+        source_test_id,
+        CsvTest {
+            test,
+            mut logs,
+            files,
+        },
+    ) in csv_tests
+    {
+        logs.sort_by(|a, b| a.id.cmp(&b.id).then(a.elapsed_s.total_cmp(&b.elapsed_s)));
+        let start_date = logs
+            .iter()
+            .filter_map(|log| optional(&log.recorded_at).or(log.start_date.clone()))
+            .min()
+            .unwrap_or_else(get_current_time);
+        let test = test.unwrap_or_else(|| Test {
+            test_id: Some(source_test_id),
+            test_name: format!("Imported test {}", source_test_id),
+            start_date,
+            profile_id: None,
+        });
+
+        let imported = import_bundle(
+            conn,
+            TestBundle {
+                format_version: BUNDLE_VERSION,
+                test,
+                profile: None,
+                safety_limits: None,
+                acceptance_criteria: None,
+                logs,
+                completion_events: Vec::new(),
+                alarms: Vec::new(),
+            },
+        );
+        match imported {
+            Ok(imported) => report.tests.push(imported),
+            Err(e) => report.rejected.push(RejectedRow {
+                file: files.join(", "),
+                line: None,
+                reason: format!("test {} not imported: {}", source_test_id, e),
+            }),
+        }
+    }
+
+    Ok(report)
+}
+
+/// Everything stored about `target_test_id`, as a [`TestBundle`].
+pub fn test_bundle(conn: &mut SqliteConnection, target_test_id: i32) -> QueryResult<TestBundle> {
+    use crate::database::schema::{
+        acceptance_criteria, alarms, completion_events, safety_limits, test_profiles, tests,
+    };
+
+    let test = tests::table
+        .filter(tests::test_id.eq(target_test_id))
+        .first::<Test>(conn)?;
+    let profile = match test.profile_id {
+        Some(profile_id) => test_profiles::table
+            .filter(test_profiles::profile_id.eq(profile_id))
+            .first::<ProfileRecord>(conn)
+            .optional()?,
+        None => None,
+    };
+
+    Ok(TestBundle {
+        format_version: BUNDLE_VERSION,
+        profile,
+        safety_limits: safety_limits::table
+            .filter(safety_limits::test_id.eq(target_test_id))
+            .first::<SafetyLimits>(conn)
+            .optional()?,
+        acceptance_criteria: acceptance_criteria::table
+            .filter(acceptance_criteria::test_id.eq(target_test_id))
+            .first::<AcceptanceCriteria>(conn)
+            .optional()?,
+        logs: test_logs(conn, target_test_id)?,
+        completion_events: completion_events::table
+            .filter(completion_events::test_id.eq(target_test_id))
+            .order(completion_events::record_id)
+            .load::<CompletionEvent>(conn)?,
+        alarms: alarms::table
+            .filter(alarms::test_id.eq(target_test_id))
+            .order(alarms::alarm_id)
+            .load::<AlarmRecord>(conn)?,
+        test,
+    })
+}
+
+/// Writes a test as a JSON bundle to `base_path`, returning the file written.
+#[tauri::command]
+#[specta::specta]
+pub fn export_test_bundle(
+    state: State<'_, Mutex<AppState>>,
+    base_path: String,
+    target_test_id: i32,
+) -> Result<String, String> {
+    let conn = &mut own_connection(&state)?;
+
+    let bundle = test_bundle(conn, target_test_id)
+        .map_err(|e| format!("Failed to load test {}: {}", target_test_id, e))?;
+    let path = Path::new(&base_path).join(format!(
+        "{}_{}.json",
+        file_stem(&bundle.test.test_name),
+        target_test_id
+    ));
+    let json = serde_json::to_string(&bundle).map_err(|e| e.to_string())?;
+    std::fs::write(&path, json)
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+
+    Ok(path.display().to_string())
+}
+
+/// Imports the JSON bundles and CSV exports in `dir` as new tests.
+#[tauri::command]
+#[specta::specta]
+pub fn import_tests(
+    state: State<'_, Mutex<AppState>>,
+    dir: String,
+) -> Result<ImportReport, String> {
+    let conn = &mut own_connection(&state)?;
+
+    import_directory(conn, Path::new(&dir)).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::sqlite::{establish_connection, MIGRATIONS};
+    use diesel_migrations::MigrationHarness;
+
+    fn database() -> SqliteConnection {
+        let mut conn = establish_connection(":memory:").unwrap();
+        conn.run_pending_migrations(MIGRATIONS).unwrap();
+        diesel::insert_into(crate::database::schema::tests::table)
+            .values(&Test {
+                test_id: Some(1),
+                test_name: "Test 1".to_string(),
+                start_date: "2025-08-03T08:00:00+00:00".to_string(),
+                profile_id: None,
+            })
+            .execute(&mut conn)
+            .unwrap();
+        conn
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("{}_{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_parse_csv() {
+        let legacy = "record_id,id,port,battery_temperature,bench_temperature_mosfet,\
+                      bench_temperature_resistor,load,voltage,current,state,status,start_date,\
+                      end_date,test_id,recorded_at,elapsed_s\n\
+                      4,2,COM3,25.5,30,30,3,3700,-1250,Discharge,Cycle 1,,,1,2025-08-03T08:00:00+00:00,60\n\
+                      5,2,COM3,25.5,30,30,3,37x0,-1250,Discharge,Cycle 1,,,1,2025-08-03T08:01:00+00:00,120\n\
+                      6,300,COM3,25.5,30,30,3,3700,-1250,Discharge,Cycle 1,,,1,,180\n";
+        let parsed = parse_csv("battery_2.csv", legacy);
+        assert_eq!(parsed.rows.len(), 1);
+        assert_eq!(parsed.rows[0].0, 1);
+        assert_eq!(parsed.rows[0].1.voltage, 3700.0);
+        assert_eq!(parsed.rows[0].1.record_id, None);
+        assert_eq!(
+            parsed.rejected,
+            [
+                RejectedRow {
+                    file: "battery_2.csv".to_string(),
+                    line: Some(3),
+                    reason: "voltage is not a number: \"37x0\"".to_string(),
+                },
+                RejectedRow {
+                    file: "battery_2.csv".to_string(),
+                    line: Some(4),
+                    reason: "battery id 300 is out of range".to_string(),
+                },
+            ]
+        );
+
+        // test-scoped exports carry the ids in the header and may use other units
+        let scoped = "# test_id: 9\n# test_name: Bench A\n# battery_id: 4\n\
+                      elapsed_h,voltage_V,current_A\n1.5,3.7,-1.25\n";
+        let parsed = parse_csv("Bench_A_battery_4.csv", scoped);
+        assert!(parsed.rejected.is_empty());
+        let (test_id, log) = &parsed.rows[0];
+        assert_eq!((*test_id, log.id), (9, 4));
+        assert_eq!(
+            (log.elapsed_s, log.voltage, log.current),
+            (5400.0, 3700.0, -1250.0)
+        );
+
+        let parsed = parse_csv("other.csv", "id,voltage,current,colour\n1,2,3,red\n");
+        assert_eq!(parsed.rejected[0].reason, "unknown column \"colour\"");
+        let parsed = parse_csv("other.csv", "id,voltage,current\n1,2,3\n");
+        assert_eq!(parsed.rejected[0].reason, "no test id column");
+    }
+
+    #[test]
+    fn test_import_directory() {
+        let mut conn = database();
+        let dir = temp_dir("import_directory");
+        // battery 2 of tests 1 and 3, like export_csv writes them
+        std::fs::write(
+            dir.join("battery_2.csv"),
+            "id,voltage,current,test_id,elapsed_s\n2,3700,-1250,1,0\n2,3690,-1250,1,60\n\
+             2,4000,1000,3,0\n",
+        )
+        .unwrap();
+        std::fs::write(dir.join("verdicts.csv"), "test_id,battery_id\n").unwrap();
+
+        let report = import_directory(&mut conn, &dir).unwrap();
+        assert_eq!(report.skipped, ["verdicts.csv"]);
+        assert!(report.rejected.is_empty());
+        // test 1 already exists here, test 3 keeps its id
+        assert_eq!(report.tests.len(), 2);
+        assert_eq!(report.tests[0].source_test_id, Some(1));
+        assert_ne!(report.tests[0].test_id, 1);
+        assert_eq!(report.tests[0].rows, 2);
+        assert_eq!(report.tests[1].test_id, 3);
+        assert_eq!(test_logs(&mut conn, 3).unwrap().len(), 1);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_import_directory_keeps_going() {
+        let mut conn = database();
+        let dir = temp_dir("import_keeps_going");
+        diesel::insert_into(crate::database::schema::safety_limits::table)
+            .values(&SafetyLimits::defaults(1))
+            .execute(&mut conn)
+            .unwrap();
+        let bundle = test_bundle(&mut conn, 1).unwrap();
+        std::fs::write(dir.join("a.json"), serde_json::to_string(&bundle).unwrap()).unwrap();
+        std::fs::write(
+            dir.join("battery_2.csv"),
+            "id,voltage,current,test_id,elapsed_s\n2,4000,1000,3,0\n",
+        )
+        .unwrap();
+        // the bundle's safety limits have nowhere to go
+        diesel::sql_query("DROP TABLE safety_limits")
+            .execute(&mut conn)
+            .unwrap();
+
+        let report = import_directory(&mut conn, &dir).unwrap();
+        assert_eq!(report.rejected.len(), 1);
+        assert_eq!(report.rejected[0].file, "a.json");
+        assert!(report.rejected[0].reason.starts_with("not imported"));
+        assert_eq!(report.tests.len(), 1);
+        assert_eq!(report.tests[0].test_id, 3);
+        // the failed bundle left nothing behind
+        assert_eq!(
+            crate::database::schema::tests::table
+                .count()
+                .get_result::<i64>(&mut conn)
+                .unwrap(),
+            2
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_bundle_round_trip() {
+        let mut conn = database();
+        let log = BatteryLog {
+            record_id: None,
+            id: 2,
+            port: "COM3".to_string(),
+            battery_temperature: 25.25,
+            bench_temperature_mosfet: 30.5,
+            bench_temperature_resistor: 31.0,
+            load: 2.96,
+            voltage: 3700.0,
+            current: -1250.0,
+            state: "Discharge".to_string(),
+            status: "Cycle 1".to_string(),
+            start_date: None,
+            end_date: None,
+            test_id: 1,
+            recorded_at: "2025-08-03T08:00:00+00:00".to_string(),
+            elapsed_s: 0.25,
+        };
+        diesel::insert_into(crate::database::schema::battery_logs::table)
+            .values(&log)
+            .execute(&mut conn)
+            .unwrap();
+        diesel::insert_into(crate::database::schema::safety_limits::table)
+            .values(&SafetyLimits::defaults(1))
+            .execute(&mut conn)
+            .unwrap();
+
+        let bundle = test_bundle(&mut conn, 1).unwrap();
+        let json = serde_json::to_string(&bundle).unwrap();
+        let imported = import_bundle(&mut conn, serde_json::from_str(&json).unwrap()).unwrap();
+        assert_ne!(imported.test_id, 1);
+        assert_eq!(imported.test_name, "Test 1");
+
+        let copy = test_bundle(&mut conn, imported.test_id).unwrap();
+        let stored = &copy.logs[0];
+        assert_eq!(stored.test_id, imported.test_id);
+        assert_eq!(
+            (stored.battery_temperature, stored.load, stored.elapsed_s),
+            (25.25, 2.96, 0.25)
+        );
+        assert_eq!(stored.recorded_at, log.recorded_at);
+        assert_eq!(
+            copy.safety_limits.unwrap().max_battery_temperature_c,
+            SafetyLimits::defaults(1).max_battery_temperature_c
+        );
+    }
+}
@@ -1,4 +1,13 @@
+pub mod acceptance;
+pub mod analysis;
+pub mod archive;
+pub mod columnar;
 pub mod export;
+pub mod import;
 pub mod models;
+pub mod profile;
+pub mod query;
+pub mod report;
 pub mod schema;
 pub mod sqlite;
+pub mod writer;
@@ -3,7 +3,11 @@
 #![allow(unused)]
 #![allow(clippy::all)]
 
-use crate::database::schema::{battery_logs, tests};
+use crate::database::schema::{
+    acceptance_criteria, alarms, battery_ids, battery_logs, cell_verdicts, completion_events,
+    criterion_results, phase_results, reserved_ids, safety_limits, sequence_states, test_profiles,
+    tests,
+};
 
 use diesel::prelude::*;
 use serde::{Deserialize, Serialize};
@@ -15,24 +19,194 @@ pub struct BatteryLog {
     pub record_id: Option<i32>,
     pub id: i32,
     pub port: String,
-    pub battery_temperature: i32,
-    pub bench_temperature_mosfet: i32,
-    pub bench_temperature_resistor: i32,
-    pub load: i32,
-    pub voltage: i32,
-    pub current: i32,
+    /// Temperatures in °C.
+    pub battery_temperature: f32,
+    pub bench_temperature_mosfet: f32,
+    pub bench_temperature_resistor: f32,
+    /// Electronic load resistance in ohms.
+    pub load: f32,
+    /// Battery voltage in mV.
+    pub voltage: f32,
+    /// Bench current in mA, negative while discharging.
+    pub current: f32,
     pub state: String,
     pub status: String,
     pub start_date: Option<String>,
     pub end_date: Option<String>,
     pub test_id: i32,
+    /// When the sample was taken, RFC 3339 UTC.
+    pub recorded_at: String,
+    /// Seconds since the test started, on a clock that ignores wall-clock
+    /// adjustments.
+    pub elapsed_s: f64,
 }
 
-#[derive(Queryable, Debug, Identifiable, Insertable, Type, Serialize, Deserialize)]
+#[derive(Queryable, Debug, Identifiable, Insertable, Type, Serialize, Deserialize, Clone)]
 #[diesel(primary_key(test_id))]
 #[diesel(table_name = tests)]
 pub struct Test {
     pub test_id: Option<i32>,
     pub test_name: String,
     pub start_date: String,
+    pub profile_id: Option<i32>,
+}
+
+#[derive(Queryable, Debug, Identifiable, Insertable, Type, Serialize, Deserialize, Clone)]
+#[diesel(primary_key(battery_id))]
+#[diesel(table_name = battery_ids)]
+pub struct BatteryIdAssignment {
+    pub battery_id: i32,
+    pub port: String,
+    pub assigned_at: String,
+}
+
+#[derive(Queryable, Debug, Identifiable, Insertable, Type, Serialize, Deserialize, Clone)]
+#[diesel(primary_key(battery_id))]
+#[diesel(table_name = reserved_ids)]
+pub struct ReservedId {
+    pub battery_id: i32,
+    pub cell_label: String,
+    pub test_id: Option<i32>,
+    pub reserved_at: String,
+}
+
+#[derive(Queryable, Debug, Identifiable, Insertable, Type, Serialize, Deserialize, Clone)]
+#[diesel(primary_key(record_id))]
+#[diesel(table_name = completion_events)]
+pub struct CompletionEvent {
+    pub record_id: Option<i32>,
+    pub battery_id: i32,
+    pub port: String,
+    pub phase: String,
+    pub outcome: String,
+    pub flags: i32,
+    pub received_at: String,
+    pub test_id: Option<i32>,
+}
+
+#[derive(Queryable, Debug, Identifiable, Insertable, Type, Serialize, Deserialize, Clone)]
+#[diesel(primary_key(profile_id))]
+#[diesel(table_name = test_profiles)]
+pub struct ProfileRecord {
+    pub profile_id: Option<i32>,
+    pub name: String,
+    /// The profile as JSON.
+    pub definition: String,
+    pub created_at: String,
+}
+
+/// Interlock limits of a test. Temperatures are in °C, voltage in mV and
+/// current in mA, either direction.
+#[derive(Queryable, Debug, Identifiable, Insertable, Type, Serialize, Deserialize, Clone)]
+#[diesel(primary_key(test_id))]
+#[diesel(table_name = safety_limits)]
+pub struct SafetyLimits {
+    pub test_id: i32,
+    pub max_battery_temperature_c: Option<f32>,
+    pub max_mosfet_temperature_c: Option<f32>,
+    pub max_resistor_temperature_c: Option<f32>,
+    pub min_voltage_mv: Option<f32>,
+    pub max_voltage_mv: Option<f32>,
+    pub max_current_ma: Option<f32>,
+}
+
+#[derive(Queryable, Debug, Identifiable, Insertable, Type, Serialize, Deserialize, Clone)]
+#[diesel(primary_key(alarm_id))]
+#[diesel(table_name = alarms)]
+pub struct AlarmRecord {
+    pub alarm_id: Option<i32>,
+    pub battery_id: i32,
+    pub port: String,
+    pub quantity: String,
+    pub value: f32,
+    pub threshold: f32,
+    pub state: String,
+    pub raised_at: String,
+    pub test_id: Option<i32>,
+}
+
+/// Last known position of a battery in its sequence.
+#[derive(Queryable, Debug, Identifiable, Insertable, Type, Serialize, Deserialize, Clone)]
+#[diesel(primary_key(test_id, battery_id))]
+#[diesel(table_name = sequence_states)]
+pub struct SequenceState {
+    pub test_id: i32,
+    pub battery_id: i32,
+    pub port: String,
+    pub step: i32,
+    pub steps: i32,
+    pub step_label: String,
+    pub state: String,
+    pub outcome: String,
+    pub step_started_at: String,
+    pub charge_mah: f32,
+    pub updated_at: String,
+}
+
+/// Charge and energy of one charge or discharge phase of a battery.
+#[derive(Queryable, Debug, Identifiable, Insertable, Type, Serialize, Deserialize, Clone)]
+#[diesel(primary_key(test_id, battery_id, phase_index))]
+#[diesel(table_name = phase_results)]
+pub struct PhaseResult {
+    pub test_id: i32,
+    pub battery_id: i32,
+    /// Position of the phase among the battery's phases, from 0.
+    pub phase_index: i32,
+    /// Charge/discharge cycle the phase belongs to, from 1.
+    pub cycle: i32,
+    pub state: String,
+    /// Step label the samples were logged with.
+    pub label: String,
+    /// How the bench announced the end of the phase, if it did.
+    pub outcome: Option<String>,
+    pub started_at: String,
+    pub ended_at: String,
+    pub duration_s: f64,
+    pub samples: i32,
+    pub charge_mah: f64,
+    pub energy_wh: f64,
+    pub computed_at: String,
+}
+
+/// What a cell has to meet to be accepted. Criteria left `None` are not
+/// evaluated.
+#[derive(Queryable, Debug, Identifiable, Insertable, Type, Serialize, Deserialize, Clone)]
+#[diesel(primary_key(test_id))]
+#[diesel(table_name = acceptance_criteria)]
+pub struct AcceptanceCriteria {
+    pub test_id: i32,
+    /// Capacity of the last discharge.
+    pub min_discharge_capacity_mah: Option<f32>,
+    /// Loss of discharge capacity from the first to the last cycle.
+    pub max_capacity_fade_pct: Option<f32>,
+    /// Battery temperature above the first sample of the test.
+    pub max_temperature_rise_c: Option<f32>,
+    pub min_voltage_mv: Option<f32>,
+    pub max_voltage_mv: Option<f32>,
+    /// Estimated from the voltage step when the current changes.
+    pub max_internal_resistance_mohm: Option<f32>,
+}
+
+#[derive(Queryable, Debug, Identifiable, Insertable, Type, Serialize, Deserialize, Clone)]
+#[diesel(primary_key(test_id, battery_id))]
+#[diesel(table_name = cell_verdicts)]
+pub struct CellVerdict {
+    pub test_id: i32,
+    pub battery_id: i32,
+    pub verdict: String,
+    pub evaluated_at: String,
+}
+
+#[derive(Queryable, Debug, Identifiable, Insertable, Type, Serialize, Deserialize, Clone)]
+#[diesel(primary_key(test_id, battery_id, criterion))]
+#[diesel(table_name = criterion_results)]
+pub struct CriterionResult {
+    pub test_id: i32,
+    pub battery_id: i32,
+    pub criterion: String,
+    pub measured: Option<f64>,
+    pub threshold: f64,
+    pub outcome: String,
+    /// Where the measured value comes from.
+    pub evidence: String,
 }
@@ -0,0 +1,557 @@
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use diesel::prelude::*;
+use diesel::SqliteConnection;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tauri::State;
+use thiserror::Error;
+
+use crate::database::models::{ProfileRecord, Test};
+use crate::database::sqlite::connection;
+use crate::serial::pilot::{get_current_time, BatteryState};
+use crate::state::AppState;
+
+/// Upper bound on the steps a profile expands to, so a typo in a repeat count
+/// can't produce a sequence that never ends.
+const MAX_STEPS: usize = 1000;
+
+#[derive(Error, Debug)]
+pub enum ProfileError {
+    #[error("Profile has no name")]
+    NoName,
+    #[error("Profile has no steps")]
+    NoSteps,
+    #[error("Repeat block has no steps")]
+    EmptyRepeat,
+    #[error("Repeat count must be at least 1")]
+    ZeroCount,
+    #[error("Rest duration must be at least 1 s")]
+    ZeroDuration,
+    #[error("Profile expands to {steps} steps, at most {MAX_STEPS} are allowed")]
+    TooManySteps { steps: usize },
+    #[error("Invalid profile: {0}")]
+    Parse(String),
+    #[error("Failed to access profile file: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// Limits checked against every sample while a charge or discharge step runs.
+#[derive(Debug, Clone, Default, PartialEq, Type, Serialize, Deserialize)]
+pub struct StepLimits {
+    /// Fails the step when the bench hasn't completed it by then.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_duration_s: Option<u32>,
+    /// Fails the step when the battery gets hotter, in °C.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_temperature_c: Option<f32>,
+    /// Ends the step before the bench does once the voltage gets there, e.g.
+    /// for a storage charge.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub until_voltage_mv: Option<u32>,
+}
+
+/// A charge or discharge step.
+#[derive(Debug, Clone, Default, PartialEq, Type, Serialize, Deserialize)]
+pub struct PhaseStep {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    #[serde(flatten)]
+    pub limits: StepLimits,
+}
+
+#[derive(Debug, Clone, PartialEq, Type, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ProfileStep {
+    Charge(PhaseStep),
+    Discharge(PhaseStep),
+    /// Standby for a fixed time, to let the cell settle.
+    Rest {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        label: Option<String>,
+        duration_s: u32,
+    },
+    Repeat {
+        count: u32,
+        steps: Vec<ProfileStep>,
+    },
+}
+
+/// An ordered list of steps run on every battery of a test.
+#[derive(Debug, Clone, PartialEq, Type, Serialize, Deserialize)]
+pub struct TestProfile {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub steps: Vec<ProfileStep>,
+}
+
+/// A step of a profile with its repeat blocks unrolled.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Step {
+    pub state: BatteryState,
+    /// "Cycle 2" inside a repeat block, the step kind otherwise, unless the
+    /// profile names it.
+    pub label: String,
+    pub limits: StepLimits,
+    /// How long a rest step lasts. Charge and discharge steps end when the
+    /// bench completes them instead.
+    pub rest: Option<Duration>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProfileFormat {
+    Json,
+    Toml,
+}
+
+impl ProfileFormat {
+    /// TOML for `.toml` files, JSON for anything else.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some(extension) if extension.eq_ignore_ascii_case("toml") => ProfileFormat::Toml,
+            _ => ProfileFormat::Json,
+        }
+    }
+}
+
+impl TestProfile {
+    /// Three charge/discharge cycles and a top-up charge, used by tests
+    /// created without a profile.
+    pub fn qualification() -> Self {
+        TestProfile {
+            name: "Qualification".to_string(),
+            description: Some("Three charge/discharge cycles, then a top-up charge".to_string()),
+            steps: vec![
+                ProfileStep::Repeat {
+                    count: 3,
+                    steps: vec![
+                        ProfileStep::Charge(PhaseStep::default()),
+                        ProfileStep::Discharge(PhaseStep::default()),
+                    ],
+                },
+                ProfileStep::Charge(PhaseStep {
+                    label: Some("Top-up".to_string()),
+                    limits: StepLimits::default(),
+                }),
+            ],
+        }
+    }
+
+    pub fn parse(text: &str, format: ProfileFormat) -> Result<Self, ProfileError> {
+        let profile: TestProfile = match format {
+            ProfileFormat::Json => {
+                serde_json::from_str(text).map_err(|e| ProfileError::Parse(e.to_string()))?
+            }
+            ProfileFormat::Toml => {
+                toml::from_str(text).map_err(|e| ProfileError::Parse(e.to_string()))?
+            }
+        };
+
+        profile.validate()?;
+        Ok(profile)
+    }
+
+    pub fn to_string(&self, format: ProfileFormat) -> Result<String, ProfileError> {
+        match format {
+            ProfileFormat::Json => {
+                serde_json::to_string_pretty(self).map_err(|e| ProfileError::Parse(e.to_string()))
+            }
+            ProfileFormat::Toml => {
+                toml::to_string_pretty(self).map_err(|e| ProfileError::Parse(e.to_string()))
+            }
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), ProfileError> {
+        if self.name.trim().is_empty() {
+            return Err(ProfileError::NoName);
+        }
+        if self.steps.is_empty() {
+            return Err(ProfileError::NoSteps);
+        }
+
+        let steps = count_steps(&self.steps)?;
+        if steps > MAX_STEPS {
+            return Err(ProfileError::TooManySteps { steps });
+        }
+
+        Ok(())
+    }
+
+    /// The steps in the order they run.
+    pub fn flatten(&self) -> Vec<Step> {
+        let mut flat = Vec::new();
+        flatten_into(&self.steps, None, &mut flat);
+        flat
+    }
+}
+
+fn count_steps(steps: &[ProfileStep]) -> Result<usize, ProfileError> {
+    let mut total: usize = 0;
+
+    for step in steps {
+        let count = match step {
+            ProfileStep::Rest { duration_s: 0, .. } => return Err(ProfileError::ZeroDuration),
+            ProfileStep::Repeat { count: 0, .. } => return Err(ProfileError::ZeroCount),
+            ProfileStep::Repeat { steps, .. } if steps.is_empty() => {
+                return Err(ProfileError::EmptyRepeat)
+            }
+            ProfileStep::Repeat { count, steps } => {
+                count_steps(steps)?.saturating_mul(*count as usize)
+            }
+            _ => 1,
+        };
+        total = total.saturating_add(count);
+    }
+
+    Ok(total)
+}
+
+fn flatten_into(steps: &[ProfileStep], cycle: Option<u32>, flat: &mut Vec<Step>) {
+    let label = |label: &Option<String>, kind: &str| match (label, cycle) {
+        (Some(label), _) => label.clone(),
+        (None, Some(cycle)) => format!("Cycle {}", cycle),
+        (None, None) => kind.to_string(),
+    };
+
+    for step in steps {
+        match step {
+            ProfileStep::Charge(phase) => flat.push(Step {
+                state: BatteryState::Charge,
+                label: label(&phase.label, "Charge"),
+                limits: phase.limits.clone(),
+                rest: None,
+            }),
+            ProfileStep::Discharge(phase) => flat.push(Step {
+                state: BatteryState::Discharge,
+                label: label(&phase.label, "Discharge"),
+                limits: phase.limits.clone(),
+                rest: None,
+            }),
+            ProfileStep::Rest {
+                label: name,
+                duration_s,
+            } => flat.push(Step {
+                state: BatteryState::Standby,
+                label: label(name, "Rest"),
+                limits: StepLimits::default(),
+                rest: Some(Duration::from_secs(*duration_s as u64)),
+            }),
+            ProfileStep::Repeat { count, steps } => {
+                for iteration in 1..=*count {
+                    flatten_into(steps, Some(iteration), flat);
+                }
+            }
+        }
+    }
+}
+
+/// A profile as stored in the database.
+#[derive(Debug, Clone, PartialEq, Type, Serialize, Deserialize)]
+pub struct StoredProfile {
+    pub profile_id: i32,
+    pub created_at: String,
+    pub profile: TestProfile,
+}
+
+impl TryFrom<ProfileRecord> for StoredProfile {
+    type Error = ProfileError;
+
+    fn try_from(record: ProfileRecord) -> Result<Self, Self::Error> {
+        Ok(StoredProfile {
+            profile_id: record.profile_id.unwrap_or_default(),
+            created_at: record.created_at,
+            profile: TestProfile::parse(&record.definition, ProfileFormat::Json)?,
+        })
+    }
+}
+
+pub fn insert_profile(
+    conn: &mut SqliteConnection,
+    profile: &TestProfile,
+) -> Result<StoredProfile, String> {
+    use crate::database::schema::test_profiles::dsl::*;
+
+    profile.validate().map_err(|e| e.to_string())?;
+
+    let taken = test_profiles
+        .filter(name.eq(&profile.name))
+        .count()
+        .get_result::<i64>(conn)
+        .map_err(|e| e.to_string())?;
+    if taken > 0 {
+        return Err(format!(
+            "A profile named \"{}\" already exists",
+            profile.name
+        ));
+    }
+
+    let record = ProfileRecord {
+        profile_id: None,
+        name: profile.name.clone(),
+        definition: serde_json::to_string(profile).map_err(|e| e.to_string())?,
+        created_at: get_current_time(),
+    };
+    let inserted: ProfileRecord = diesel::insert_into(test_profiles)
+        .values(&record)
+        .get_result(conn)
+        .map_err(|e| e.to_string())?;
+
+    StoredProfile::try_from(inserted).map_err(|e| e.to_string())
+}
+
+pub fn load_profile(
+    conn: &mut SqliteConnection,
+    target_profile_id: i32,
+) -> Result<StoredProfile, String> {
+    use crate::database::schema::test_profiles::dsl::*;
+
+    let record: ProfileRecord = test_profiles
+        .filter(profile_id.eq(target_profile_id))
+        .first(conn)
+        .map_err(|e| format!("Failed to load profile {}: {}", target_profile_id, e))?;
+
+    StoredProfile::try_from(record).map_err(|e| e.to_string())
+}
+
+/// The profile a test was created with, or the qualification profile for
+/// tests that have none.
+pub fn profile_for_test(
+    conn: &mut SqliteConnection,
+    target_test_id: i32,
+) -> Result<TestProfile, String> {
+    use crate::database::schema::tests::dsl::*;
+
+    let test: Test = tests
+        .filter(test_id.eq(target_test_id))
+        .first(conn)
+        .map_err(|e| format!("Failed to load test {}: {}", target_test_id, e))?;
+
+    match test.profile_id {
+        Some(target_profile_id) => Ok(load_profile(conn, target_profile_id)?.profile),
+        None => Ok(TestProfile::qualification()),
+    }
+}
+
+#[tauri::command]
+#[specta::specta]
+pub fn get_profiles(state: State<'_, Mutex<AppState>>) -> Result<Vec<StoredProfile>, String> {
+    let mut state = state.lock().map_err(|e| e.to_string())?;
+    let conn = connection(&mut state).map_err(|e| e.to_string())?;
+
+    use crate::database::schema::test_profiles::dsl::*;
+    test_profiles
+        .order(name.asc())
+        .load::<ProfileRecord>(conn)
+        .map_err(|e| format!("Failed to load profiles: {}", e))?
+        .into_iter()
+        .map(|record| StoredProfile::try_from(record).map_err(|e| e.to_string()))
+        .collect()
+}
+
+/// Reads a profile from a `.json` or `.toml` file and stores it.
+#[tauri::command]
+#[specta::specta]
+pub fn import_profile(
+    state: State<'_, Mutex<AppState>>,
+    path: String,
+) -> Result<StoredProfile, String> {
+    let path = Path::new(&path);
+    let text = std::fs::read_to_string(path).map_err(|e| ProfileError::from(e).to_string())?;
+    let profile =
+        TestProfile::parse(&text, ProfileFormat::from_path(path)).map_err(|e| e.to_string())?;
+
+    let mut state = state.lock().map_err(|e| e.to_string())?;
+    let conn = connection(&mut state).map_err(|e| e.to_string())?;
+    insert_profile(conn, &profile)
+}
+
+/// Writes a stored profile to a `.json` or `.toml` file.
+#[tauri::command]
+#[specta::specta]
+pub fn export_profile(
+    state: State<'_, Mutex<AppState>>,
+    profile_id: i32,
+    path: String,
+) -> Result<(), String> {
+    let stored = {
+        let mut state = state.lock().map_err(|e| e.to_string())?;
+        let conn = connection(&mut state).map_err(|e| e.to_string())?;
+        load_profile(conn, profile_id)?
+    };
+
+    let path = Path::new(&path);
+    let text = stored
+        .profile
+        .to_string(ProfileFormat::from_path(path))
+        .map_err(|e| e.to_string())?;
+    std::fs::write(path, text).map_err(|e| ProfileError::from(e).to_string())
+}
+
+#[tauri::command]
+#[specta::specta]
+pub fn delete_profile(state: State<'_, Mutex<AppState>>, profile_id: i32) -> Result<(), String> {
+    let mut state = state.lock().map_err(|e| e.to_string())?;
+    let conn = connection(&mut state).map_err(|e| e.to_string())?;
+
+    use crate::database::schema::test_profiles::dsl;
+    diesel::delete(dsl::test_profiles.filter(dsl::profile_id.eq(profile_id)))
+        .execute(conn)
+        .map_err(|e| format!("Failed to delete profile {}: {}", profile_id, e))?;
+
+    Ok(())
+}
+
+/// Sets the profile a test runs, `None` for the qualification profile.
+#[tauri::command]
+#[specta::specta]
+pub fn set_test_profile(
+    state: State<'_, Mutex<AppState>>,
+    target_test_id: i32,
+    profile_id: Option<i32>,
+) -> Result<Test, String> {
+    let mut state = state.lock().map_err(|e| e.to_string())?;
+    let conn = connection(&mut state).map_err(|e| e.to_string())?;
+
+    use crate::database::schema::tests::dsl;
+    diesel::update(dsl::tests.filter(dsl::test_id.eq(target_test_id)))
+        .set(dsl::profile_id.eq(profile_id))
+        .get_result(conn)
+        .map_err(|e| format!("Failed to set profile of test {}: {}", target_test_id, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::sqlite::{establish_connection, MIGRATIONS};
+    use diesel_migrations::MigrationHarness;
+
+    const STORAGE_TOML: &str = r#"
+name = "LFP acceptance"
+description = "Five cycles with rests, then a storage charge"
+
+[[steps]]
+kind = "repeat"
+count = 5
+
+[[steps.steps]]
+kind = "charge"
+max_temperature_c = 45
+
+[[steps.steps]]
+kind = "rest"
+duration_s = 600
+
+[[steps.steps]]
+kind = "discharge"
+max_duration_s = 14400
+
+[[steps]]
+kind = "discharge"
+label = "Capacity check"
+
+[[steps]]
+kind = "charge"
+label = "Storage"
+until_voltage_mv = 3300
+"#;
+
+    #[test]
+    fn test_parse_toml_profile() {
+        let profile = TestProfile::parse(STORAGE_TOML, ProfileFormat::Toml).unwrap();
+        let steps = profile.flatten();
+
+        assert_eq!(steps.len(), 17);
+        assert_eq!(steps[0].state, BatteryState::Charge);
+        assert_eq!(steps[0].label, "Cycle 1");
+        assert_eq!(steps[0].limits.max_temperature_c, Some(45.0));
+        assert_eq!(steps[1].rest, Some(Duration::from_secs(600)));
+        assert_eq!(steps[14].label, "Cycle 5");
+        assert_eq!(steps[15].label, "Capacity check");
+        assert_eq!(steps[16].limits.until_voltage_mv, Some(3300));
+    }
+
+    #[test]
+    fn test_profile_round_trip() {
+        let profile = TestProfile::parse(STORAGE_TOML, ProfileFormat::Toml).unwrap();
+
+        for format in [ProfileFormat::Json, ProfileFormat::Toml] {
+            let text = profile.to_string(format).unwrap();
+            assert_eq!(TestProfile::parse(&text, format).unwrap(), profile);
+        }
+    }
+
+    #[test]
+    fn test_qualification_profile() {
+        let labels: Vec<_> = TestProfile::qualification()
+            .flatten()
+            .into_iter()
+            .map(|step| (step.state, step.label))
+            .collect();
+
+        assert_eq!(labels.len(), 7);
+        assert_eq!(labels[3], (BatteryState::Discharge, "Cycle 2".to_string()));
+        assert_eq!(labels[6], (BatteryState::Charge, "Top-up".to_string()));
+    }
+
+    #[test]
+    fn test_invalid_profiles() {
+        let parse = |json: &str| TestProfile::parse(json, ProfileFormat::Json);
+
+        assert!(matches!(
+            parse(r#"{"name": "x", "steps": []}"#),
+            Err(ProfileError::NoSteps)
+        ));
+        assert!(matches!(
+            parse(
+                r#"{"name": "x", "steps": [{"kind": "repeat", "count": 0, "steps": [{"kind": "charge"}]}]}"#
+            ),
+            Err(ProfileError::ZeroCount)
+        ));
+        assert!(matches!(
+            parse(r#"{"name": "x", "steps": [{"kind": "rest", "duration_s": 0}]}"#),
+            Err(ProfileError::ZeroDuration)
+        ));
+        assert!(matches!(
+            parse(
+                r#"{"name": "x", "steps": [{"kind": "repeat", "count": 4000, "steps": [{"kind": "charge"}]}]}"#
+            ),
+            Err(ProfileError::TooManySteps { steps: 4000 })
+        ));
+        assert!(matches!(
+            parse(r#"{"name": "x", "steps": [{"kind": "boil"}]}"#),
+            Err(ProfileError::Parse(_))
+        ));
+    }
+
+    #[test]
+    fn test_profile_for_test() {
+        let mut conn = establish_connection(":memory:").unwrap();
+        conn.run_pending_migrations(MIGRATIONS).unwrap();
+
+        let profile = TestProfile::parse(STORAGE_TOML, ProfileFormat::Toml).unwrap();
+        let stored = insert_profile(&mut conn, &profile).unwrap();
+        assert!(insert_profile(&mut conn, &profile).is_err());
+
+        for (id, profile_id) in [(1, None), (2, Some(stored.profile_id))] {
+            diesel::insert_into(crate::database::schema::tests::table)
+                .values(&Test {
+                    test_id: Some(id),
+                    test_name: format!("Test {}", id),
+                    start_date: get_current_time(),
+                    profile_id,
+                })
+                .execute(&mut conn)
+                .unwrap();
+        }
+
+        assert_eq!(
+            profile_for_test(&mut conn, 1).unwrap(),
+            TestProfile::qualification()
+        );
+        assert_eq!(profile_for_test(&mut conn, 2).unwrap(), profile);
+    }
+}
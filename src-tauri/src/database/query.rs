@@ -0,0 +1,463 @@
+use std::sync::Mutex;
+
+use diesel::prelude::*;
+use diesel::sqlite::Sqlite;
+use diesel::SqliteConnection;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tauri::State;
+
+use crate::database::models::BatteryLog;
+use crate::database::schema::battery_logs;
+use crate::database::sqlite::connection;
+use crate::state::AppState;
+
+/// Most rows a page can hold, whatever the caller asks for.
+pub const MAX_PAGE_SIZE: u32 = 10_000;
+
+/// Fewest points a downsampled battery keeps, so the first and last sample
+/// always fit.
+const MIN_POINTS: u32 = 3;
+
+/// Which samples of a test to read. Unset fields don't filter.
+#[derive(Debug, Clone, Type, Serialize, Deserialize)]
+pub struct LogFilter {
+    pub test_id: i32,
+    pub battery_ids: Option<Vec<i32>>,
+    /// Seconds since the start of the test, inclusive.
+    pub from_s: Option<f64>,
+    pub to_s: Option<f64>,
+    pub states: Option<Vec<String>>,
+    pub statuses: Option<Vec<String>>,
+}
+
+/// Where the next page starts: after this sample in time order.
+#[derive(Debug, Clone, Copy, PartialEq, Type, Serialize, Deserialize)]
+pub struct LogCursor {
+    pub elapsed_s: f64,
+    pub record_id: i32,
+}
+
+#[derive(Debug, Clone, Type, Serialize, Deserialize)]
+pub struct LogPage {
+    pub logs: Vec<BatteryLog>,
+    /// `None` on the last page.
+    pub next: Option<LogCursor>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Type, Serialize, Deserialize)]
+pub enum Downsampling {
+    /// The lowest and highest sample of each time bucket, which keeps spikes.
+    MinMax,
+    /// One sample per time bucket with every reading averaged.
+    Average,
+    /// Largest-Triangle-Three-Buckets, which keeps the shape of the curve.
+    Lttb,
+}
+
+/// The reading [`Downsampling::MinMax`] and [`Downsampling::Lttb`] pick
+/// samples by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Type, Serialize, Deserialize)]
+pub enum LogQuantity {
+    Voltage,
+    Current,
+    Load,
+    BatteryTemperature,
+    MosfetTemperature,
+    ResistorTemperature,
+}
+
+impl LogQuantity {
+    pub fn of(self, log: &BatteryLog) -> f64 {
+        (match self {
+            LogQuantity::Voltage => log.voltage,
+            LogQuantity::Current => log.current,
+            LogQuantity::Load => log.load,
+            LogQuantity::BatteryTemperature => log.battery_temperature,
+            LogQuantity::MosfetTemperature => log.bench_temperature_mosfet,
+            LogQuantity::ResistorTemperature => log.bench_temperature_resistor,
+        }) as f64
+    }
+}
+
+impl LogFilter {
+    pub fn test(test_id: i32) -> Self {
+        LogFilter {
+            test_id,
+            battery_ids: None,
+            from_s: None,
+            to_s: None,
+            states: None,
+            statuses: None,
+        }
+    }
+
+    /// The samples matching the filter, unordered.
+    pub fn query(&self) -> battery_logs::BoxedQuery<'static, Sqlite> {
+        use crate::database::schema::battery_logs::dsl;
+
+        let mut query = dsl::battery_logs
+            .filter(dsl::test_id.eq(self.test_id))
+            .into_boxed();
+        if let Some(battery_ids) = &self.battery_ids {
+            query = query.filter(dsl::id.eq_any(battery_ids.clone()));
+        }
+        if let Some(from_s) = self.from_s {
+            query = query.filter(dsl::elapsed_s.ge(from_s));
+        }
+        if let Some(to_s) = self.to_s {
+            query = query.filter(dsl::elapsed_s.le(to_s));
+        }
+        if let Some(states) = &self.states {
+            query = query.filter(dsl::state.eq_any(states.clone()));
+        }
+        if let Some(statuses) = &self.statuses {
+            query = query.filter(dsl::status.eq_any(statuses.clone()));
+        }
+        query
+    }
+}
+
+/// A page of at most `limit` samples in time order, starting after `after`.
+pub fn query_logs(
+    conn: &mut SqliteConnection,
+    filter: &LogFilter,
+    after: Option<LogCursor>,
+    limit: u32,
+) -> QueryResult<LogPage> {
+    use crate::database::schema::battery_logs::dsl;
+
+    let limit = limit.clamp(1, MAX_PAGE_SIZE);
+    let mut query = filter
+        .query()
+        .order((dsl::elapsed_s, dsl::record_id))
+        .limit(limit as i64);
+    if let Some(cursor) = after {
+        query = query.filter(
+            dsl::elapsed_s.gt(cursor.elapsed_s).or(dsl::elapsed_s
+                .eq(cursor.elapsed_s)
+                .and(dsl::record_id.gt(cursor.record_id))),
+        );
+    }
+
+    let logs = query.load::<BatteryLog>(conn)?;
+    let next = match logs.len() == limit as usize {
+        true => logs.last().map(|last| LogCursor {
+            elapsed_s: last.elapsed_s,
+            record_id: last.record_id.unwrap_or_default(),
+        }),
+        false => None,
+    };
+    Ok(LogPage { logs, next })
+}
+
+/// Splits `logs` into `buckets` runs of equal duration. Empty buckets are
+/// left out.
+fn time_buckets(logs: &[BatteryLog], buckets: usize) -> Vec<&[BatteryLog]> {
+    let (start, end) = (logs[0].elapsed_s, logs[logs.len() - 1].elapsed_s);
+    let width = (end - start) / buckets as f64;
+    let bucket_of = |log: &BatteryLog| match width > 0.0 {
+        true => (((log.elapsed_s - start) / width) as usize).min(buckets - 1),
+        false => 0,
+    };
+    logs.chunk_by(|a, b| bucket_of(a) == bucket_of(b)).collect()
+}
+
+fn min_max(logs: &[BatteryLog], points: usize, quantity: LogQuantity) -> Vec<BatteryLog> {
+    let mut kept = Vec::with_capacity(points);
+    for bucket in time_buckets(logs, (points / 2).max(1)) {
+        let by_value = |a: &&BatteryLog, b: &&BatteryLog| quantity.of(a).total_cmp(&quantity.of(b));
+        let (low, high) = (
+            bucket
+                .iter()
+                .enumerate()
+                .min_by(|a, b| by_value(&a.1, &b.1)),
+            bucket
+                .iter()
+                .enumerate()
+                .max_by(|a, b| by_value(&a.1, &b.1)),
+        );
+        if let (Some((low_index, low)), Some((high_index, high))) = (low, high) {
+            match low_index.cmp(&high_index) {
+                std::cmp::Ordering::Less => kept.extend([low.clone(), high.clone()]),
+                std::cmp::Ordering::Greater => kept.extend([high.clone(), low.clone()]),
+                std::cmp::Ordering::Equal => kept.push(low.clone()),
+            }
+        }
+    }
+    kept
+}
+
+fn average(logs: &[BatteryLog], points: usize) -> Vec<BatteryLog> {
+    time_buckets(logs, points)
+        .into_iter()
+        .map(|bucket| {
+            let count = bucket.len() as f64;
+            let mean = |value: fn(&BatteryLog) -> f32| {
+                (bucket.iter().map(|log| value(log) as f64).sum::<f64>() / count) as f32
+            };
+            // the first sample of the bucket names the phase and timestamp
+            BatteryLog {
+                battery_temperature: mean(|log| log.battery_temperature),
+                bench_temperature_mosfet: mean(|log| log.bench_temperature_mosfet),
+                bench_temperature_resistor: mean(|log| log.bench_temperature_resistor),
+                load: mean(|log| log.load),
+                voltage: mean(|log| log.voltage),
+                current: mean(|log| log.current),
+                elapsed_s: bucket.iter().map(|log| log.elapsed_s).sum::<f64>() / count,
+                ..bucket[0].clone()
+            }
+        })
+        .collect()
+}
+
+/// Largest-Triangle-Three-Buckets: keeps the first and last sample and, from
+/// each bucket in between, the one forming the largest triangle with the
+/// sample kept before it and the average of the next bucket.
+fn lttb(logs: &[BatteryLog], points: usize, quantity: LogQuantity) -> Vec<BatteryLog> {
+    let point = |log: &BatteryLog| (log.elapsed_s, quantity.of(log));
+    let every = (logs.len() - 2) as f64 / (points - 2) as f64;
+    let bucket = |index: usize| {
+        let start = (index as f64 * every) as usize + 1;
+        let end = (((index + 1) as f64 * every) as usize + 1).min(logs.len() - 1);
+        &logs[start..end.max(start + 1)]
+    };
+
+    let mut kept = Vec::with_capacity(points);
+    kept.push(logs[0].clone());
+    let mut previous = point(&logs[0]);
+    for index in 0..points - 2 {
+        let next = match index + 1 < points - 2 {
+            true => bucket(index + 1),
+            false => &logs[logs.len() - 1..],
+        };
+        let count = next.len() as f64;
+        let (next_x, next_y) = next.iter().map(point).fold((0.0, 0.0), |(x, y), (px, py)| {
+            (x + px / count, y + py / count)
+        });
+
+        let area = |log: &BatteryLog| {
+            let (x, y) = point(log);
+            ((previous.0 - next_x) * (y - previous.1) - (previous.0 - x) * (next_y - previous.1))
+                .abs()
+        };
+        let chosen = bucket(index)
+            .iter()
+            .max_by(|a, b| area(a).total_cmp(&area(b)))
+            .unwrap_or(&logs[0]);
+        previous = point(chosen);
+        kept.push(chosen.clone());
+    }
+    kept.push(logs[logs.len() - 1].clone());
+    kept
+}
+
+/// Reduces the samples of one battery, in time order, to about `points`.
+pub fn downsample(
+    logs: &[BatteryLog],
+    points: u32,
+    method: Downsampling,
+    quantity: LogQuantity,
+) -> Vec<BatteryLog> {
+    let points = points.max(MIN_POINTS) as usize;
+    if logs.len() <= points {
+        return logs.to_vec();
+    }
+    match method {
+        Downsampling::MinMax => min_max(logs, points, quantity),
+        Downsampling::Average => average(logs, points),
+        Downsampling::Lttb => lttb(logs, points, quantity),
+    }
+}
+
+/// The filtered samples with each battery downsampled to about `points`,
+/// grouped by battery and in time order.
+pub fn downsampled_logs(
+    conn: &mut SqliteConnection,
+    filter: &LogFilter,
+    points: u32,
+    method: Downsampling,
+    quantity: LogQuantity,
+) -> QueryResult<Vec<BatteryLog>> {
+    use crate::database::schema::battery_logs::dsl;
+
+    let logs = filter
+        .query()
+        .order((dsl::id, dsl::elapsed_s, dsl::record_id))
+        .load::<BatteryLog>(conn)?;
+    Ok(logs
+        .chunk_by(|a, b| a.id == b.id)
+        .flat_map(|battery| downsample(battery, points, method, quantity))
+        .collect())
+}
+
+/// A page of the filtered samples, for tables and exports that need every
+/// row without holding them all.
+#[tauri::command]
+#[specta::specta]
+pub fn query_battery_logs(
+    state: State<'_, Mutex<AppState>>,
+    filter: LogFilter,
+    after: Option<LogCursor>,
+    limit: u32,
+) -> Result<LogPage, String> {
+    let mut state = state.lock().map_err(|e| e.to_string())?;
+    let conn = connection(&mut state).map_err(|e| e.to_string())?;
+
+    query_logs(conn, &filter, after, limit)
+        .map_err(|e| format!("Failed to get logs for test {}: {}", filter.test_id, e))
+}
+
+/// The filtered samples reduced to about `points` per battery, for charts.
+#[tauri::command]
+#[specta::specta]
+pub fn get_downsampled_logs(
+    state: State<'_, Mutex<AppState>>,
+    filter: LogFilter,
+    points: u32,
+    method: Downsampling,
+    quantity: LogQuantity,
+) -> Result<Vec<BatteryLog>, String> {
+    let mut state = state.lock().map_err(|e| e.to_string())?;
+    let conn = connection(&mut state).map_err(|e| e.to_string())?;
+
+    downsampled_logs(conn, &filter, points, method, quantity)
+        .map_err(|e| format!("Failed to get logs for test {}: {}", filter.test_id, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::models::Test;
+    use crate::database::sqlite::{establish_connection, MIGRATIONS};
+    use diesel_migrations::MigrationHarness;
+
+    fn sample(id: i32, elapsed_s: f64, voltage: f32) -> BatteryLog {
+        BatteryLog {
+            record_id: None,
+            id,
+            port: "COM3".to_string(),
+            battery_temperature: 25.0,
+            bench_temperature_mosfet: 30.0,
+            bench_temperature_resistor: 30.0,
+            load: 3.0,
+            voltage,
+            current: -1250.0,
+            state: match elapsed_s < 500.0 {
+                true => "Charge".to_string(),
+                false => "Discharge".to_string(),
+            },
+            status: "Cycle 1".to_string(),
+            start_date: None,
+            end_date: None,
+            test_id: 1,
+            recorded_at: String::new(),
+            elapsed_s,
+        }
+    }
+
+    /// A slow ramp with one spike at 700 s.
+    fn ramp(id: i32) -> Vec<BatteryLog> {
+        (0..1000)
+            .map(|second| {
+                let voltage = match second {
+                    700 => 4500.0,
+                    _ => 3000.0 + second as f32,
+                };
+                sample(id, second as f64, voltage)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_query_logs() {
+        let mut conn = establish_connection(":memory:").unwrap();
+        conn.run_pending_migrations(MIGRATIONS).unwrap();
+        diesel::insert_into(crate::database::schema::tests::table)
+            .values(&Test {
+                test_id: Some(1),
+                test_name: "Test 1".to_string(),
+                start_date: String::new(),
+                profile_id: None,
+            })
+            .execute(&mut conn)
+            .unwrap();
+        for id in [2, 3] {
+            diesel::insert_into(crate::database::schema::battery_logs::table)
+                .values(&ramp(id))
+                .execute(&mut conn)
+                .unwrap();
+        }
+
+        // every sample exactly once, across pages that split same-time rows
+        let filter = LogFilter::test(1);
+        let (mut seen, mut after) = (0, None);
+        loop {
+            let page = query_logs(&mut conn, &filter, after, 333).unwrap();
+            seen += page.logs.len();
+            match page.next {
+                Some(next) => after = Some(next),
+                None => break,
+            }
+        }
+        assert_eq!(seen, 2000);
+
+        let filter = LogFilter {
+            battery_ids: Some(vec![3]),
+            from_s: Some(100.0),
+            to_s: Some(599.0),
+            states: Some(vec!["Discharge".to_string()]),
+            ..LogFilter::test(1)
+        };
+        let page = query_logs(&mut conn, &filter, None, 1000).unwrap();
+        assert_eq!(page.logs.len(), 100);
+        assert!(page.next.is_none());
+        assert!(page
+            .logs
+            .iter()
+            .all(|log| log.id == 3 && log.state == "Discharge"));
+
+        let logs = downsampled_logs(
+            &mut conn,
+            &LogFilter::test(1),
+            100,
+            Downsampling::Lttb,
+            LogQuantity::Voltage,
+        )
+        .unwrap();
+        assert_eq!(logs.len(), 200);
+        assert!(logs[..100].iter().all(|log| log.id == 2));
+    }
+
+    #[test]
+    fn test_downsample() {
+        let logs = ramp(2);
+        let spike = |kept: &[BatteryLog]| kept.iter().any(|log| log.voltage == 4500.0);
+
+        for method in [Downsampling::MinMax, Downsampling::Lttb] {
+            let kept = downsample(&logs, 50, method, LogQuantity::Voltage);
+            assert!(kept.len() <= 50, "{:?} kept {}", method, kept.len());
+            assert!(spike(&kept), "{:?} lost the spike", method);
+            assert!(kept
+                .windows(2)
+                .all(|pair| pair[0].elapsed_s < pair[1].elapsed_s));
+        }
+
+        let kept = downsample(&logs, 50, Downsampling::Lttb, LogQuantity::Voltage);
+        assert_eq!(kept.len(), 50);
+        assert_eq!(kept[0].elapsed_s, 0.0);
+        assert_eq!(kept[49].elapsed_s, 999.0);
+
+        let kept = downsample(&logs, 10, Downsampling::Average, LogQuantity::Voltage);
+        assert_eq!(kept.len(), 10);
+        // the first 100 s average to the middle of the ramp
+        assert!((kept[0].voltage - 3049.5).abs() < 0.01);
+        assert!(!spike(&kept));
+
+        // short series come back whole
+        assert_eq!(
+            downsample(&logs[..20], 50, Downsampling::MinMax, LogQuantity::Voltage).len(),
+            20
+        );
+    }
+}
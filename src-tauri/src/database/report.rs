@@ -0,0 +1,492 @@
+use std::fmt::Write;
+use std::sync::Mutex;
+
+use diesel::prelude::*;
+use diesel::SqliteConnection;
+use tauri::State;
+
+use crate::database::acceptance::{load_verdicts, BatteryVerdict};
+use crate::database::analysis::{analyze_test, test_logs, TestAnalysis};
+use crate::database::models::{AlarmRecord, BatteryLog, ProfileRecord, Test};
+use crate::database::sqlite::connection;
+use crate::serial::pilot::get_current_time;
+use crate::state::AppState;
+
+/// Points drawn per line. Longer series are decimated, which keeps a report
+/// of a week long test in the low megabytes.
+const MAX_PLOT_POINTS: usize = 800;
+
+const PLOT_WIDTH: f64 = 680.0;
+const PLOT_HEIGHT: f64 = 180.0;
+/// Room for the axis labels left and below the plot area.
+const MARGIN_LEFT: f64 = 56.0;
+const MARGIN_BOTTOM: f64 = 22.0;
+
+const STYLE: &str = "
+body { font-family: sans-serif; font-size: 13px; margin: 24px; color: #111; }
+h1 { font-size: 20px; } h2 { font-size: 16px; margin-top: 28px; } h3 { font-size: 14px; }
+table { border-collapse: collapse; margin: 8px 0; }
+th, td { border: 1px solid #bbb; padding: 3px 8px; text-align: right; }
+th { background: #eee; } td.text { text-align: left; }
+.Pass { color: #166534; font-weight: bold; } .Fail { color: #b91c1c; font-weight: bold; }
+.Inconclusive { color: #92400e; font-weight: bold; }
+svg { display: block; margin: 4px 0; } svg text { font-size: 10px; fill: #333; }
+.cell { page-break-before: always; }
+@media print { body { margin: 0; } .cell { break-before: page; } }
+";
+
+/// One line of a plot.
+struct Series<'a> {
+    name: &'a str,
+    color: &'a str,
+    values: Vec<(f64, f64)>,
+}
+
+/// Everything that goes into the report of a test.
+pub struct ReportData {
+    pub test: Test,
+    pub profile: Option<ProfileRecord>,
+    /// The samples, grouped by battery and in time order.
+    pub logs: Vec<BatteryLog>,
+    pub analysis: TestAnalysis,
+    pub alarms: Vec<AlarmRecord>,
+    pub verdicts: Vec<BatteryVerdict>,
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Every n-th value so at most [`MAX_PLOT_POINTS`] are left, keeping the last.
+fn decimate(values: Vec<(f64, f64)>) -> Vec<(f64, f64)> {
+    let stride = values.len().div_ceil(MAX_PLOT_POINTS).max(1);
+    if stride == 1 {
+        return values;
+    }
+    let last = values.last().copied();
+    let mut kept: Vec<(f64, f64)> = values.into_iter().step_by(stride).collect();
+    if kept.last().copied() != last {
+        kept.extend(last);
+    }
+    kept
+}
+
+/// A line chart of `series` against hours since the start of the test, as
+/// inline SVG.
+fn line_chart(title: &str, unit: &str, series: Vec<Series>) -> String {
+    let series: Vec<Series> = series
+        .into_iter()
+        .map(|line| Series {
+            values: decimate(line.values),
+            ..line
+        })
+        .collect();
+    let points = series.iter().flat_map(|line| line.values.iter());
+    let (mut x_max, mut y_min, mut y_max) = (0.0f64, f64::MAX, f64::MIN);
+    for &(x, y) in points {
+        x_max = x_max.max(x);
+        y_min = y_min.min(y);
+        y_max = y_max.max(y);
+    }
+    if y_min > y_max {
+        return format!("<p>{}: no samples</p>", escape(title));
+    }
+    if (y_max - y_min).abs() < f64::EPSILON {
+        (y_min, y_max) = (y_min - 1.0, y_max + 1.0);
+    }
+    let x_max = x_max.max(f64::EPSILON);
+
+    let area_width = PLOT_WIDTH - MARGIN_LEFT;
+    let area_height = PLOT_HEIGHT - MARGIN_BOTTOM;
+    let x_of = |x: f64| MARGIN_LEFT + x / x_max * area_width;
+    let y_of = |y: f64| (y_max - y) / (y_max - y_min) * area_height;
+
+    let mut svg = String::new();
+    let _ = write!(
+        svg,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" viewBox=\"0 0 {w} {h}\">\
+         <title>{title}</title>\
+         <rect x=\"{l}\" y=\"0\" width=\"{aw}\" height=\"{ah}\" fill=\"none\" stroke=\"#999\"/>\
+         <text x=\"{tx}\" y=\"10\" text-anchor=\"end\">{y_max:.1} {unit}</text>\
+         <text x=\"{tx}\" y=\"{ah}\" text-anchor=\"end\">{y_min:.1} {unit}</text>\
+         <text x=\"{l}\" y=\"{bottom}\">0 h</text>\
+         <text x=\"{w}\" y=\"{bottom}\" text-anchor=\"end\">{x_max:.2} h</text>",
+        w = PLOT_WIDTH,
+        h = PLOT_HEIGHT,
+        title = escape(title),
+        l = MARGIN_LEFT,
+        aw = area_width,
+        ah = area_height,
+        tx = MARGIN_LEFT - 4.0,
+        bottom = PLOT_HEIGHT - 6.0,
+        unit = escape(unit),
+    );
+    for (index, line) in series.iter().enumerate() {
+        let path: Vec<String> = line
+            .values
+            .iter()
+            .map(|&(x, y)| format!("{:.1},{:.1}", x_of(x), y_of(y)))
+            .collect();
+        let _ = write!(
+            svg,
+            "<polyline fill=\"none\" stroke=\"{color}\" stroke-width=\"1\" points=\"{points}\"/>\
+             <text x=\"{x}\" y=\"{y}\" fill=\"{color}\">{name}</text>",
+            color = line.color,
+            points = path.join(" "),
+            x = MARGIN_LEFT + 6.0 + 90.0 * index as f64,
+            y = area_height - 6.0,
+            name = escape(line.name),
+        );
+    }
+    svg.push_str("</svg>");
+    svg
+}
+
+fn hours(log: &BatteryLog) -> f64 {
+    log.elapsed_s / 3600.0
+}
+
+fn cell_plots(logs: &[BatteryLog]) -> String {
+    let series = |name, color, value: fn(&BatteryLog) -> f32| Series {
+        name,
+        color,
+        values: logs
+            .iter()
+            .map(|log| (hours(log), value(log) as f64))
+            .collect(),
+    };
+
+    [
+        line_chart(
+            "Voltage",
+            "mV",
+            vec![series("voltage", "#1d4ed8", |log| log.voltage)],
+        ),
+        line_chart(
+            "Current",
+            "mA",
+            vec![series("current", "#b45309", |log| log.current)],
+        ),
+        line_chart(
+            "Temperature",
+            "°C",
+            vec![
+                series("battery", "#b91c1c", |log| log.battery_temperature),
+                series("mosfet", "#7c3aed", |log| log.bench_temperature_mosfet),
+                series("resistor", "#047857", |log| log.bench_temperature_resistor),
+            ],
+        ),
+    ]
+    .concat()
+}
+
+fn phase_table(analysis: &TestAnalysis, battery_id: i32) -> String {
+    let mut html = String::from(
+        "<table><tr><th>#</th><th>Cycle</th><th>Phase</th><th>Step</th><th>Outcome</th>\
+         <th>Duration (h)</th><th>Samples</th><th>Capacity (mAh)</th><th>Energy (Wh)</th></tr>",
+    );
+    for phase in analysis
+        .phases
+        .iter()
+        .filter(|phase| phase.battery_id == battery_id)
+    {
+        let _ = write!(
+            html,
+            "<tr><td>{}</td><td>{}</td><td class=\"text\">{}</td><td class=\"text\">{}</td>\
+             <td class=\"text\">{}</td><td>{:.2}</td><td>{}</td><td>{:.0}</td><td>{:.2}</td></tr>",
+            phase.phase_index,
+            phase.cycle,
+            escape(&phase.state),
+            escape(&phase.label),
+            escape(phase.outcome.as_deref().unwrap_or("-")),
+            phase.duration_s / 3600.0,
+            phase.samples,
+            phase.charge_mah,
+            phase.energy_wh,
+        );
+    }
+    html.push_str("</table>");
+    html
+}
+
+fn verdict_section(verdict: Option<&BatteryVerdict>) -> String {
+    let Some(verdict) = verdict else {
+        return "<p>Not evaluated against acceptance criteria.</p>".to_string();
+    };
+    let label = format!("{:?}", verdict.verdict);
+    let mut html = format!(
+        "<p>Verdict: <span class=\"{label}\">{label}</span> (evaluated {})</p>",
+        escape(&verdict.evaluated_at)
+    );
+    if verdict.criteria.is_empty() {
+        return html;
+    }
+    html.push_str(
+        "<table><tr><th>Criterion</th><th>Measured</th><th>Threshold</th><th>Outcome</th>\
+         <th>Evidence</th></tr>",
+    );
+    for criterion in &verdict.criteria {
+        let _ = write!(
+            html,
+            "<tr><td class=\"text\">{}</td><td>{}</td><td>{:.1}</td>\
+             <td class=\"text {outcome}\">{outcome}</td><td class=\"text\">{}</td></tr>",
+            escape(&criterion.criterion),
+            criterion
+                .measured
+                .map_or("-".to_string(), |measured| format!("{:.1}", measured)),
+            criterion.threshold,
+            escape(&criterion.evidence),
+            outcome = escape(&criterion.outcome),
+        );
+    }
+    html.push_str("</table>");
+    html
+}
+
+fn alarm_table(alarms: &[AlarmRecord]) -> String {
+    if alarms.is_empty() {
+        return "<p>No alarms.</p>".to_string();
+    }
+    let mut html = String::from(
+        "<table><tr><th>Raised at</th><th>Battery</th><th>Port</th><th>Quantity</th>\
+         <th>Value</th><th>Threshold</th><th>State</th></tr>",
+    );
+    for alarm in alarms {
+        let _ = write!(
+            html,
+            "<tr><td class=\"text\">{}</td><td>{}</td><td class=\"text\">{}</td>\
+             <td class=\"text\">{}</td><td>{:.1}</td><td>{:.1}</td><td class=\"text\">{}</td></tr>",
+            escape(&alarm.raised_at),
+            alarm.battery_id,
+            escape(&alarm.port),
+            escape(&alarm.quantity),
+            alarm.value,
+            alarm.threshold,
+            escape(&alarm.state),
+        );
+    }
+    html.push_str("</table>");
+    html
+}
+
+/// Renders the report as a single HTML document without external resources,
+/// laid out so printing it gives one page per cell.
+pub fn render_report(data: &ReportData, generated_at: &str) -> String {
+    let name = escape(&data.test.test_name);
+    let mut html = format!(
+        "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><title>{name}</title>\
+         <style>{STYLE}</style></head><body><h1>Qualification report: {name}</h1>"
+    );
+
+    let cells: Vec<&[BatteryLog]> = data.logs.chunk_by(|a, b| a.id == b.id).collect();
+    let ports: Vec<&str> = {
+        let mut ports: Vec<&str> = data.logs.iter().map(|log| log.port.as_str()).collect();
+        ports.sort_unstable();
+        ports.dedup();
+        ports
+    };
+    let _ = write!(
+        html,
+        "<table><tr><th>Test</th><td class=\"text\">{}</td></tr>\
+         <tr><th>Started</th><td class=\"text\">{}</td></tr>\
+         <tr><th>Profile</th><td class=\"text\">{}</td></tr>\
+         <tr><th>Cells</th><td class=\"text\">{}</td></tr>\
+         <tr><th>Ports</th><td class=\"text\">{}</td></tr>\
+         <tr><th>Samples</th><td class=\"text\">{}</td></tr>\
+         <tr><th>Generated</th><td class=\"text\">{}</td></tr></table>",
+        data.test.test_id.unwrap_or_default(),
+        escape(&data.test.start_date),
+        data.profile
+            .as_ref()
+            .map_or("-".to_string(), |profile| escape(&profile.name)),
+        cells.len(),
+        escape(&ports.join(", ")),
+        data.logs.len(),
+        escape(generated_at),
+    );
+
+    html.push_str("<h2>Verdicts</h2>");
+    if data.verdicts.is_empty() {
+        html.push_str("<p>Not evaluated against acceptance criteria.</p>");
+    } else {
+        html.push_str("<table><tr><th>Battery</th><th>Verdict</th></tr>");
+        for verdict in &data.verdicts {
+            let _ = write!(
+                html,
+                "<tr><td>{}</td><td class=\"text {v}\">{v}</td></tr>",
+                verdict.battery_id,
+                v = format_args!("{:?}", verdict.verdict),
+            );
+        }
+        html.push_str("</table>");
+    }
+
+    html.push_str("<h2>Alarms</h2>");
+    html.push_str(&alarm_table(&data.alarms));
+
+    for logs in cells {
+        let battery_id = logs[0].id;
+        let _ = write!(
+            html,
+            "<section class=\"cell\"><h2>Battery {}</h2>",
+            battery_id
+        );
+        html.push_str(&verdict_section(
+            data.verdicts
+                .iter()
+                .find(|verdict| verdict.battery_id == battery_id),
+        ));
+        html.push_str(&cell_plots(logs));
+        html.push_str("<h3>Phases</h3>");
+        html.push_str(&phase_table(&data.analysis, battery_id));
+        html.push_str("</section>");
+    }
+
+    html.push_str("</body></html>");
+    html
+}
+
+/// Gathers the report of `target_test_id`, analyzing it again so the phase
+/// tables match the samples. Verdicts are the ones of the last evaluation.
+pub fn report_data(conn: &mut SqliteConnection, target_test_id: i32) -> QueryResult<ReportData> {
+    use crate::database::schema::{alarms, test_profiles, tests};
+
+    let test = tests::table
+        .filter(tests::test_id.eq(target_test_id))
+        .first::<Test>(conn)?;
+    let profile = match test.profile_id {
+        Some(profile_id) => test_profiles::table
+            .filter(test_profiles::profile_id.eq(profile_id))
+            .first::<ProfileRecord>(conn)
+            .optional()?,
+        None => None,
+    };
+    let analysis = analyze_test(conn, target_test_id)?;
+
+    Ok(ReportData {
+        test,
+        profile,
+        logs: test_logs(conn, target_test_id)?,
+        analysis,
+        alarms: alarms::table
+            .filter(alarms::test_id.eq(target_test_id))
+            .order(alarms::alarm_id)
+            .load::<AlarmRecord>(conn)?,
+        verdicts: load_verdicts(conn, target_test_id)?,
+    })
+}
+
+/// Writes the qualification report of a test to `file_path` as HTML. There is
+/// no PDF output; a PDF is had by printing the HTML from a browser.
+#[tauri::command]
+#[specta::specta]
+pub fn export_report(
+    state: State<'_, Mutex<AppState>>,
+    target_test_id: i32,
+    file_path: String,
+) -> Result<(), String> {
+    let data = {
+        let mut state = state.lock().map_err(|e| e.to_string())?;
+        let conn = connection(&mut state).map_err(|e| e.to_string())?;
+        report_data(conn, target_test_id)
+            .map_err(|e| format!("Failed to load test {}: {}", target_test_id, e))?
+    };
+
+    std::fs::write(&file_path, render_report(&data, &get_current_time()))
+        .map_err(|e| format!("Failed to write {}: {}", file_path, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::acceptance::Verdict;
+    use crate::database::analysis::battery_phases;
+    use crate::database::models::CriterionResult;
+
+    fn sample(id: i32, elapsed_s: f64, state: &str, current: f32) -> BatteryLog {
+        BatteryLog {
+            record_id: None,
+            id,
+            port: "COM3".to_string(),
+            battery_temperature: 25.0 + elapsed_s as f32 / 3600.0,
+            bench_temperature_mosfet: 30.0,
+            bench_temperature_resistor: 30.0,
+            load: 0.0,
+            voltage: 3700.0,
+            current,
+            state: state.to_string(),
+            status: "Cycle 1".to_string(),
+            start_date: None,
+            end_date: None,
+            test_id: 1,
+            recorded_at: format!("2025-07-31T08:{:02}:00+00:00", (elapsed_s / 60.0) as i32),
+            elapsed_s,
+        }
+    }
+
+    #[test]
+    fn test_decimate() {
+        let values: Vec<(f64, f64)> = (0..10_000).map(|x| (x as f64, 0.0)).collect();
+        let kept = decimate(values);
+        assert!(kept.len() <= MAX_PLOT_POINTS + 1);
+        assert_eq!(kept.first(), Some(&(0.0, 0.0)));
+        assert_eq!(kept.last(), Some(&(9999.0, 0.0)));
+    }
+
+    #[test]
+    fn test_render_report() {
+        let mut logs: Vec<BatteryLog> = (0..30)
+            .map(|minute| sample(1, minute as f64 * 60.0, "Charge", 1000.0))
+            .collect();
+        logs.extend((0..30).map(|minute| sample(2, minute as f64 * 60.0, "Discharge", -500.0)));
+        let phases = [
+            battery_phases(1, 1, &logs[..30], &[]),
+            battery_phases(1, 2, &logs[30..], &[]),
+        ]
+        .concat();
+
+        let data = ReportData {
+            test: Test {
+                test_id: Some(1),
+                test_name: "Cells <A&B>".to_string(),
+                start_date: "2025-07-31T08:00:00+00:00".to_string(),
+                profile_id: None,
+            },
+            profile: None,
+            logs,
+            analysis: TestAnalysis {
+                test_id: 1,
+                phases,
+                cycles: Vec::new(),
+            },
+            alarms: Vec::new(),
+            verdicts: vec![BatteryVerdict {
+                battery_id: 2,
+                verdict: Verdict::Fail,
+                evaluated_at: "2025-07-31T09:00:00+00:00".to_string(),
+                criteria: vec![CriterionResult {
+                    test_id: 1,
+                    battery_id: 2,
+                    criterion: "DischargeCapacity".to_string(),
+                    measured: Some(241.7),
+                    threshold: 400.0,
+                    outcome: "Fail".to_string(),
+                    evidence: "discharge of cycle 1 (Cycle 1)".to_string(),
+                }],
+            }],
+        };
+        let html = render_report(&data, "2025-07-31T10:00:00+00:00");
+
+        assert!(html.contains("Qualification report: Cells &lt;A&amp;B&gt;"));
+        // three plots per cell, inline
+        assert_eq!(html.matches("<svg").count(), 6);
+        assert!(!html.contains("src=\"http"));
+        assert_eq!(html.matches("<section class=\"cell\">").count(), 2);
+        assert!(html.contains("Not evaluated against acceptance criteria."));
+        assert!(html.contains("<td class=\"text Fail\">Fail</td>"));
+        assert!(html.contains("No alarms."));
+    }
+}
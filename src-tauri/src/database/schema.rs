@@ -1,21 +1,156 @@
 // @generated automatically by Diesel CLI.
 
+diesel::table! {
+    acceptance_criteria (test_id) {
+        test_id -> Integer,
+        min_discharge_capacity_mah -> Nullable<Float>,
+        max_capacity_fade_pct -> Nullable<Float>,
+        max_temperature_rise_c -> Nullable<Float>,
+        min_voltage_mv -> Nullable<Float>,
+        max_voltage_mv -> Nullable<Float>,
+        max_internal_resistance_mohm -> Nullable<Float>,
+    }
+}
+
+diesel::table! {
+    alarms (alarm_id) {
+        alarm_id -> Nullable<Integer>,
+        battery_id -> Integer,
+        port -> Text,
+        quantity -> Text,
+        value -> Float,
+        threshold -> Float,
+        state -> Text,
+        raised_at -> Text,
+        test_id -> Nullable<Integer>,
+    }
+}
+
+diesel::table! {
+    battery_ids (battery_id) {
+        battery_id -> Integer,
+        port -> Text,
+        assigned_at -> Text,
+    }
+}
+
 diesel::table! {
     battery_logs (record_id) {
         record_id -> Nullable<Integer>,
         id -> Integer,
         port -> Text,
-        battery_temperature -> Integer,
-        bench_temperature_mosfet -> Integer,
-        bench_temperature_resistor -> Integer,
-        load -> Integer,
-        voltage -> Integer,
-        current -> Integer,
+        battery_temperature -> Float,
+        bench_temperature_mosfet -> Float,
+        bench_temperature_resistor -> Float,
+        load -> Float,
+        voltage -> Float,
+        current -> Float,
         state -> Text,
         status -> Text,
         start_date -> Nullable<Text>,
         end_date -> Nullable<Text>,
         test_id -> Integer,
+        recorded_at -> Text,
+        elapsed_s -> Double,
+    }
+}
+
+diesel::table! {
+    cell_verdicts (test_id, battery_id) {
+        test_id -> Integer,
+        battery_id -> Integer,
+        verdict -> Text,
+        evaluated_at -> Text,
+    }
+}
+
+diesel::table! {
+    completion_events (record_id) {
+        record_id -> Nullable<Integer>,
+        battery_id -> Integer,
+        port -> Text,
+        phase -> Text,
+        outcome -> Text,
+        flags -> Integer,
+        received_at -> Text,
+        test_id -> Nullable<Integer>,
+    }
+}
+
+diesel::table! {
+    criterion_results (test_id, battery_id, criterion) {
+        test_id -> Integer,
+        battery_id -> Integer,
+        criterion -> Text,
+        measured -> Nullable<Double>,
+        threshold -> Double,
+        outcome -> Text,
+        evidence -> Text,
+    }
+}
+
+diesel::table! {
+    phase_results (test_id, battery_id, phase_index) {
+        test_id -> Integer,
+        battery_id -> Integer,
+        phase_index -> Integer,
+        cycle -> Integer,
+        state -> Text,
+        label -> Text,
+        outcome -> Nullable<Text>,
+        started_at -> Text,
+        ended_at -> Text,
+        duration_s -> Double,
+        samples -> Integer,
+        charge_mah -> Double,
+        energy_wh -> Double,
+        computed_at -> Text,
+    }
+}
+
+diesel::table! {
+    reserved_ids (battery_id) {
+        battery_id -> Integer,
+        cell_label -> Text,
+        test_id -> Nullable<Integer>,
+        reserved_at -> Text,
+    }
+}
+
+diesel::table! {
+    safety_limits (test_id) {
+        test_id -> Integer,
+        max_battery_temperature_c -> Nullable<Float>,
+        max_mosfet_temperature_c -> Nullable<Float>,
+        max_resistor_temperature_c -> Nullable<Float>,
+        min_voltage_mv -> Nullable<Float>,
+        max_voltage_mv -> Nullable<Float>,
+        max_current_ma -> Nullable<Float>,
+    }
+}
+
+diesel::table! {
+    sequence_states (test_id, battery_id) {
+        test_id -> Integer,
+        battery_id -> Integer,
+        port -> Text,
+        step -> Integer,
+        steps -> Integer,
+        step_label -> Text,
+        state -> Text,
+        outcome -> Text,
+        step_started_at -> Text,
+        charge_mah -> Float,
+        updated_at -> Text,
+    }
+}
+
+diesel::table! {
+    test_profiles (profile_id) {
+        profile_id -> Nullable<Integer>,
+        name -> Text,
+        definition -> Text,
+        created_at -> Text,
     }
 }
 
@@ -24,12 +159,34 @@ diesel::table! {
         test_id -> Nullable<Integer>,
         test_name -> Text,
         start_date -> Text,
+        profile_id -> Nullable<Integer>,
     }
 }
 
+diesel::joinable!(acceptance_criteria -> tests (test_id));
+diesel::joinable!(alarms -> tests (test_id));
 diesel::joinable!(battery_logs -> tests (test_id));
+diesel::joinable!(cell_verdicts -> tests (test_id));
+diesel::joinable!(completion_events -> tests (test_id));
+diesel::joinable!(criterion_results -> tests (test_id));
+diesel::joinable!(phase_results -> tests (test_id));
+diesel::joinable!(reserved_ids -> tests (test_id));
+diesel::joinable!(safety_limits -> tests (test_id));
+diesel::joinable!(sequence_states -> tests (test_id));
+diesel::joinable!(tests -> test_profiles (profile_id));
 
 diesel::allow_tables_to_appear_in_same_query!(
+    acceptance_criteria,
+    alarms,
+    battery_ids,
     battery_logs,
+    cell_verdicts,
+    completion_events,
+    criterion_results,
+    phase_results,
+    reserved_ids,
+    safety_limits,
+    sequence_states,
+    test_profiles,
     tests,
 );
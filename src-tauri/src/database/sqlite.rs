@@ -1,18 +1,30 @@
+use std::collections::HashSet;
 use std::fs;
 use std::sync::Mutex;
 use thiserror::Error;
 
+use diesel::connection::SimpleConnection;
 use diesel::prelude::*;
 use diesel::{Connection, SqliteConnection};
 use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
 use tauri::{Manager, State};
 
-use crate::database::models::{BatteryLog, Test};
+use crate::database::models::{
+    AlarmRecord, BatteryIdAssignment, BatteryLog, CompletionEvent, ReservedId, SafetyLimits,
+    SequenceState, Test,
+};
+use crate::database::writer::LogWriter;
 use crate::serial::pilot::get_current_time;
+use crate::serial::sequencer::{SequenceOutcome, TestClock};
+use crate::serial::serial::UNASSIGNED_ID;
 use crate::state::AppState;
 
 pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!("./migrations");
 
+/// How long a connection waits for another one's write to finish before
+/// giving up with "database is locked".
+const BUSY_TIMEOUT_MS: u32 = 5000;
+
 #[derive(Error, Debug)]
 pub enum DatabaseError {
     #[error("Failed to get app data directory: {0}")]
@@ -63,15 +75,8 @@ pub fn insert_battery_log(
 
     diesel::insert_into(crate::database::schema::battery_logs::table)
         .values(&log_data)
-        .execute(connection)
-        .map_err(|e| e.to_string())?;
-
-    let inserted_log = crate::database::schema::battery_logs::table
-        .order(crate::database::schema::battery_logs::record_id.desc())
-        .first(connection)
-        .map_err(|e| e.to_string())?;
-
-    Ok(inserted_log)
+        .get_result(connection)
+        .map_err(|e| e.to_string())
 }
 #[tauri::command]
 #[specta::specta]
@@ -109,6 +114,7 @@ pub fn insert_new_test(statee: State<'_, Mutex<AppState>>) -> Result<Test, Strin
         test_id: None,
         test_name: name,
         start_date: current_time,
+        profile_id: None,
     };
 
     insert_test(statee, test)
@@ -166,6 +172,7 @@ pub fn get_battery_logs_for_test(
 
     battery_logs
         .filter(test_id.eq(target_test_id))
+        .order((elapsed_s, record_id))
         .load::<BatteryLog>(conn)
         .map_err(|e| format!("Failed to get logs for test {}: {}", target_test_id, e))
 }
@@ -188,6 +195,383 @@ pub fn get_all_tests(state: State<'_, Mutex<AppState>>) -> Result<Vec<Test>, Str
         .map_err(|e| format!("Failed to load tests: {}", e))
 }
 
+#[tauri::command]
+#[specta::specta]
+pub fn get_battery_ids(
+    state: State<'_, Mutex<AppState>>,
+) -> Result<Vec<BatteryIdAssignment>, String> {
+    let mut state = state.lock().map_err(|e| e.to_string())?;
+    let conn = connection(&mut state).map_err(|e| e.to_string())?;
+
+    use crate::database::schema::battery_ids::dsl::*;
+    battery_ids
+        .order(battery_id)
+        .load::<BatteryIdAssignment>(conn)
+        .map_err(|e| format!("Failed to load battery IDs: {}", e))
+}
+
+#[tauri::command]
+#[specta::specta]
+pub fn get_reserved_ids(state: State<'_, Mutex<AppState>>) -> Result<Vec<ReservedId>, String> {
+    let mut state = state.lock().map_err(|e| e.to_string())?;
+    let conn = connection(&mut state).map_err(|e| e.to_string())?;
+
+    use crate::database::schema::reserved_ids::dsl::*;
+    reserved_ids
+        .order(battery_id)
+        .load::<ReservedId>(conn)
+        .map_err(|e| format!("Failed to load reserved IDs: {}", e))
+}
+
+#[tauri::command]
+#[specta::specta]
+pub fn reserve_battery_id(
+    state: State<'_, Mutex<AppState>>,
+    cell_label: String,
+    battery_id: u8,
+    test_id: Option<i32>,
+) -> Result<ReservedId, String> {
+    let mut state = state.lock().map_err(|e| e.to_string())?;
+    let conn = connection(&mut state).map_err(|e| e.to_string())?;
+
+    reserve_id(conn, &cell_label, battery_id, test_id)
+}
+
+#[tauri::command]
+#[specta::specta]
+pub fn release_reserved_id(
+    state: State<'_, Mutex<AppState>>,
+    battery_id: u8,
+) -> Result<(), String> {
+    let mut state = state.lock().map_err(|e| e.to_string())?;
+    let conn = connection(&mut state).map_err(|e| e.to_string())?;
+
+    use crate::database::schema::reserved_ids;
+    diesel::delete(reserved_ids::table.find(battery_id as i32))
+        .execute(conn)
+        .map_err(|e| format!("Failed to release battery ID {}: {}", battery_id, e))?;
+
+    Ok(())
+}
+
+/// Sets `battery_id` aside for the cell labelled `cell_label`, moving the
+/// label off any ID it had before.
+///
+/// Refuses IDs reserved for another label and IDs another cell already used,
+/// either through an assignment or in its logs.
+pub fn reserve_id(
+    conn: &mut SqliteConnection,
+    cell_label: &str,
+    battery_id: u8,
+    test_id: Option<i32>,
+) -> Result<ReservedId, String> {
+    use crate::database::schema::reserved_ids;
+
+    let cell_label = cell_label.trim();
+    if cell_label.is_empty() {
+        return Err("Cell label must not be empty".to_string());
+    }
+    if battery_id == UNASSIGNED_ID {
+        return Err(format!(
+            "Battery ID {} is used by benches without an ID",
+            UNASSIGNED_ID
+        ));
+    }
+
+    let holder = reserved_ids::table
+        .find(battery_id as i32)
+        .first::<ReservedId>(conn)
+        .optional()
+        .map_err(|e| e.to_string())?;
+
+    match holder {
+        Some(holder) if holder.cell_label != cell_label => {
+            return Err(format!(
+                "Battery ID {} is already reserved for {}",
+                battery_id, holder.cell_label
+            ))
+        }
+        Some(_) => {}
+        None => {
+            if used_battery_ids(conn)
+                .map_err(|e| e.to_string())?
+                .contains(&battery_id)
+            {
+                return Err(format!(
+                    "Battery ID {} is already used by another cell",
+                    battery_id
+                ));
+            }
+        }
+    }
+
+    diesel::delete(reserved_ids::table.filter(reserved_ids::cell_label.eq(cell_label)))
+        .execute(conn)
+        .map_err(|e| e.to_string())?;
+
+    let reserved = ReservedId {
+        battery_id: battery_id as i32,
+        cell_label: cell_label.to_string(),
+        test_id,
+        reserved_at: get_current_time(),
+    };
+    diesel::insert_into(reserved_ids::table)
+        .values(&reserved)
+        .execute(conn)
+        .map_err(|e| e.to_string())?;
+
+    Ok(reserved)
+}
+
+pub fn is_reserved(conn: &mut SqliteConnection, battery_id: u8) -> QueryResult<bool> {
+    use crate::database::schema::reserved_ids;
+
+    diesel::select(diesel::dsl::exists(
+        reserved_ids::table.find(battery_id as i32),
+    ))
+    .get_result(conn)
+}
+
+/// IDs automatic assignment must not hand out: used or reserved.
+pub fn taken_battery_ids(conn: &mut SqliteConnection) -> QueryResult<HashSet<u8>> {
+    use crate::database::schema::reserved_ids;
+
+    let mut taken = used_battery_ids(conn)?;
+    let reserved = reserved_ids::table
+        .select(reserved_ids::battery_id)
+        .load::<i32>(conn)?;
+    taken.extend(reserved.into_iter().filter_map(|id| u8::try_from(id).ok()));
+
+    Ok(taken)
+}
+
+/// Every ID that was ever assigned to a bench or appears in a log.
+pub fn used_battery_ids(conn: &mut SqliteConnection) -> QueryResult<HashSet<u8>> {
+    use crate::database::schema::{battery_ids, battery_logs};
+
+    let assigned = battery_ids::table
+        .select(battery_ids::battery_id)
+        .load::<i32>(conn)?;
+    let logged = battery_logs::table
+        .select(battery_logs::id)
+        .distinct()
+        .load::<i32>(conn)?;
+
+    Ok(assigned
+        .into_iter()
+        .chain(logged)
+        .filter_map(|id| u8::try_from(id).ok())
+        .collect())
+}
+
+pub fn record_battery_id(
+    conn: &mut SqliteConnection,
+    assignment: &BatteryIdAssignment,
+) -> QueryResult<()> {
+    diesel::replace_into(crate::database::schema::battery_ids::table)
+        .values(assignment)
+        .execute(conn)?;
+    Ok(())
+}
+
+#[tauri::command]
+#[specta::specta]
+pub fn get_completion_events(
+    state: State<'_, Mutex<AppState>>,
+    target_test_id: Option<i32>,
+) -> Result<Vec<CompletionEvent>, String> {
+    let mut state = state.lock().map_err(|e| e.to_string())?;
+    let conn = connection(&mut state).map_err(|e| e.to_string())?;
+
+    use crate::database::schema::completion_events::dsl::*;
+    let query = completion_events.order(record_id).into_boxed();
+    let query = match target_test_id {
+        Some(target) => query.filter(test_id.eq(target)),
+        None => query,
+    };
+    query
+        .load::<CompletionEvent>(conn)
+        .map_err(|e| format!("Failed to load completion events: {}", e))
+}
+
+pub fn insert_completion_event(
+    conn: &mut SqliteConnection,
+    event: &CompletionEvent,
+) -> QueryResult<()> {
+    diesel::insert_into(crate::database::schema::completion_events::table)
+        .values(event)
+        .execute(conn)?;
+    Ok(())
+}
+
+pub fn insert_alarm(conn: &mut SqliteConnection, alarm: &AlarmRecord) -> QueryResult<()> {
+    diesel::insert_into(crate::database::schema::alarms::table)
+        .values(alarm)
+        .execute(conn)?;
+    Ok(())
+}
+
+#[tauri::command]
+#[specta::specta]
+pub fn get_alarms(
+    state: State<'_, Mutex<AppState>>,
+    target_test_id: Option<i32>,
+) -> Result<Vec<AlarmRecord>, String> {
+    let mut state = state.lock().map_err(|e| e.to_string())?;
+    let conn = connection(&mut state).map_err(|e| e.to_string())?;
+
+    use crate::database::schema::alarms::dsl;
+    let query = dsl::alarms.order(dsl::alarm_id).into_boxed();
+    let query = match target_test_id {
+        Some(target) => query.filter(dsl::test_id.eq(target)),
+        None => query,
+    };
+    query
+        .load::<AlarmRecord>(conn)
+        .map_err(|e| format!("Failed to load alarms: {}", e))
+}
+
+/// The interlock limits of a test, or the defaults when it has none.
+pub fn limits_for_test(
+    conn: &mut SqliteConnection,
+    target_test_id: i32,
+) -> QueryResult<SafetyLimits> {
+    use crate::database::schema::safety_limits::dsl::*;
+    Ok(safety_limits
+        .filter(test_id.eq(target_test_id))
+        .first::<SafetyLimits>(conn)
+        .optional()?
+        .unwrap_or_else(|| SafetyLimits::defaults(target_test_id)))
+}
+
+#[tauri::command]
+#[specta::specta]
+pub fn get_safety_limits(
+    state: State<'_, Mutex<AppState>>,
+    target_test_id: i32,
+) -> Result<SafetyLimits, String> {
+    let mut state = state.lock().map_err(|e| e.to_string())?;
+    let conn = connection(&mut state).map_err(|e| e.to_string())?;
+
+    limits_for_test(conn, target_test_id)
+        .map_err(|e| format!("Failed to load limits of test {}: {}", target_test_id, e))
+}
+
+/// Sets the interlock limits of `limits.test_id`. Sequences already running
+/// keep the limits they started with.
+#[tauri::command]
+#[specta::specta]
+pub fn set_safety_limits(
+    state: State<'_, Mutex<AppState>>,
+    limits: SafetyLimits,
+) -> Result<SafetyLimits, String> {
+    let mut state = state.lock().map_err(|e| e.to_string())?;
+    let conn = connection(&mut state).map_err(|e| e.to_string())?;
+
+    diesel::replace_into(crate::database::schema::safety_limits::table)
+        .values(&limits)
+        .execute(conn)
+        .map_err(|e| format!("Failed to set limits of test {}: {}", limits.test_id, e))?;
+
+    Ok(limits)
+}
+
+pub fn save_sequence_states(
+    conn: &mut SqliteConnection,
+    states: &[SequenceState],
+) -> QueryResult<()> {
+    diesel::replace_into(crate::database::schema::sequence_states::table)
+        .values(states)
+        .execute(conn)?;
+    Ok(())
+}
+
+/// Forgets where the batteries of a test were, before it starts over.
+pub fn clear_sequence_states(conn: &mut SqliteConnection, target_test_id: i32) -> QueryResult<()> {
+    use crate::database::schema::sequence_states::dsl;
+    diesel::delete(dsl::sequence_states.filter(dsl::test_id.eq(target_test_id))).execute(conn)?;
+    Ok(())
+}
+
+pub fn sequence_states_for(
+    conn: &mut SqliteConnection,
+    target_test_id: i32,
+    target_port: &str,
+) -> QueryResult<Vec<SequenceState>> {
+    use crate::database::schema::sequence_states::dsl;
+    dsl::sequence_states
+        .filter(dsl::test_id.eq(target_test_id))
+        .filter(dsl::port.eq(target_port))
+        .order(dsl::battery_id)
+        .load(conn)
+}
+
+/// Batteries whose sequence never finished, by port and test.
+pub fn interrupted_sequence_states(conn: &mut SqliteConnection) -> QueryResult<Vec<SequenceState>> {
+    use crate::database::schema::sequence_states::dsl;
+    dsl::sequence_states
+        .filter(dsl::outcome.eq(format!("{:?}", SequenceOutcome::Running)))
+        .order((dsl::port, dsl::test_id, dsl::battery_id))
+        .load(conn)
+}
+
+/// Marks the batteries of an interrupted sequence as stopped.
+pub fn stop_sequence_states(
+    conn: &mut SqliteConnection,
+    target_test_id: i32,
+    target_port: &str,
+) -> QueryResult<usize> {
+    use crate::database::schema::sequence_states::dsl;
+    diesel::update(
+        dsl::sequence_states
+            .filter(dsl::test_id.eq(target_test_id))
+            .filter(dsl::port.eq(target_port))
+            .filter(dsl::outcome.eq(format!("{:?}", SequenceOutcome::Running))),
+    )
+    .set((
+        dsl::outcome.eq(format!("{:?}", SequenceOutcome::Stopped)),
+        dsl::updated_at.eq(get_current_time()),
+    ))
+    .execute(conn)
+}
+
+/// The clock samples of `target_test_id` are timed with, carrying on from the
+/// test's start date and its latest sample.
+pub fn clock_for_test(conn: &mut SqliteConnection, target_test_id: i32) -> QueryResult<TestClock> {
+    use crate::database::schema::{battery_logs, tests};
+
+    let started_at = tests::table
+        .filter(tests::test_id.eq(target_test_id))
+        .select(tests::start_date)
+        .first::<String>(conn)
+        .optional()?;
+    let last_elapsed_s = battery_logs::table
+        .filter(battery_logs::test_id.eq(target_test_id))
+        .select(diesel::dsl::max(battery_logs::elapsed_s))
+        .first::<Option<f64>>(conn)?;
+
+    Ok(match started_at {
+        Some(started_at) => TestClock::resume(&started_at, last_elapsed_s),
+        None => TestClock::starting_now(),
+    })
+}
+
+/// The open database connection, established on first use.
+pub fn connection(state: &mut AppState) -> Result<&mut SqliteConnection, DatabaseError> {
+    if state.db_connection.is_none() {
+        state.db_connection = Some(establish_connection(&state.db_path)?);
+    }
+    Ok(state.db_connection.as_mut().unwrap())
+}
+
+/// A connection of its own, for exports and imports that take long enough to
+/// hold up acquisition if they kept the app's lock. The lock is only held to
+/// read the database path.
+pub fn own_connection(state: &Mutex<AppState>) -> Result<SqliteConnection, String> {
+    let db_path = state.lock().map_err(|e| e.to_string())?.db_path.clone();
+    establish_connection(&db_path).map_err(|e| e.to_string())
+}
+
 pub fn init_database(app_handle: &tauri::AppHandle) -> Result<(), DatabaseError> {
     // Get app data directory
     let app_dir = app_handle
@@ -211,17 +595,324 @@ pub fn init_database(app_handle: &tauri::AppHandle) -> Result<(), DatabaseError>
     let mut state = state.lock().unwrap();
     state.db_path = db_path_str.to_string();
 
-    let mut connection = establish_connection(&db_path_str)?;
+    let mut connection = establish_connection(db_path_str)?;
 
-    connection
-        .run_pending_migrations(MIGRATIONS)
-        .map_err(DatabaseError::Migration)?;
+    without_foreign_keys(&mut connection, |conn| {
+        conn.run_pending_migrations(MIGRATIONS).map(|_| ())
+    })?
+    .map_err(DatabaseError::Migration)?;
 
     state.db_connection = Some(connection);
+    state.log_writer = Some(LogWriter::spawn(
+        db_path_str.to_string(),
+        LogWriter::journal_path(&db_path),
+    ));
 
     Ok(())
 }
 
+/// Opens the database in WAL mode, so reads don't wait on the logger, with
+/// foreign keys enforced. SQLite leaves both off on every new connection.
+/// Writes from the log writer and the app's connection take turns instead of
+/// failing on each other.
 pub fn establish_connection(db_path_str: &str) -> Result<SqliteConnection, DatabaseError> {
-    SqliteConnection::establish(db_path_str).map_err(DatabaseError::Connection)
+    let mut connection =
+        SqliteConnection::establish(db_path_str).map_err(DatabaseError::Connection)?;
+    connection
+        .batch_execute(&format!(
+            "PRAGMA busy_timeout = {}; PRAGMA journal_mode = WAL; PRAGMA foreign_keys = ON;",
+            BUSY_TIMEOUT_MS
+        ))
+        .map_err(DatabaseError::Operation)?;
+    Ok(connection)
+}
+
+/// Runs `migrate` with foreign keys off. Down migrations rebuild `tests`,
+/// and dropping it with them on would cascade to every sample. SQLite
+/// ignores the pragma inside a transaction, so it's set around diesel's.
+pub fn without_foreign_keys<T>(
+    conn: &mut SqliteConnection,
+    migrate: impl FnOnce(&mut SqliteConnection) -> T,
+) -> Result<T, DatabaseError> {
+    conn.batch_execute("PRAGMA foreign_keys = OFF;")
+        .map_err(DatabaseError::Operation)?;
+    // back on whether `migrate` returns or panics
+    let migrated = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| migrate(conn)));
+    let restored = conn
+        .batch_execute("PRAGMA foreign_keys = ON;")
+        .map_err(DatabaseError::Operation);
+    match migrated {
+        Ok(migrated) => restored.map(|_| migrated),
+        Err(panic) => std::panic::resume_unwind(panic),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn memory_connection() -> SqliteConnection {
+        let mut conn = establish_connection(":memory:").unwrap();
+        conn.run_pending_migrations(MIGRATIONS).unwrap();
+        conn
+    }
+
+    #[test]
+    fn test_migrations() {
+        let path = std::env::temp_dir().join(format!("migrations_{}.db", std::process::id()));
+        let path = path.to_str().unwrap();
+        let mut conn = establish_connection(path).unwrap();
+
+        conn.run_pending_migrations(MIGRATIONS).unwrap();
+        assert!(!conn.has_pending_migration(MIGRATIONS).unwrap());
+
+        diesel::insert_into(crate::database::schema::tests::table)
+            .values(&Test {
+                test_id: Some(1),
+                test_name: "Test 1".to_string(),
+                start_date: get_current_time(),
+                profile_id: None,
+            })
+            .execute(&mut conn)
+            .unwrap();
+        let log = crate::serial::simulator::CellModel::new(2500.0).sample(1, "COM3", 1);
+        diesel::insert_into(crate::database::schema::battery_logs::table)
+            .values(&log)
+            .execute(&mut conn)
+            .unwrap();
+        insert_completion_event(
+            &mut conn,
+            &CompletionEvent {
+                record_id: None,
+                battery_id: 1,
+                port: "COM3".to_string(),
+                phase: "Charge".to_string(),
+                outcome: "Success".to_string(),
+                flags: 0,
+                received_at: get_current_time(),
+                test_id: Some(1),
+            },
+        )
+        .unwrap();
+
+        // down to before test profiles, whose down migration rebuilds `tests`
+        without_foreign_keys(&mut conn, |conn| loop {
+            let reverted = conn.revert_last_migration(MIGRATIONS).unwrap();
+            if reverted.to_string() == "20250724090000" {
+                break;
+            }
+        })
+        .unwrap();
+        let count = |conn: &mut SqliteConnection, table: &str| {
+            let count = format!("(SELECT COUNT(*) FROM {})", table);
+            diesel::select(diesel::dsl::sql::<diesel::sql_types::BigInt>(&count))
+                .get_result::<i64>(conn)
+                .unwrap()
+        };
+        for table in ["tests", "battery_logs", "completion_events"] {
+            assert_eq!(count(&mut conn, table), 1, "{}", table);
+        }
+
+        without_foreign_keys(&mut conn, |conn| {
+            conn.revert_all_migrations(MIGRATIONS).map(|_| ())
+        })
+        .unwrap()
+        .unwrap();
+        // only diesel's own bookkeeping is left
+        let tables = diesel::dsl::sql::<diesel::sql_types::Text>(
+            "(SELECT COALESCE(GROUP_CONCAT(name), '') FROM sqlite_master \
+             WHERE name NOT LIKE 'sqlite_%' AND name != '__diesel_schema_migrations')",
+        );
+        let tables = diesel::select(tables)
+            .get_result::<String>(&mut conn)
+            .unwrap();
+        assert_eq!(tables, "");
+        without_foreign_keys(&mut conn, |conn| {
+            conn.run_pending_migrations(MIGRATIONS).map(|_| ())
+        })
+        .unwrap()
+        .unwrap();
+
+        let journal_mode = diesel::dsl::sql::<diesel::sql_types::Text>(
+            "(SELECT journal_mode FROM pragma_journal_mode())",
+        );
+        assert_eq!(
+            diesel::select(journal_mode)
+                .get_result::<String>(&mut conn)
+                .unwrap(),
+            "wal"
+        );
+
+        // deleting a test takes its samples along
+        diesel::insert_into(crate::database::schema::tests::table)
+            .values(&Test {
+                test_id: Some(1),
+                test_name: "Test 1".to_string(),
+                start_date: get_current_time(),
+                profile_id: None,
+            })
+            .execute(&mut conn)
+            .unwrap();
+        let log = crate::serial::simulator::CellModel::new(2500.0).sample(1, "COM3", 1);
+        diesel::insert_into(crate::database::schema::battery_logs::table)
+            .values(&log)
+            .execute(&mut conn)
+            .unwrap();
+        diesel::delete(crate::database::schema::tests::table)
+            .execute(&mut conn)
+            .unwrap();
+        let left = crate::database::schema::battery_logs::table
+            .count()
+            .get_result::<i64>(&mut conn)
+            .unwrap();
+        assert_eq!(left, 0);
+
+        drop(conn);
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = fs::remove_file(format!("{}{}", path, suffix));
+        }
+    }
+
+    #[test]
+    fn test_concurrent_writers() {
+        let path = std::env::temp_dir().join(format!("writers_{}.db", std::process::id()));
+        let path = path.to_str().unwrap().to_string();
+        let mut conn = establish_connection(&path).unwrap();
+        conn.run_pending_migrations(MIGRATIONS).unwrap();
+        diesel::insert_into(crate::database::schema::tests::table)
+            .values(&Test {
+                test_id: Some(1),
+                test_name: "Test 1".to_string(),
+                start_date: get_current_time(),
+                profile_id: None,
+            })
+            .execute(&mut conn)
+            .unwrap();
+
+        // one connection per thread, like the app's and the log writer's
+        let writers: Vec<_> = (0..2)
+            .map(|battery_id| {
+                let path = path.clone();
+                std::thread::spawn(move || {
+                    let mut conn = establish_connection(&path).unwrap();
+                    let cell = crate::serial::simulator::CellModel::new(2500.0);
+                    for _ in 0..200 {
+                        diesel::insert_into(crate::database::schema::battery_logs::table)
+                            .values(&cell.sample(battery_id, "COM3", 1))
+                            .execute(&mut conn)
+                            .unwrap();
+                    }
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+
+        let stored = crate::database::schema::battery_logs::table
+            .count()
+            .get_result::<i64>(&mut conn)
+            .unwrap();
+        assert_eq!(stored, 400);
+
+        drop(conn);
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = fs::remove_file(format!("{}{}", path, suffix));
+        }
+    }
+
+    #[test]
+    fn test_reserve_id() {
+        let mut conn = memory_connection();
+        let earlier = BatteryIdAssignment {
+            battery_id: 7,
+            port: "COM3".to_string(),
+            assigned_at: get_current_time(),
+        };
+        record_battery_id(&mut conn, &earlier).unwrap();
+
+        let reserved = reserve_id(&mut conn, " cell A ", 3, None).unwrap();
+        assert_eq!(reserved.cell_label, "cell A");
+        assert!(is_reserved(&mut conn, 3).unwrap());
+
+        // same label again is fine, another label is not
+        assert!(reserve_id(&mut conn, "cell A", 3, None).is_ok());
+        assert!(reserve_id(&mut conn, "cell B", 3, None).is_err());
+
+        // used by another cell, or the unassigned marker
+        assert!(reserve_id(&mut conn, "cell B", 7, None).is_err());
+        assert!(reserve_id(&mut conn, "cell B", UNASSIGNED_ID, None).is_err());
+        assert!(reserve_id(&mut conn, "  ", 4, None).is_err());
+
+        // moving a label frees its old ID
+        reserve_id(&mut conn, "cell A", 4, None).unwrap();
+        assert!(!is_reserved(&mut conn, 3).unwrap());
+
+        let taken = taken_battery_ids(&mut conn).unwrap();
+        assert_eq!(taken, HashSet::from([4, 7]));
+    }
+
+    #[test]
+    fn test_limits_for_test() {
+        let mut conn = memory_connection();
+        diesel::insert_into(crate::database::schema::tests::table)
+            .values(&Test {
+                test_id: Some(1),
+                test_name: "Test 1".to_string(),
+                start_date: get_current_time(),
+                profile_id: None,
+            })
+            .execute(&mut conn)
+            .unwrap();
+
+        let defaults = limits_for_test(&mut conn, 1).unwrap();
+        assert!(defaults.max_battery_temperature_c.is_some());
+        assert_eq!(defaults.min_voltage_mv, None);
+
+        let limits = SafetyLimits {
+            max_battery_temperature_c: Some(45.0),
+            min_voltage_mv: Some(2800.0),
+            ..defaults
+        };
+        diesel::replace_into(crate::database::schema::safety_limits::table)
+            .values(&limits)
+            .execute(&mut conn)
+            .unwrap();
+
+        let stored = limits_for_test(&mut conn, 1).unwrap();
+        assert_eq!(stored.max_battery_temperature_c, Some(45.0));
+        assert_eq!(stored.min_voltage_mv, Some(2800.0));
+    }
+
+    #[test]
+    fn test_clock_for_test() {
+        let mut conn = memory_connection();
+        let an_hour_ago = chrono::Utc::now() - chrono::Duration::hours(1);
+        diesel::insert_into(crate::database::schema::tests::table)
+            .values(&Test {
+                test_id: Some(1),
+                test_name: "Test 1".to_string(),
+                start_date: an_hour_ago.to_rfc3339(),
+                profile_id: None,
+            })
+            .execute(&mut conn)
+            .unwrap();
+
+        let elapsed = clock_for_test(&mut conn, 1).unwrap().elapsed_s();
+        assert!((3599.0..3700.0).contains(&elapsed), "{}", elapsed);
+
+        // the wall clock went back since the last sample
+        let log = crate::serial::simulator::CellModel::new(2500.0).sample(1, "COM3", 1);
+        diesel::insert_into(crate::database::schema::battery_logs::table)
+            .values(&BatteryLog {
+                elapsed_s: 7200.0,
+                ..log
+            })
+            .execute(&mut conn)
+            .unwrap();
+        assert!(clock_for_test(&mut conn, 1).unwrap().elapsed_s() >= 7200.0);
+
+        // unknown tests start from zero
+        assert!(clock_for_test(&mut conn, 2).unwrap().elapsed_s() < 60.0);
+    }
 }
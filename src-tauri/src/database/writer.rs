@@ -0,0 +1,446 @@
+use std::{
+    fs::{self, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{self, Receiver, RecvTimeoutError, SyncSender, TrySendError},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use diesel::prelude::*;
+use diesel::result::{DatabaseErrorKind, Error as DieselError};
+use diesel::SqliteConnection;
+
+use crate::database::models::{BatteryLog, SequenceState};
+use crate::database::sqlite::{establish_connection, save_sequence_states};
+
+/// Samples waiting for the writer before new ones go to the journal instead.
+const QUEUE_CAPACITY: usize = 4096;
+
+/// Most samples stored in one transaction.
+const BATCH_SIZE: usize = 512;
+
+/// Longest a sample waits for its batch to fill up.
+const FLUSH_INTERVAL: Duration = Duration::from_millis(500);
+
+/// How often journaled samples are retried while the database fails.
+const RETRY_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How long [`LogWriter::flush`] waits between attempts to queue its request
+/// while the queue is full.
+const FLUSH_RETRY: Duration = Duration::from_millis(10);
+
+enum Message {
+    Log(BatteryLog),
+    /// Where the batteries of a sequence are. Only the latest matters.
+    Progress(Vec<SequenceState>),
+    /// Acknowledged once everything sent before it is stored or journaled.
+    Flush(mpsc::Sender<()>),
+}
+
+/// Samples that could not be stored yet, one JSON object per line.
+struct Journal {
+    path: PathBuf,
+    lock: Mutex<()>,
+}
+
+impl Journal {
+    fn append(&self, logs: &[BatteryLog]) -> std::io::Result<()> {
+        let mut lines = String::new();
+        for log in logs {
+            lines.push_str(&serde_json::to_string(log)?);
+            lines.push('\n');
+        }
+
+        let _guard = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?
+            .write_all(lines.as_bytes())
+    }
+
+    fn has_logs(&self) -> bool {
+        self.path.metadata().is_ok_and(|meta| meta.len() > 0)
+    }
+
+    /// Empties the journal, returning what it held. Lines that don't parse
+    /// are dropped.
+    fn take(&self) -> std::io::Result<Vec<BatteryLog>> {
+        let _guard = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+        let file = match fs::File::open(&self.path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+
+        let mut logs = Vec::new();
+        for line in BufReader::new(file).lines() {
+            match serde_json::from_str(&line?) {
+                Ok(log) => logs.push(log),
+                Err(error) => println!("Dropping unreadable journaled sample: {}", error),
+            }
+        }
+        fs::remove_file(&self.path)?;
+        Ok(logs)
+    }
+}
+
+/// Stores samples and sequence progress from a thread of its own, so
+/// acquisition never waits on the database. Samples are written in batches,
+/// one transaction each. When the queue is full or the database fails they go
+/// to a journal file next to it, which is replayed once the database works
+/// again.
+#[derive(Clone)]
+pub struct LogWriter {
+    sender: SyncSender<Message>,
+    journal: Arc<Journal>,
+}
+
+impl LogWriter {
+    /// Starts the writer on the database at `db_path`, which must already be
+    /// migrated, journaling to `journal_path`.
+    pub fn spawn(db_path: String, journal_path: PathBuf) -> Self {
+        let (sender, receiver) = mpsc::sync_channel(QUEUE_CAPACITY);
+        let journal = Arc::new(Journal {
+            path: journal_path,
+            lock: Mutex::new(()),
+        });
+
+        let writer_journal = journal.clone();
+        thread::Builder::new()
+            .name("log-writer".to_string())
+            .spawn(move || run(&db_path, receiver, &writer_journal))
+            .expect("Failed to start the log writer");
+
+        LogWriter { sender, journal }
+    }
+
+    /// The journal used next to the database at `db_path`.
+    pub fn journal_path(db_path: &Path) -> PathBuf {
+        db_path.with_extension("journal")
+    }
+
+    /// Queues a sample. Never blocks on the database: a sample that doesn't
+    /// fit in the queue is journaled right away.
+    pub fn submit(&self, log: BatteryLog) {
+        let log = match self.sender.try_send(Message::Log(log)) {
+            Ok(()) => return,
+            Err(TrySendError::Full(Message::Log(log)))
+            | Err(TrySendError::Disconnected(Message::Log(log))) => log,
+            Err(_) => return,
+        };
+        if let Err(error) = self.journal.append(&[log]) {
+            println!("Failed to journal battery log: {}", error);
+        }
+    }
+
+    /// Queues the progress of a sequence. Never blocks: when the queue is
+    /// full it is dropped, the next progress replaces it anyway.
+    pub fn save_progress(&self, states: Vec<SequenceState>) {
+        if self.sender.try_send(Message::Progress(states)).is_err() {
+            println!("Log writer busy, skipping a sequence progress save");
+        }
+    }
+
+    /// Waits up to `timeout` for the samples queued so far to be stored or
+    /// journaled. Returns whether they were. Blocks, so async code runs it
+    /// with `spawn_blocking`.
+    pub fn flush(&self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        let (ack, done) = mpsc::channel();
+        let mut request = Message::Flush(ack);
+        loop {
+            match self.sender.try_send(request) {
+                Ok(()) => break,
+                Err(TrySendError::Full(message)) if Instant::now() < deadline => {
+                    request = message;
+                    thread::sleep(FLUSH_RETRY);
+                }
+                Err(_) => return false,
+            }
+        }
+        done.recv_timeout(deadline.saturating_duration_since(Instant::now()))
+            .is_ok()
+    }
+}
+
+/// Constraint failures only concern the rows that caused them; anything else
+/// means the database can't be written to right now.
+fn is_row_error(error: &DieselError) -> bool {
+    matches!(
+        error,
+        DieselError::DatabaseError(
+            DatabaseErrorKind::ForeignKeyViolation
+                | DatabaseErrorKind::NotNullViolation
+                | DatabaseErrorKind::UniqueViolation
+                | DatabaseErrorKind::CheckViolation,
+            _
+        )
+    )
+}
+
+/// Inserts `logs` in one transaction. When some rows are refused, the others
+/// are inserted one by one and the refused ones dropped. Returns how many
+/// were stored.
+pub fn insert_logs(conn: &mut SqliteConnection, logs: &[BatteryLog]) -> QueryResult<usize> {
+    use crate::database::schema::battery_logs;
+
+    let batch = conn.transaction(|conn| {
+        diesel::insert_into(battery_logs::table)
+            .values(logs)
+            .execute(conn)
+    });
+    match batch {
+        Err(error) if is_row_error(&error) => conn.transaction(|conn| {
+            let mut stored = 0;
+            for log in logs {
+                match diesel::insert_into(battery_logs::table)
+                    .values(log)
+                    .execute(conn)
+                {
+                    Ok(count) => stored += count,
+                    Err(error) if is_row_error(&error) => println!(
+                        "Dropping battery log of battery {} in test {}: {}",
+                        log.id, log.test_id, error
+                    ),
+                    Err(error) => return Err(error),
+                }
+            }
+            Ok(stored)
+        }),
+        result => result,
+    }
+}
+
+struct Writer<'a> {
+    db_path: &'a str,
+    conn: Option<SqliteConnection>,
+    journal: &'a Journal,
+    last_replay: Option<Instant>,
+}
+
+impl Writer<'_> {
+    /// Stores `logs`, journaling them when the database fails.
+    fn store(&mut self, logs: Vec<BatteryLog>) {
+        if logs.is_empty() {
+            return;
+        }
+        if let Err(error) = self.insert(&logs) {
+            // give the database a moment before trying the journal again
+            self.last_replay = Some(Instant::now());
+            println!(
+                "Failed to store {} battery logs, journaling them: {}",
+                logs.len(),
+                error
+            );
+            if let Err(error) = self.journal.append(&logs) {
+                println!("Failed to journal {} battery logs: {}", logs.len(), error);
+            }
+        }
+    }
+
+    fn insert(&mut self, logs: &[BatteryLog]) -> Result<usize, String> {
+        self.write(|conn| insert_logs(conn, logs))
+    }
+
+    fn save_progress(&mut self, states: &[SequenceState]) {
+        if states.is_empty() {
+            return;
+        }
+        if let Err(error) = self.write(|conn| save_sequence_states(conn, states)) {
+            println!("Failed to save sequence progress: {}", error);
+        }
+    }
+
+    fn write<T>(
+        &mut self,
+        write: impl FnOnce(&mut SqliteConnection) -> QueryResult<T>,
+    ) -> Result<T, String> {
+        if self.conn.is_none() {
+            self.conn = Some(establish_connection(self.db_path).map_err(|e| e.to_string())?);
+        }
+        let conn = self.conn.as_mut().unwrap();
+
+        write(conn).map_err(|error| {
+            // reconnect next time, the file may have moved or come back
+            self.conn = None;
+            error.to_string()
+        })
+    }
+
+    /// Stores the journaled samples, at most every [`RETRY_INTERVAL`] unless
+    /// `now` is set.
+    fn replay(&mut self, now: bool) {
+        let due = self
+            .last_replay
+            .is_none_or(|last| last.elapsed() >= RETRY_INTERVAL);
+        if !self.journal.has_logs() || !(now || due) {
+            return;
+        }
+        self.last_replay = Some(Instant::now());
+
+        match self.journal.take() {
+            Ok(logs) => self.store(logs),
+            Err(error) => println!("Failed to read the log journal: {}", error),
+        }
+    }
+}
+
+fn run(db_path: &str, receiver: Receiver<Message>, journal: &Journal) {
+    let mut writer = Writer {
+        db_path,
+        conn: None,
+        journal,
+        last_replay: None,
+    };
+
+    let mut closed = false;
+    while !closed {
+        let mut batch = Vec::new();
+        let mut progress = Vec::new();
+        let mut flushes = Vec::new();
+
+        // wait for a first sample, then give the batch a moment to fill
+        let mut deadline = None;
+        while batch.len() < BATCH_SIZE && flushes.is_empty() {
+            let timeout = deadline.map_or(FLUSH_INTERVAL, |deadline: Instant| {
+                deadline.saturating_duration_since(Instant::now())
+            });
+            match receiver.recv_timeout(timeout) {
+                Ok(Message::Log(log)) => {
+                    batch.push(log);
+                    deadline.get_or_insert_with(|| Instant::now() + FLUSH_INTERVAL);
+                }
+                Ok(Message::Progress(states)) => progress.extend(states),
+                Ok(Message::Flush(ack)) => flushes.push(ack),
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => {
+                    closed = true;
+                    break;
+                }
+            }
+        }
+
+        // journaled samples are older, store them first
+        writer.replay(!flushes.is_empty());
+        writer.store(batch);
+        // saved in order, so each battery ends up with its latest state
+        writer.save_progress(&progress);
+        for ack in flushes {
+            let _ = ack.send(());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::models::Test;
+    use crate::database::sqlite::MIGRATIONS;
+    use crate::serial::simulator::CellModel;
+    use diesel_migrations::MigrationHarness;
+
+    fn temp_database(name: &str) -> (String, PathBuf) {
+        let path = std::env::temp_dir().join(format!("{}_{}.db", name, std::process::id()));
+        let journal = LogWriter::journal_path(&path);
+        let _ = fs::remove_file(&journal);
+
+        let mut conn = establish_connection(path.to_str().unwrap()).unwrap();
+        conn.run_pending_migrations(MIGRATIONS).unwrap();
+        diesel::insert_into(crate::database::schema::tests::table)
+            .values(&Test {
+                test_id: Some(1),
+                test_name: "Test 1".to_string(),
+                start_date: String::new(),
+                profile_id: None,
+            })
+            .execute(&mut conn)
+            .unwrap();
+        (path.to_str().unwrap().to_string(), journal)
+    }
+
+    fn remove_database(path: &str) {
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = fs::remove_file(format!("{}{}", path, suffix));
+        }
+    }
+
+    fn stored(path: &str) -> i64 {
+        let mut conn = establish_connection(path).unwrap();
+        crate::database::schema::battery_logs::table
+            .count()
+            .get_result(&mut conn)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_log_writer() {
+        let (path, journal) = temp_database("log_writer");
+        let writer = LogWriter::spawn(path.clone(), journal.clone());
+
+        let cell = CellModel::new(2500.0);
+        for _ in 0..1000 {
+            writer.submit(cell.sample(1, "COM3", 1));
+        }
+        // a sample of an unknown test doesn't take the others down
+        writer.submit(cell.sample(1, "COM3", 2));
+        assert!(writer.flush(Duration::from_secs(10)));
+        assert_eq!(stored(&path), 1000);
+        assert!(!journal.exists());
+
+        // the latest progress of each battery wins
+        let progress = |step| SequenceState {
+            test_id: 1,
+            battery_id: 1,
+            port: "COM3".to_string(),
+            step,
+            steps: 3,
+            step_label: format!("Step {}", step),
+            state: "Charge".to_string(),
+            outcome: "Running".to_string(),
+            step_started_at: String::new(),
+            charge_mah: 0.0,
+            updated_at: String::new(),
+        };
+        writer.save_progress(vec![progress(0)]);
+        writer.save_progress(vec![progress(1)]);
+        assert!(writer.flush(Duration::from_secs(10)));
+        let mut conn = establish_connection(&path).unwrap();
+        let saved = crate::database::sqlite::sequence_states_for(&mut conn, 1, "COM3").unwrap();
+        assert_eq!(saved.len(), 1);
+        assert_eq!(saved[0].step, 1);
+
+        remove_database(&path);
+    }
+
+    #[test]
+    fn test_journal_replay() {
+        let (path, journal) = temp_database("log_journal");
+
+        // the database is gone while samples come in
+        let missing = std::env::temp_dir()
+            .join(format!("missing_{}", std::process::id()))
+            .join("battery_logs.db");
+        let writer = LogWriter::spawn(missing.to_str().unwrap().to_string(), journal.clone());
+        let cell = CellModel::new(2500.0);
+        for _ in 0..10 {
+            writer.submit(cell.sample(1, "COM3", 1));
+        }
+        assert!(writer.flush(Duration::from_secs(10)));
+        assert!(journal.exists());
+        drop(writer);
+
+        // and back after a restart
+        let writer = LogWriter::spawn(path.clone(), journal.clone());
+        writer.submit(cell.sample(1, "COM3", 1));
+        assert!(writer.flush(Duration::from_secs(10)));
+        assert_eq!(stored(&path), 11);
+        assert!(!journal.exists());
+
+        remove_database(&path);
+    }
+}
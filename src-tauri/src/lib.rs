@@ -4,29 +4,46 @@ use tauri::{ipc::Channel, Manager};
 use specta_typescript::Typescript;
 use tauri_specta::*;
 
-use rand::Rng;
-
-mod database;
-mod serial;
+pub mod database;
+pub mod serial;
 
 mod misc;
 mod state;
 
-use database::export::export_csv;
+use database::export::{export_csv, export_test_csv};
 use database::sqlite::init_database;
 use misc::populate_fake_data;
 
 use crate::{
     database::{
+        acceptance::{
+            evaluate_acceptance, get_acceptance_criteria, get_verdicts, set_acceptance_criteria,
+        },
+        analysis::{analyze_phases, get_phase_results},
+        archive::{export_test_archive, import_test_archive},
+        columnar::export_test_columnar,
+        import::{export_test_bundle, import_tests},
         models::BatteryLog,
+        profile::{delete_profile, export_profile, get_profiles, import_profile, set_test_profile},
+        query::{get_downsampled_logs, query_battery_logs},
+        report::export_report,
         sqlite::{
-            delete_test, get_all_battery_logs, get_all_tests, get_battery_logs_for_test,
-            insert_battery_log, insert_new_test, insert_test,
+            delete_test, get_alarms, get_all_battery_logs, get_all_tests, get_battery_ids,
+            get_battery_logs_for_test, get_completion_events, get_reserved_ids, get_safety_limits,
+            insert_battery_log, insert_new_test, insert_test, release_reserved_id,
+            reserve_battery_id, set_safety_limits,
         },
     },
     serial::{
-        pilot::{assign_id, data_request, set_state},
-        serial::{command_request, detect_serial_ports},
+        interlock::Alarm,
+        pilot::{assign_id, assign_reserved_id, data_request, set_state, BatteryState},
+        sequencer::{
+            discard_interrupted_sequence, get_interrupted_sequences, get_sequence_progress,
+            resume_sequence, start_sequence, stop_sequence,
+        },
+        serial::{command_request, detect_serial_ports, CompletionStatus},
+        session::{close_bench, list_benches, open_bench, set_auto_assign, BenchLost},
+        simulator::CellModel,
     },
     state::AppState,
 };
@@ -34,51 +51,91 @@ use crate::{
 #[tauri::command]
 #[specta::specta]
 async fn parse_log(on_event: Channel<BatteryLog>) {
-    thread::spawn(move || loop {
-        let mut rng = rand::rng();
+    thread::spawn(move || {
+        let mut cell = CellModel::new(2500.0);
+        cell.set_state(BatteryState::Charge);
+
+        loop {
+            thread::sleep(time::Duration::from_secs(2));
+
+            // a minute of cell time per update so both phases show up
+            if cell.step(60.0).is_some() {
+                let next = match cell.state_of_charge() > 0.5 {
+                    true => BatteryState::Discharge,
+                    false => BatteryState::Charge,
+                };
+                cell.set_state(next);
+            }
 
-        let temp: i32 = rng.random();
-        let log = BatteryLog {
-            record_id: Some(32),
-            id: 3,
-            port: "port".to_string(),
-            battery_temperature: 22,
-            bench_temperature_mosfet: 11,
-            bench_temperature_resistor: 33,
-            load: 50,
-            voltage: 300,
-            current: 500,
-            state: "state".to_string(),
-            status: "status".to_string(),
-            start_date: Some("start date".to_string()),
-            end_date: Some("end date".to_string()),
-            test_id: 2,
-        };
-        thread::sleep(time::Duration::from_secs(2));
-        dbg!(&log);
-        on_event.send(log).unwrap();
+            let log = cell.sample(3, "simulator", 2);
+            if on_event.send(log).is_err() {
+                break;
+            }
+        }
     });
 }
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
-    let builder = Builder::<tauri::Wry>::new().commands(collect_commands![
-        insert_battery_log,
-        export_csv,
-        parse_log,
-        get_all_battery_logs,
-        command_request,
-        detect_serial_ports,
-        populate_fake_data,
-        get_all_tests,
-        get_battery_logs_for_test,
-        insert_test,
-        delete_test,
-        insert_new_test,
-        data_request,
-        assign_id,
-        set_state
-    ]);
+    let builder = Builder::<tauri::Wry>::new()
+        .commands(collect_commands![
+            insert_battery_log,
+            export_csv,
+            export_test_csv,
+            export_test_columnar,
+            export_test_bundle,
+            import_tests,
+            export_test_archive,
+            import_test_archive,
+            query_battery_logs,
+            get_downsampled_logs,
+            parse_log,
+            get_all_battery_logs,
+            command_request,
+            detect_serial_ports,
+            populate_fake_data,
+            get_all_tests,
+            get_battery_logs_for_test,
+            insert_test,
+            delete_test,
+            insert_new_test,
+            data_request,
+            assign_id,
+            set_state,
+            open_bench,
+            close_bench,
+            list_benches,
+            get_battery_ids,
+            get_reserved_ids,
+            reserve_battery_id,
+            release_reserved_id,
+            assign_reserved_id,
+            set_auto_assign,
+            get_completion_events,
+            start_sequence,
+            stop_sequence,
+            get_sequence_progress,
+            get_interrupted_sequences,
+            resume_sequence,
+            discard_interrupted_sequence,
+            get_profiles,
+            import_profile,
+            export_profile,
+            delete_profile,
+            set_test_profile,
+            get_safety_limits,
+            set_safety_limits,
+            get_alarms,
+            analyze_phases,
+            get_phase_results,
+            get_acceptance_criteria,
+            set_acceptance_criteria,
+            evaluate_acceptance,
+            get_verdicts,
+            export_report
+        ])
+        .events(collect_events![BenchLost, Alarm])
+        .typ::<CompletionStatus>();
 
     #[cfg(debug_assertions)] // <- Only export on non-release builds
     builder
@@ -99,6 +156,16 @@ pub fn run() {
 
             Ok(())
         })
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|app, event| {
+            // samples still queued would be lost
+            if let tauri::RunEvent::Exit = event {
+                let state = app.state::<Mutex<AppState>>();
+                let writer = state.lock().ok().and_then(|state| state.log_writer.clone());
+                if let Some(writer) = writer {
+                    writer.flush(time::Duration::from_secs(5));
+                }
+            }
+        });
 }
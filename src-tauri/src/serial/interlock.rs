@@ -0,0 +1,249 @@
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tauri::{AppHandle, Manager};
+use tauri_specta::Event;
+
+use crate::{
+    database::{
+        models::{AlarmRecord, BatteryLog, SafetyLimits},
+        sqlite::{connection, insert_alarm},
+    },
+    serial::{
+        pilot::{change_state, get_current_time, BatteryState},
+        session::BenchSession,
+    },
+    state::AppState,
+};
+
+/// Limits for tests that don't set their own, in °C. Voltage and current
+/// are left unchecked until a test sets an envelope.
+const DEFAULT_MAX_BATTERY_TEMPERATURE: f32 = 60.0;
+const DEFAULT_MAX_MOSFET_TEMPERATURE: f32 = 100.0;
+const DEFAULT_MAX_RESISTOR_TEMPERATURE: f32 = 120.0;
+
+/// How many times SetStandBy is sent before the interlock gives up.
+const STANDBY_ATTEMPTS: u32 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Type, Serialize, Deserialize)]
+pub enum Quantity {
+    BatteryTemperature,
+    MosfetTemperature,
+    ResistorTemperature,
+    Voltage,
+    Current,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Violation {
+    pub quantity: Quantity,
+    pub value: f32,
+    pub threshold: f32,
+}
+
+/// Sent to the frontend when the interlock puts a battery in standby.
+#[derive(Debug, Clone, Type, Serialize, Deserialize, Event)]
+pub struct Alarm {
+    pub port: String,
+    pub battery_id: u8,
+    pub test_id: Option<i32>,
+    pub quantity: Quantity,
+    pub value: f32,
+    pub threshold: f32,
+    /// What the battery was doing when the limit was crossed.
+    pub state: String,
+    pub raised_at: String,
+    /// Whether the bench acknowledged the SetStandBy frame.
+    pub standby: bool,
+}
+
+impl SafetyLimits {
+    pub fn defaults(test_id: i32) -> Self {
+        SafetyLimits {
+            test_id,
+            max_battery_temperature_c: Some(DEFAULT_MAX_BATTERY_TEMPERATURE),
+            max_mosfet_temperature_c: Some(DEFAULT_MAX_MOSFET_TEMPERATURE),
+            max_resistor_temperature_c: Some(DEFAULT_MAX_RESISTOR_TEMPERATURE),
+            min_voltage_mv: None,
+            max_voltage_mv: None,
+            max_current_ma: None,
+        }
+    }
+
+    /// The first limit `log` is outside of, if any.
+    pub fn check(&self, log: &BatteryLog) -> Option<Violation> {
+        let above = |quantity, value: f32, threshold: Option<f32>| {
+            threshold
+                .filter(|&threshold| value > threshold)
+                .map(|threshold| Violation {
+                    quantity,
+                    value,
+                    threshold,
+                })
+        };
+        let below = |quantity, value: f32, threshold: Option<f32>| {
+            threshold
+                .filter(|&threshold| value < threshold)
+                .map(|threshold| Violation {
+                    quantity,
+                    value,
+                    threshold,
+                })
+        };
+
+        above(
+            Quantity::BatteryTemperature,
+            log.battery_temperature,
+            self.max_battery_temperature_c,
+        )
+        .or_else(|| {
+            above(
+                Quantity::MosfetTemperature,
+                log.bench_temperature_mosfet,
+                self.max_mosfet_temperature_c,
+            )
+        })
+        .or_else(|| {
+            above(
+                Quantity::ResistorTemperature,
+                log.bench_temperature_resistor,
+                self.max_resistor_temperature_c,
+            )
+        })
+        .or_else(|| above(Quantity::Voltage, log.voltage, self.max_voltage_mv))
+        .or_else(|| below(Quantity::Voltage, log.voltage, self.min_voltage_mv))
+        .or_else(|| above(Quantity::Current, log.current.abs(), self.max_current_ma))
+    }
+}
+
+impl From<&Alarm> for AlarmRecord {
+    fn from(alarm: &Alarm) -> Self {
+        AlarmRecord {
+            alarm_id: None,
+            battery_id: alarm.battery_id as i32,
+            port: alarm.port.clone(),
+            quantity: format!("{:?}", alarm.quantity),
+            value: alarm.value,
+            threshold: alarm.threshold,
+            state: alarm.state.clone(),
+            raised_at: alarm.raised_at.clone(),
+            test_id: alarm.test_id,
+        }
+    }
+}
+
+/// Checks `log` against `limits` and puts the battery in standby if it is
+/// outside of them.
+pub async fn enforce(
+    session: &BenchSession,
+    limits: &SafetyLimits,
+    log: &BatteryLog,
+    test_id: Option<i32>,
+) -> Option<Alarm> {
+    let violation = limits.check(log)?;
+    let battery_id = log.id as u8;
+    println!(
+        "{}: battery {} {:?} at {} is past {}, forcing standby",
+        session.port(),
+        battery_id,
+        violation.quantity,
+        violation.value,
+        violation.threshold
+    );
+
+    Some(Alarm {
+        port: session.port().to_string(),
+        battery_id,
+        test_id,
+        quantity: violation.quantity,
+        value: violation.value,
+        threshold: violation.threshold,
+        state: log.state.clone(),
+        raised_at: get_current_time(),
+        standby: force_standby(session, battery_id).await,
+    })
+}
+
+async fn force_standby(session: &BenchSession, battery_id: u8) -> bool {
+    for attempt in 1..=STANDBY_ATTEMPTS {
+        match change_state(session, battery_id, BatteryState::Standby).await {
+            Ok(()) => return true,
+            Err(error) => println!(
+                "{}: standby attempt {} for battery {} failed: {}",
+                session.port(),
+                attempt,
+                battery_id,
+                error
+            ),
+        }
+    }
+    false
+}
+
+/// Stores `alarm` and pushes it to the frontend.
+pub fn report_alarm(app: &AppHandle, alarm: &Alarm) {
+    let state = app.state::<Mutex<AppState>>();
+    match state.lock() {
+        Ok(mut state) => {
+            let stored = connection(&mut state)
+                .map_err(|e| e.to_string())
+                .and_then(|conn| insert_alarm(conn, &alarm.into()).map_err(|e| e.to_string()));
+            if let Err(error) = stored {
+                println!("Failed to store alarm: {}", error);
+            }
+        }
+        Err(error) => println!("Failed to store alarm: {}", error),
+    }
+
+    if let Err(error) = alarm.emit(app) {
+        println!("Failed to emit alarm event: {}", error);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn log(battery_temperature: f32, voltage: f32, current: f32) -> BatteryLog {
+        BatteryLog {
+            record_id: None,
+            id: 3,
+            port: "memory".to_string(),
+            battery_temperature,
+            bench_temperature_mosfet: 30.0,
+            bench_temperature_resistor: 30.0,
+            load: 3.0,
+            voltage,
+            current,
+            state: "Discharge".to_string(),
+            status: String::new(),
+            start_date: None,
+            end_date: None,
+            test_id: 1,
+            recorded_at: get_current_time(),
+            elapsed_s: 0.0,
+        }
+    }
+
+    #[test]
+    fn test_check_limits() {
+        let mut limits = SafetyLimits::defaults(1);
+        assert_eq!(limits.check(&log(25.0, 3700.0, -1200.0)), None);
+
+        let violation = limits.check(&log(61.0, 3700.0, -1200.0)).unwrap();
+        assert_eq!(violation.quantity, Quantity::BatteryTemperature);
+        assert_eq!(violation.threshold, 60.0);
+
+        limits.min_voltage_mv = Some(3000.0);
+        limits.max_current_ma = Some(1000.0);
+        let violation = limits.check(&log(25.0, 2900.0, -1200.0)).unwrap();
+        assert_eq!(violation.quantity, Quantity::Voltage);
+        assert_eq!(violation.value, 2900.0);
+
+        // current is checked either direction
+        let violation = limits.check(&log(25.0, 3700.0, -1200.0)).unwrap();
+        assert_eq!(violation.quantity, Quantity::Current);
+        assert_eq!(violation.value, 1200.0);
+    }
+}
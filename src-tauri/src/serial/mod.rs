@@ -1,2 +1,8 @@
+pub mod interlock;
 pub mod pilot;
+pub mod sequencer;
+#[allow(clippy::module_inception)]
 pub mod serial;
+pub mod session;
+pub mod simulator;
+pub mod transport;
@@ -1,20 +1,36 @@
 use std::{
+    collections::HashSet,
     sync::{Arc, Mutex},
-    thread,
     time::Duration,
 };
 
 use chrono::Utc;
 use serde::{Deserialize, Serialize};
 use specta::Type;
-use tauri::{ipc::Channel, State};
+use tauri::{AppHandle, Manager, State};
+use tokio::sync::broadcast::{self, error::RecvError};
 
 use crate::{
-    database::{models::BatteryLog, sqlite},
-    serial::serial::{BatteryCommand, Command},
+    database::{
+        models::{BatteryIdAssignment, BatteryLog, CompletionEvent, SafetyLimits},
+        sqlite::{
+            connection, insert_completion_event, is_reserved, limits_for_test, record_battery_id,
+            taken_battery_ids,
+        },
+    },
+    serial::{
+        interlock::{enforce, report_alarm, Alarm},
+        serial::{BatteryCommand, BenchError, Command, CompletionStatus, UNASSIGNED_ID},
+        session::{session_for, BenchSession},
+    },
+    state::AppState,
 };
 
-#[derive(Debug, Default, Serialize, Deserialize, Clone, Type)]
+/// How long a bench has to ping with a newly assigned ID. It pings every
+/// second, so this covers a lost frame.
+const CLAIM_TIMEOUT: Duration = Duration::from_millis(2500);
+
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Type)]
 pub enum BatteryState {
     #[default]
     Standby,
@@ -41,41 +57,34 @@ impl Bench {
         //spawns a thread that scans all the open ports every sec and adds them to open ports list
     }
 
-    pub fn new() -> Result<BatteryLog, &'static str> {
+    pub fn new() -> Result<Self, &'static str> {
         todo!()
 
         //pings a newly open port to check if firmware is running on the port
     }
 
-    //starts a thread for the thread that pings the bench every sec
-    pub fn start_sequence(
-        &self,
-        state: State<'_, Mutex<crate::state::AppState>>,
-        on_event: Channel<BatteryLog>,
-    ) {
-        let bench = Arc::new(Mutex::new(self.clone()));
-        let state_arc = state.inner().clone();
-
-        // thread::spawn(move || loop {
-        //     let mut bench_guard = bench.lock().unwrap();
-        //     bench_guard.complete_sequence_step(state.clone(), on_event.clone());
-        // });
-    }
-
-    pub fn complete_sequence_step(
+    /// Samples every battery, putting any that is past `limits` in standby.
+    /// Returns the alarms raised.
+    pub async fn complete_sequence_step(
         &mut self,
-        state: State<'_, Mutex<crate::state::AppState>>,
-        on_event: Channel<BatteryLog>,
-    ) {
+        session: &BenchSession,
+        limits: &SafetyLimits,
+        mut on_sample: impl FnMut(BatteryLog),
+    ) -> Result<Vec<Alarm>, BenchError> {
         let mut bat_count = 0;
+        let mut alarms = Vec::new();
 
-        for battery in &self.batteries {
+        for battery in &mut self.batteries {
             // request data
-            match data_request(self.clone(), battery.clone()) {
+            match request_data(session, battery.id).await {
                 Ok(data) => {
-                    sqlite::insert_battery_log(state.clone(), data.clone());
-                    // pass to channel
-                    on_event.send(data).unwrap();
+                    if let Some(alarm) = enforce(session, limits, &data, None).await {
+                        if alarm.standby {
+                            battery.state = BatteryState::Standby;
+                        }
+                        alarms.push(alarm);
+                    }
+                    on_sample(data)
                 }
                 Err(error) => print!("Error while fetching data: {}", error),
             }
@@ -83,33 +92,45 @@ impl Bench {
             bat_count += 1;
         }
 
-        while bat_count < 4 {
-            // ping with new ID
-            match assign_id(self.clone()) {
-                Ok(id) => {
-                    self.batteries.push(Battery {
-                        id: id,
-                        state: BatteryState::Standby,
-                    });
-                    bat_count += 1;
-                }
-                Err(_) => todo!(),
+        // batteries get their ID from the assigner, pick up the new ones
+        for id in session.known_ids() {
+            if bat_count < 4 && !self.batteries.iter().any(|battery| battery.id == id) {
+                self.batteries.push(Battery {
+                    id,
+                    state: BatteryState::Standby,
+                });
+                bat_count += 1;
             }
         }
-    }
 
-    pub fn complete_sequence(&mut self) {
-        todo!()
+        Ok(alarms)
     }
 }
 
 #[tauri::command]
 #[specta::specta]
-pub fn set_state(
+pub async fn set_state(
+    state: State<'_, Mutex<AppState>>,
     bench: Bench,
     battery: Battery,
     new_state: BatteryState,
-) -> Result<String, String> {
+) -> Result<String, BenchError> {
+    let session = session_for(&state, &bench.port)?;
+    change_state(&session, battery.id, new_state).await?;
+
+    Ok(format!(
+        "Battery {} state successfully changed to {:?}",
+        battery.id, new_state
+    ))
+}
+
+/// Sends the SetStandBy/SetCharge/SetDischarge frame for `new_state` and
+/// waits for the bench to echo it.
+pub async fn change_state(
+    session: &BenchSession,
+    battery_id: u8,
+    new_state: BatteryState,
+) -> Result<(), BenchError> {
     let command = match new_state {
         BatteryState::Standby => Command::SetStandBy,
         BatteryState::Charge => Command::SetCharge,
@@ -117,147 +138,577 @@ pub fn set_state(
     };
 
     let battery_cmd = BatteryCommand {
-        command: command,
-        battery_id: battery.id,
+        command,
+        battery_id,
         payload: vec![],
     };
-    let encoded_data = battery_cmd.encode();
-
-    let mut response = vec![0u8; command.response_lenght()];
+    session.exchange(&battery_cmd).await?;
 
-    let mut port = serialport::new(bench.port, 9600)
-        .timeout(Duration::from_millis(100))
-        .open()
-        .map_err(|e| e.to_string())?;
+    Ok(())
+}
 
-    port.write_all(&encoded_data).map_err(|e| e.to_string())?;
+#[tauri::command]
+#[specta::specta]
+pub async fn assign_id(state: State<'_, Mutex<AppState>>, bench: Bench) -> Result<u8, BenchError> {
+    let session = session_for(&state, &bench.port)?;
+    assign_unassigned(&state, &session).await
+}
 
-    loop {
-        match port.read_exact(&mut response) {
-            Ok(_) => break,
-            Err(e) if e.kind() == std::io::ErrorKind::TimedOut => {
-                println!("State change command not ready");
-                thread::sleep(Duration::from_millis(333));
+/// Answers every `0xFF` Ping on `session` with a fresh ID, for as long as the
+/// session is open.
+pub fn spawn_id_assigner(app: AppHandle, session: &Arc<BenchSession>) {
+    let mut pings = session.subscribe();
+    let session = Arc::downgrade(session);
+
+    tauri::async_runtime::spawn(async move {
+        loop {
+            let ping = match pings.recv().await {
+                Ok(ping) => ping,
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => break,
+            };
+            if ping.command != Command::Ping || ping.battery_id != UNASSIGNED_ID {
+                continue;
+            }
+            let Some(session) = session.upgrade() else {
+                break;
+            };
+            if !session.auto_assign() {
+                continue;
             }
-            Err(e) => return Err(e.to_string()),
-        }
-    }
 
-    dbg!(&response);
-
-    match BatteryCommand::decode(&response) {
-        Ok(decoded_response) => {
-            // Check if the response command matches what we sent
-            if decoded_response.command == command && decoded_response.battery_id == battery.id {
-                Ok(format!(
-                    "Battery {} state successfully changed to {:?}",
-                    battery.id, new_state
-                ))
-            } else {
-                Err(format!(
-                    "Unexpected response: got {:?} for battery {}, expected {:?} for battery {}",
-                    decoded_response.command, decoded_response.battery_id, command, battery.id
-                ))
+            let state = app.state::<Mutex<AppState>>();
+            match assign_unassigned(&state, &session).await {
+                Ok(battery_id) => println!("{}: assigned battery {}", session.port(), battery_id),
+                Err(error) => println!("{}: ID assignment failed: {}", session.port(), error),
             }
+
+            // 0xFF pings queued while assigning are from the bench we just
+            // handled, any other unassigned bench pings again within a second
+            pings = pings.resubscribe();
         }
-        Err(error) => Err(error),
-    }
+    });
 }
 
 #[tauri::command]
 #[specta::specta]
-pub fn assign_id(bench: Bench) -> Result<u8, String> {
-    let command = Command::AssignId;
-    let mut battery_id: u8 = 0;
-
-    // FIXME: potentially infinite loop
-    while bench
-        .batteries
-        .iter()
-        .any(|battery| battery.id == battery_id)
-    {
-        if battery_id == 255 {
-            battery_id = 0;
+pub async fn assign_reserved_id(
+    state: State<'_, Mutex<AppState>>,
+    port: String,
+    battery_id: u8,
+) -> Result<u8, BenchError> {
+    let session = session_for(&state, &port)?;
+    assign_reserved(&state, &session, battery_id).await
+}
+
+/// Stores every completion the bench on `session` announces, for as long as
+/// the session is open.
+pub fn spawn_completion_recorder(app: AppHandle, session: &Arc<BenchSession>) {
+    let mut frames = session.subscribe();
+    let port = session.port().to_string();
+
+    tauri::async_runtime::spawn(async move {
+        loop {
+            let frame = match frames.recv().await {
+                Ok(frame) => frame,
+                Err(RecvError::Lagged(missed)) => {
+                    println!("{}: missed {} bench frames", port, missed);
+                    continue;
+                }
+                Err(RecvError::Closed) => break,
+            };
+            if frame.command != Command::RequestCompletion {
+                continue;
+            }
+
+            let state = app.state::<Mutex<AppState>>();
+            let test_id = state
+                .lock()
+                .ok()
+                .and_then(|state| state.sequences.get(&port).map(|sequence| sequence.test_id));
+            if let Err(error) = record_completion(&state, &port, &frame, test_id) {
+                println!("{}: dropped completion {:?}: {}", port, frame, error);
+            }
         }
-        battery_id += 1;
-    }
+    });
+}
 
-    let battery_cmd = BatteryCommand {
-        command: command,
-        battery_id: battery_id,
-        payload: vec![],
+/// Decodes a RequestCompletion frame and stores it.
+pub fn record_completion(
+    state: &Mutex<AppState>,
+    port: &str,
+    frame: &BatteryCommand,
+    test_id: Option<i32>,
+) -> Result<CompletionStatus, BenchError> {
+    let status = frame.parse_completion(&frame.payload)?;
+    let event = CompletionEvent {
+        record_id: None,
+        battery_id: frame.battery_id as i32,
+        port: port.to_string(),
+        phase: format!("{:?}", status.phase),
+        outcome: format!("{:?}", status.outcome),
+        flags: status.flags() as i32,
+        received_at: get_current_time(),
+        test_id,
     };
-    let encoded_data = battery_cmd.encode();
 
-    let mut response = vec![0u8; command.response_lenght()];
+    let mut state = state.lock().map_err(|e| BenchError::Io {
+        message: e.to_string(),
+    })?;
+    insert_completion_event(connection(&mut state)?, &event)?;
 
-    let mut port = serialport::new(bench.port, 9600)
-        .timeout(Duration::from_millis(100))
-        .open()
-        .map_err(|e| e.to_string())?;
+    Ok(status)
+}
 
-    port.write_all(&encoded_data).map_err(|e| e.to_string())?;
+/// Sends a fresh ID to the unassigned bench on `session`.
+pub async fn assign_unassigned(
+    state: &Mutex<AppState>,
+    session: &BenchSession,
+) -> Result<u8, BenchError> {
+    let battery_id = allocate_battery_id(state)?;
+    push_battery_id(state, session, battery_id).await
+}
 
-    loop {
-        match port.read_exact(&mut response) {
-            Ok(_) => break,
-            Err(e) if e.kind() == std::io::ErrorKind::TimedOut => {
-                println!("data was not ready");
-                thread::sleep(Duration::from_millis(333));
-            }
-            Err(e) => return Err(e.to_string()),
+/// Sends an ID the operator reserved beforehand to the unassigned bench on
+/// `session`.
+pub async fn assign_reserved(
+    state: &Mutex<AppState>,
+    session: &BenchSession,
+    battery_id: u8,
+) -> Result<u8, BenchError> {
+    hold_reserved_id(state, battery_id)?;
+    push_battery_id(state, session, battery_id).await
+}
+
+/// Makes sure every battery in `battery_ids` pings on `session`, giving its
+/// old ID back to a bench that dropped it, e.g. while the GUI was down.
+/// Automatic assignment should be off so the benches stay unassigned.
+pub async fn reclaim_battery_ids(
+    state: &Mutex<AppState>,
+    session: &BenchSession,
+    battery_ids: &[u8],
+) -> Result<(), BenchError> {
+    // a bench that kept its ID pings again within a second
+    let deadline = tokio::time::Instant::now() + CLAIM_TIMEOUT;
+    while tokio::time::Instant::now() < deadline {
+        if battery_ids
+            .iter()
+            .all(|&battery_id| session.last_heard(battery_id).is_some())
+        {
+            return Ok(());
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+
+    for &battery_id in battery_ids {
+        if session.last_heard(battery_id).is_none() {
+            hold_previous_id(state, battery_id)?;
+            push_battery_id(state, session, battery_id).await?;
         }
     }
 
-    dbg!(&response);
+    Ok(())
+}
+
+/// Sends `battery_id`, held in `pending_battery_ids`, and waits for the bench
+/// to ping with it before recording it in the database.
+async fn push_battery_id(
+    state: &Mutex<AppState>,
+    session: &BenchSession,
+    battery_id: u8,
+) -> Result<u8, BenchError> {
+    let mut pings = session.subscribe();
+    let claimed = claim_battery_id(session, &mut pings, battery_id).await;
+
+    let mut state = state.lock().map_err(|e| BenchError::Io {
+        message: e.to_string(),
+    })?;
+    state.pending_battery_ids.remove(&battery_id);
+    claimed?;
+
+    let assignment = BatteryIdAssignment {
+        battery_id: battery_id as i32,
+        port: session.port().to_string(),
+        assigned_at: get_current_time(),
+    };
+    record_battery_id(connection(&mut state)?, &assignment)?;
+
+    Ok(battery_id)
+}
+
+/// Picks the lowest ID not used by any open bench, pending assignment, or
+/// assignment, log or reservation in the database, and holds it until the
+/// assignment finishes.
+fn allocate_battery_id(state: &Mutex<AppState>) -> Result<u8, BenchError> {
+    let mut state = state.lock().map_err(|e| BenchError::Io {
+        message: e.to_string(),
+    })?;
+
+    let mut taken = taken_battery_ids(connection(&mut state)?)?;
+    taken.extend(ids_in_use(&state));
+
+    let battery_id = (0..UNASSIGNED_ID)
+        .find(|id| !taken.contains(id))
+        .ok_or(BenchError::NoFreeId)?;
+    state.pending_battery_ids.insert(battery_id);
+
+    Ok(battery_id)
+}
+
+/// Holds an ID a bench had before until the assignment finishes, refusing it
+/// while another bench is using it.
+fn hold_previous_id(state: &Mutex<AppState>, battery_id: u8) -> Result<(), BenchError> {
+    let mut state = state.lock().map_err(|e| BenchError::Io {
+        message: e.to_string(),
+    })?;
+
+    if ids_in_use(&state).contains(&battery_id) {
+        return Err(BenchError::IdInUse { battery_id });
+    }
+    state.pending_battery_ids.insert(battery_id);
+
+    Ok(())
+}
+
+/// Holds a reserved ID until the assignment finishes, refusing it while a
+/// bench is already using it.
+fn hold_reserved_id(state: &Mutex<AppState>, battery_id: u8) -> Result<(), BenchError> {
+    let mut state = state.lock().map_err(|e| BenchError::Io {
+        message: e.to_string(),
+    })?;
+
+    if !is_reserved(connection(&mut state)?, battery_id)? {
+        return Err(BenchError::IdNotReserved { battery_id });
+    }
+    if ids_in_use(&state).contains(&battery_id) {
+        return Err(BenchError::IdInUse { battery_id });
+    }
+    state.pending_battery_ids.insert(battery_id);
+
+    Ok(())
+}
+
+/// IDs pinging on an open bench or on their way to one.
+fn ids_in_use(state: &AppState) -> HashSet<u8> {
+    let mut ids: HashSet<u8> = state.pending_battery_ids.iter().copied().collect();
+    for session in state.sessions.values() {
+        ids.extend(session.known_ids());
+    }
+    ids
+}
+
+async fn claim_battery_id(
+    session: &BenchSession,
+    pings: &mut broadcast::Receiver<BatteryCommand>,
+    battery_id: u8,
+) -> Result<(), BenchError> {
+    let assign = BatteryCommand {
+        command: Command::AssignId,
+        battery_id,
+        payload: vec![],
+    };
+    session.send(&assign).await?;
 
-    match BatteryCommand::decode(&response) {
-        Ok(decoded_response) => battery_cmd.parse_assign_id(&decoded_response.payload),
-        Err(error) => Err(error),
+    let claimed = async {
+        loop {
+            match pings.recv().await {
+                Ok(ping) if ping.command == Command::Ping && ping.battery_id == battery_id => {
+                    return true
+                }
+                Ok(_) | Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => return false,
+            }
+        }
+    };
+
+    match tokio::time::timeout(CLAIM_TIMEOUT, claimed).await {
+        Ok(true) => Ok(()),
+        _ => Err(BenchError::UnclaimedId { battery_id }),
     }
 }
 
 #[tauri::command]
 #[specta::specta]
-pub fn data_request(bench: Bench, battery: Battery) -> Result<BatteryLog, String> {
-    let command = Command::RequestData;
+pub async fn data_request(
+    app: AppHandle,
+    state: State<'_, Mutex<AppState>>,
+    bench: Bench,
+    battery: Battery,
+) -> Result<BatteryLog, BenchError> {
+    let session = session_for(&state, &bench.port)?;
+    let log = request_data(&session, battery.id).await?;
+
+    // the limits of the test running on the bench, if any
+    let (test_id, limits) = {
+        let mut state = state.lock().map_err(|e| BenchError::Io {
+            message: e.to_string(),
+        })?;
+        let test_id = state
+            .sequences
+            .get(&bench.port)
+            .map(|sequence| sequence.test_id);
+        let limits = match test_id {
+            Some(test_id) => limits_for_test(connection(&mut state)?, test_id)?,
+            None => SafetyLimits::defaults(0),
+        };
+        (test_id, limits)
+    };
+
+    if let Some(alarm) = enforce(&session, &limits, &log, test_id).await {
+        report_alarm(&app, &alarm);
+    }
+
+    Ok(log)
+}
+
+pub async fn request_data(
+    session: &BenchSession,
+    battery_id: u8,
+) -> Result<BatteryLog, BenchError> {
     let battery_cmd = BatteryCommand {
-        command: command,
-        battery_id: battery.id,
+        command: Command::RequestData,
+        battery_id,
         payload: vec![0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
     };
-    let encoded_data = battery_cmd.encode();
 
-    let mut response = vec![0u8; command.response_lenght()];
+    let decoded_response = session.exchange(&battery_cmd).await?;
 
-    let mut port = serialport::new(bench.port.clone(), 9600)
-        .timeout(Duration::from_millis(100))
-        .open()
-        .map_err(|e| e.to_string())?;
+    Ok(battery_cmd.parse_request_data(
+        &decoded_response.payload,
+        battery_id,
+        session.port().to_string(),
+    )?)
+}
 
-    port.write_all(&encoded_data).map_err(|e| e.to_string())?;
+pub fn get_current_time() -> String {
+    Utc::now().to_rfc3339()
+}
 
-    loop {
-        match port.read_exact(&mut response) {
-            Ok(_) => break,
-            Err(e) if e.kind() == std::io::ErrorKind::TimedOut => {
-                println!("data was not ready");
-                thread::sleep(Duration::from_millis(333));
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use diesel::prelude::*;
+    use diesel_migrations::MigrationHarness;
+
+    use super::*;
+    use crate::{
+        database::sqlite::{establish_connection, reserve_id, MIGRATIONS},
+        serial::{
+            serial::FrameDecoder,
+            simulator::{CellModel, SimulatorConfig, SimulatorHandle},
+            transport::{memory_pipe, MemoryTransport},
+        },
+    };
+
+    /// Answers every RequestData frame with the SDD example payload.
+    fn fake_bench(mut port: MemoryTransport) -> thread::JoinHandle<()> {
+        thread::spawn(move || {
+            let mut decoder = FrameDecoder::new();
+            let mut chunk = [0u8; 64];
+
+            loop {
+                let count = match std::io::Read::read(&mut port, &mut chunk) {
+                    Ok(0) => return,
+                    Ok(count) => count,
+                    Err(_) => continue,
+                };
+
+                for frame in decoder.feed(&chunk[..count]) {
+                    let reply = BatteryCommand {
+                        command: frame.command.command,
+                        battery_id: frame.command.battery_id,
+                        payload: vec![0x07, 0xE4, 0x07, 0xE4, 0x07, 0xE4, 0x07, 0xE4, 0, 0, 0, 0],
+                    };
+                    // garbage in between frames must not matter
+                    std::io::Write::write_all(&mut port, &[0x00, 0x42]).unwrap();
+                    std::io::Write::write_all(&mut port, &reply.encode()).unwrap();
+                }
             }
-            Err(e) => return Err(e.to_string()),
-        }
+        })
+    }
+
+    #[tokio::test]
+    async fn test_complete_sequence_step() {
+        let (gui, bench_port) = memory_pipe();
+        let bench_thread = fake_bench(bench_port);
+        let session = BenchSession::from_transport("memory", Box::new(gui.clone())).unwrap();
+
+        let mut bench = Bench {
+            batteries: (1..=4)
+                .map(|id| Battery {
+                    id,
+                    state: BatteryState::Standby,
+                })
+                .collect(),
+            port: "memory".to_string(),
+        };
+
+        let mut samples = Vec::new();
+        let alarms = bench
+            .complete_sequence_step(&session, &SafetyLimits::defaults(0), |log| {
+                samples.push(log)
+            })
+            .await
+            .unwrap();
+        assert!(alarms.is_empty());
+
+        let ids: Vec<_> = samples.iter().map(|log| log.id).collect();
+        assert_eq!(ids, vec![1, 2, 3, 4]);
+        assert!(samples.iter().all(|log| log.port == "memory"));
+        assert_eq!(bench.batteries.len(), 4);
+
+        session.close();
+        gui.close();
+        bench_thread.join().unwrap();
+    }
+
+    fn memory_state() -> Mutex<AppState> {
+        let mut connection = establish_connection(":memory:").unwrap();
+        connection.run_pending_migrations(MIGRATIONS).unwrap();
+
+        Mutex::new(AppState {
+            db_connection: Some(connection),
+            ..AppState::default()
+        })
     }
 
-    dbg!(&response);
+    #[tokio::test]
+    async fn test_assign_unassigned_skips_taken_ids() {
+        let state = memory_state();
+        {
+            let mut state = state.lock().unwrap();
+            let conn = connection(&mut state).unwrap();
+            let test = crate::database::models::Test {
+                test_id: Some(1),
+                test_name: "Test 1".to_string(),
+                start_date: get_current_time(),
+                profile_id: None,
+            };
+            diesel::insert_into(crate::database::schema::tests::table)
+                .values(&test)
+                .execute(conn)
+                .unwrap();
+            diesel::insert_into(crate::database::schema::battery_logs::table)
+                .values(&CellModel::new(2500.0).sample(0, "COM1", 1))
+                .execute(conn)
+                .unwrap();
+            let earlier = BatteryIdAssignment {
+                battery_id: 1,
+                port: "COM1".to_string(),
+                assigned_at: get_current_time(),
+            };
+            record_battery_id(conn, &earlier).unwrap();
+        }
+
+        let (gui, bench_port) = memory_pipe();
+        let _simulator = SimulatorHandle::spawn(SimulatorConfig::default(), Box::new(bench_port));
+        let session = BenchSession::from_transport("simulator", Box::new(gui.clone())).unwrap();
+
+        assert_eq!(assign_unassigned(&state, &session).await, Ok(2));
+
+        let mut state = state.lock().unwrap();
+        assert!(state.pending_battery_ids.is_empty());
+        let taken = taken_battery_ids(connection(&mut state).unwrap()).unwrap();
+        assert!(taken.contains(&2));
+        assert!(session.known_ids().contains(&2));
 
-    match BatteryCommand::decode(&response) {
-        Ok(decoded_response) => {
-            battery_cmd.parse_request_data(&decoded_response.payload, battery.id, bench.port)
+        session.close();
+        gui.close();
+    }
+
+    #[tokio::test]
+    async fn test_assign_reserved() {
+        let state = memory_state();
+        {
+            let mut state = state.lock().unwrap();
+            reserve_id(connection(&mut state).unwrap(), "cell A", 0x42, None).unwrap();
         }
-        Err(error) => Err(error),
+
+        let (gui, bench_port) = memory_pipe();
+        let _simulator = SimulatorHandle::spawn(SimulatorConfig::default(), Box::new(bench_port));
+        let session = BenchSession::from_transport("simulator", Box::new(gui.clone())).unwrap();
+
+        assert_eq!(
+            assign_reserved(&state, &session, 0x43).await,
+            Err(BenchError::IdNotReserved { battery_id: 0x43 })
+        );
+        assert_eq!(assign_reserved(&state, &session, 0x42).await, Ok(0x42));
+
+        // the ID is on a bench now, and never handed out automatically
+        state
+            .lock()
+            .unwrap()
+            .sessions
+            .insert("simulator".to_string(), session.clone());
+        assert_eq!(
+            assign_reserved(&state, &session, 0x42).await,
+            Err(BenchError::IdInUse { battery_id: 0x42 })
+        );
+        assert_ne!(allocate_battery_id(&state), Ok(0x42));
+
+        state.lock().unwrap().sessions.clear();
+        session.close();
+        gui.close();
     }
-}
 
-pub fn get_current_time() -> String {
-    Utc::now().to_rfc3339()
+    #[tokio::test]
+    async fn test_reclaim_battery_ids() {
+        let state = memory_state();
+        let (gui, bench_port) = memory_pipe();
+        let _simulator = SimulatorHandle::spawn(
+            SimulatorConfig {
+                cells: 2,
+                ping_interval: Duration::from_millis(100),
+                ..SimulatorConfig::default()
+            },
+            Box::new(bench_port),
+        );
+        let session = BenchSession::from_transport("simulator", Box::new(gui.clone())).unwrap();
+
+        // both cells lost their IDs
+        reclaim_battery_ids(&state, &session, &[4, 9])
+            .await
+            .unwrap();
+        let mut known = session.known_ids();
+        known.sort_unstable();
+        assert_eq!(known, vec![4, 9]);
+        assert!(state.lock().unwrap().pending_battery_ids.is_empty());
+
+        // nothing to do once they ping with them
+        reclaim_battery_ids(&state, &session, &[4, 9])
+            .await
+            .unwrap();
+
+        session.close();
+        gui.close();
+    }
+
+    #[test]
+    fn test_record_completion() {
+        let state = memory_state();
+        let frame = BatteryCommand {
+            command: Command::RequestCompletion,
+            battery_id: 0x23,
+            payload: vec![0x82],
+        };
+
+        let status = record_completion(&state, "COM3", &frame, None).unwrap();
+        assert_eq!(status.flags(), 0x82);
+
+        let invalid = BatteryCommand {
+            payload: vec![0x8A],
+            ..frame
+        };
+        assert!(record_completion(&state, "COM3", &invalid, None).is_err());
+
+        let mut state = state.lock().unwrap();
+        let events = crate::database::schema::completion_events::table
+            .load::<CompletionEvent>(connection(&mut state).unwrap())
+            .unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].battery_id, 0x23);
+        assert_eq!(events[0].phase, "Discharge");
+        assert_eq!(events[0].outcome, "Failed");
+    }
 }
@@ -0,0 +1,1295 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tauri::{ipc::Channel, AppHandle, Manager, State};
+use tokio::{
+    sync::{broadcast::error::RecvError, watch},
+    time::MissedTickBehavior,
+};
+
+use crate::{
+    database::{
+        analysis::analyze_test,
+        models::{BatteryLog, SafetyLimits, SequenceState},
+        profile::{profile_for_test, Step, TestProfile},
+        sqlite::{
+            clear_sequence_states, clock_for_test, connection, insert_battery_log,
+            interrupted_sequence_states, limits_for_test, save_sequence_states,
+            sequence_states_for, stop_sequence_states,
+        },
+        writer::LogWriter,
+    },
+    serial::{
+        interlock::{enforce, report_alarm, Alarm},
+        pilot::{change_state, get_current_time, reclaim_battery_ids, request_data, BatteryState},
+        serial::{BatteryCommand, BenchError, Command, CompletionOutcome, CompletionPhase},
+        session::{open_session, session_for, BenchSession},
+    },
+    state::AppState,
+};
+
+/// How often each battery is sampled. The interlock only sees a reading on
+/// each sample, so this is also how late it can notice a limit being crossed.
+pub const SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long the end of a sequence waits for its samples to be stored.
+const WRITER_FLUSH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Consecutive failed data requests after which a battery is given up on.
+const MAX_MISSED_SAMPLES: u32 = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Type, Serialize, Deserialize)]
+pub enum SequenceOutcome {
+    Running,
+    Complete,
+    Failed,
+    Stopped,
+    /// Put in standby by the safety interlock.
+    Aborted,
+}
+
+/// What to do with the step a sequence was interrupted in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Type, Serialize, Deserialize)]
+pub enum ResumePolicy {
+    /// Carry on with the time and charge the step already had.
+    Continue,
+    /// Start the step over.
+    RestartStep,
+}
+
+/// Where one battery is in its sequence.
+#[derive(Debug, Clone, PartialEq, Type, Serialize, Deserialize)]
+pub struct BatteryProgress {
+    pub battery_id: u8,
+    /// Index of the current step.
+    pub step: u32,
+    pub steps: u32,
+    /// Label of the current step in the profile.
+    pub step_label: String,
+    pub state: BatteryState,
+    pub outcome: SequenceOutcome,
+    /// When the current step started, RFC 3339.
+    pub step_started_at: String,
+    /// Charge moved in or out of the battery during the current step.
+    pub charge_mah: f32,
+}
+
+impl BatteryProgress {
+    /// The current step's label, or how the sequence ended.
+    pub fn label(&self) -> String {
+        match self.outcome {
+            SequenceOutcome::Running => self.step_label.clone(),
+            outcome => format!("{:?}", outcome),
+        }
+    }
+
+    pub fn to_state(&self, test_id: i32, port: &str) -> SequenceState {
+        SequenceState {
+            test_id,
+            battery_id: self.battery_id as i32,
+            port: port.to_string(),
+            step: self.step as i32,
+            steps: self.steps as i32,
+            step_label: self.step_label.clone(),
+            state: format!("{:?}", self.state),
+            outcome: format!("{:?}", self.outcome),
+            step_started_at: self.step_started_at.clone(),
+            charge_mah: self.charge_mah,
+            updated_at: get_current_time(),
+        }
+    }
+}
+
+impl TryFrom<SequenceState> for BatteryProgress {
+    type Error = String;
+
+    fn try_from(saved: SequenceState) -> Result<Self, Self::Error> {
+        // both enums serialize as their variant name
+        fn variant<T: serde::de::DeserializeOwned>(name: &str) -> Result<T, String> {
+            serde_json::from_value(serde_json::Value::String(name.to_string()))
+                .map_err(|_| format!("unknown value {:?}", name))
+        }
+
+        Ok(BatteryProgress {
+            battery_id: u8::try_from(saved.battery_id)
+                .map_err(|_| format!("invalid battery ID {}", saved.battery_id))?,
+            step: saved.step as u32,
+            steps: saved.steps as u32,
+            step_label: saved.step_label,
+            state: variant(&saved.state)?,
+            outcome: variant(&saved.outcome)?,
+            step_started_at: saved.step_started_at,
+            charge_mah: saved.charge_mah,
+        })
+    }
+}
+
+/// A sequence that was still running when the application stopped.
+#[derive(Debug, Clone, Type, Serialize, Deserialize)]
+pub struct InterruptedSequence {
+    pub port: String,
+    pub test_id: i32,
+    pub batteries: Vec<BatteryProgress>,
+}
+
+/// Seconds since a test started, counted on a monotonic clock so that
+/// wall-clock adjustments during a run don't show up in the samples.
+#[derive(Debug, Clone, Copy)]
+pub struct TestClock {
+    anchor: Instant,
+    offset: Duration,
+}
+
+impl TestClock {
+    pub fn starting_now() -> Self {
+        TestClock {
+            anchor: Instant::now(),
+            offset: Duration::ZERO,
+        }
+    }
+
+    /// The clock of a test that started at `started_at`, RFC 3339, which
+    /// never goes back behind `last_elapsed_s`, the last time stored for it.
+    pub fn resume(started_at: &str, last_elapsed_s: Option<f64>) -> Self {
+        let since_start = DateTime::parse_from_rfc3339(started_at)
+            .ok()
+            .and_then(|started| (Utc::now() - started.with_timezone(&Utc)).to_std().ok())
+            .unwrap_or_default();
+        let last =
+            Duration::try_from_secs_f64(last_elapsed_s.unwrap_or_default()).unwrap_or_default();
+
+        TestClock {
+            anchor: Instant::now(),
+            offset: since_start.max(last),
+        }
+    }
+
+    pub fn elapsed_s(&self) -> f64 {
+        (self.offset + self.anchor.elapsed()).as_secs_f64()
+    }
+}
+
+/// Receives what a running [`Sequencer`] produces.
+pub trait SequenceObserver: Send {
+    fn sample(&mut self, log: BatteryLog);
+
+    fn progress(&mut self, _progress: &[BatteryProgress]) {}
+
+    fn alarm(&mut self, _alarm: &Alarm) {}
+}
+
+#[derive(Debug)]
+struct Track {
+    progress: BatteryProgress,
+    missed_samples: u32,
+    step_started: Instant,
+    /// When the last sample was taken, to integrate charge.
+    last_sample: Option<Instant>,
+}
+
+/// Runs the steps of a [`TestProfile`] on every battery of one bench.
+///
+/// Batteries advance independently: each one moves to its next step when its
+/// bench announces a successful completion of the current one, when a rest
+/// step's time is up, or when a sample reaches the step's target voltage.
+#[derive(Debug)]
+pub struct Sequencer {
+    steps: Vec<Step>,
+    batteries: Vec<Track>,
+    sample_interval: Duration,
+    test_id: i32,
+    limits: SafetyLimits,
+    clock: TestClock,
+}
+
+impl Sequencer {
+    pub fn new(battery_ids: &[u8], test_id: i32, profile: &TestProfile) -> Self {
+        let steps = profile.flatten();
+        let batteries = battery_ids
+            .iter()
+            .map(|&battery_id| Track {
+                progress: BatteryProgress {
+                    battery_id,
+                    step: 0,
+                    steps: steps.len() as u32,
+                    step_label: steps[0].label.clone(),
+                    state: BatteryState::Standby,
+                    outcome: SequenceOutcome::Running,
+                    step_started_at: get_current_time(),
+                    charge_mah: 0.0,
+                },
+                missed_samples: 0,
+                step_started: Instant::now(),
+                last_sample: None,
+            })
+            .collect();
+
+        Sequencer {
+            steps,
+            batteries,
+            sample_interval: SAMPLE_INTERVAL,
+            test_id,
+            limits: SafetyLimits::defaults(test_id),
+            clock: TestClock::starting_now(),
+        }
+    }
+
+    /// Picks up a sequence from the saved progress of its batteries.
+    pub fn resume(
+        saved: &[BatteryProgress],
+        test_id: i32,
+        profile: &TestProfile,
+        policy: ResumePolicy,
+    ) -> Result<Self, String> {
+        let steps = profile.flatten();
+        if let Some(progress) = saved.iter().find(|progress| {
+            progress.steps as usize != steps.len() || progress.step as usize >= steps.len()
+        }) {
+            return Err(format!(
+                "battery {} was at step {} of {}, the profile now has {} steps",
+                progress.battery_id,
+                progress.step + 1,
+                progress.steps,
+                steps.len()
+            ));
+        }
+
+        let now = Utc::now();
+        let batteries = saved
+            .iter()
+            .map(|progress| {
+                let elapsed = DateTime::parse_from_rfc3339(&progress.step_started_at)
+                    .ok()
+                    .and_then(|started| (now - started.with_timezone(&Utc)).to_std().ok())
+                    .unwrap_or_default();
+                Track {
+                    progress: progress.clone(),
+                    missed_samples: 0,
+                    step_started: Instant::now()
+                        .checked_sub(elapsed)
+                        .unwrap_or_else(Instant::now),
+                    last_sample: None,
+                }
+            })
+            .collect();
+
+        let mut sequencer = Sequencer {
+            steps,
+            batteries,
+            sample_interval: SAMPLE_INTERVAL,
+            test_id,
+            limits: SafetyLimits::defaults(test_id),
+            clock: TestClock::starting_now(),
+        };
+        if policy == ResumePolicy::RestartStep {
+            for index in 0..sequencer.batteries.len() {
+                if sequencer.batteries[index].progress.outcome == SequenceOutcome::Running {
+                    sequencer.reset_step(index);
+                }
+            }
+        }
+
+        Ok(sequencer)
+    }
+
+    pub fn with_limits(mut self, limits: SafetyLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    pub fn with_clock(mut self, clock: TestClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn with_sample_interval(mut self, sample_interval: Duration) -> Self {
+        self.sample_interval = sample_interval;
+        self
+    }
+
+    pub fn progress(&self) -> Vec<BatteryProgress> {
+        self.batteries
+            .iter()
+            .map(|track| track.progress.clone())
+            .collect()
+    }
+
+    fn is_running(&self) -> bool {
+        self.batteries
+            .iter()
+            .any(|track| track.progress.outcome == SequenceOutcome::Running)
+    }
+
+    /// Runs until every battery completed or failed, or until `stop` is set.
+    pub async fn run(
+        &mut self,
+        session: &BenchSession,
+        mut stop: watch::Receiver<bool>,
+        observer: &mut impl SequenceObserver,
+    ) -> Result<(), BenchError> {
+        // subscribe before the first state change so no completion is missed
+        let mut frames = session.subscribe();
+
+        for index in 0..self.batteries.len() {
+            if self.batteries[index].progress.outcome == SequenceOutcome::Running {
+                self.apply_state(session, index).await;
+            }
+        }
+        observer.progress(&self.progress());
+
+        let mut interval = tokio::time::interval(self.sample_interval);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        while self.is_running() {
+            tokio::select! {
+                _ = interval.tick() => {
+                    self.check_durations(session).await;
+                    self.sample(session, observer).await;
+                    // the charge moves with every sample
+                    observer.progress(&self.progress());
+                }
+                frame = frames.recv() => match frame {
+                    Ok(frame) if frame.command == Command::RequestCompletion => {
+                        if self.complete(session, &frame).await {
+                            observer.progress(&self.progress());
+                        }
+                    }
+                    Ok(_) | Err(RecvError::Lagged(_)) => {}
+                    Err(RecvError::Closed) => {
+                        return Err(BenchError::NotConnected {
+                            port: session.port().to_string(),
+                        })
+                    }
+                },
+                changed = stop.changed() => {
+                    if changed.is_err() || *stop.borrow() {
+                        self.stop(session).await;
+                        observer.progress(&self.progress());
+                        break;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn enter_step(&mut self, session: &BenchSession, index: usize) {
+        self.reset_step(index);
+        self.apply_state(session, index).await;
+    }
+
+    /// Starts the clock and the charge count of the battery's current step.
+    fn reset_step(&mut self, index: usize) {
+        let track = &mut self.batteries[index];
+        track.step_started = Instant::now();
+        track.last_sample = None;
+
+        let progress = &mut track.progress;
+        progress.step_started_at = get_current_time();
+        progress.charge_mah = 0.0;
+        if progress.outcome == SequenceOutcome::Running {
+            progress.step_label = self.steps[progress.step as usize].label.clone();
+        }
+    }
+
+    /// Puts the battery in the state of its current step, or in standby once
+    /// it is done.
+    async fn apply_state(&mut self, session: &BenchSession, index: usize) {
+        let progress = &mut self.batteries[index].progress;
+        let new_state = match progress.outcome {
+            SequenceOutcome::Running => self.steps[progress.step as usize].state,
+            _ => BatteryState::Standby,
+        };
+
+        match change_state(session, progress.battery_id, new_state).await {
+            Ok(()) => progress.state = new_state,
+            Err(error) => {
+                println!(
+                    "{}: battery {} could not enter {:?}: {}",
+                    session.port(),
+                    progress.battery_id,
+                    new_state,
+                    error
+                );
+                if progress.outcome == SequenceOutcome::Running {
+                    progress.outcome = SequenceOutcome::Failed;
+                }
+            }
+        }
+    }
+
+    /// Moves on from rest steps whose time is up, and fails steps that ran
+    /// past their time limit.
+    async fn check_durations(&mut self, session: &BenchSession) {
+        for index in 0..self.batteries.len() {
+            let track = &self.batteries[index];
+            if track.progress.outcome != SequenceOutcome::Running {
+                continue;
+            }
+
+            let step = &self.steps[track.progress.step as usize];
+            let elapsed = track.step_started.elapsed();
+            if step.rest.is_some_and(|rest| elapsed >= rest) {
+                self.advance(session, index).await;
+            } else if step
+                .limits
+                .max_duration_s
+                .is_some_and(|limit| elapsed >= Duration::from_secs(limit as u64))
+            {
+                println!(
+                    "{}: battery {} did not finish {} in time",
+                    session.port(),
+                    track.progress.battery_id,
+                    step.label
+                );
+                self.fail(session, index).await;
+            }
+        }
+    }
+
+    /// Requests data from every running battery, counts the charge it moved
+    /// and checks it against the step's limits.
+    async fn sample(&mut self, session: &BenchSession, observer: &mut impl SequenceObserver) {
+        for index in 0..self.batteries.len() {
+            let track = &mut self.batteries[index];
+            if track.progress.outcome != SequenceOutcome::Running {
+                continue;
+            }
+
+            let log = match request_data(session, track.progress.battery_id).await {
+                Ok(log) => log,
+                Err(error) => {
+                    track.missed_samples += 1;
+                    println!(
+                        "{}: no data from battery {}: {}",
+                        session.port(),
+                        track.progress.battery_id,
+                        error
+                    );
+
+                    if track.missed_samples >= MAX_MISSED_SAMPLES {
+                        track.progress.outcome = SequenceOutcome::Failed;
+                    }
+                    continue;
+                }
+            };
+
+            track.missed_samples = 0;
+            let now = Instant::now();
+            if let Some(last_sample) = track.last_sample {
+                let hours = now.duration_since(last_sample).as_secs_f32() / 3600.0;
+                track.progress.charge_mah += log.current.abs() * hours;
+            }
+            track.last_sample = Some(now);
+            let log = BatteryLog {
+                test_id: self.test_id,
+                elapsed_s: self.clock.elapsed_s(),
+                state: format!("{:?}", track.progress.state),
+                status: track.progress.label(),
+                ..log
+            };
+
+            if let Some(alarm) = enforce(session, &self.limits, &log, Some(self.test_id)).await {
+                let progress = &mut self.batteries[index].progress;
+                progress.outcome = SequenceOutcome::Aborted;
+                if alarm.standby {
+                    progress.state = BatteryState::Standby;
+                }
+                observer.sample(log);
+                observer.alarm(&alarm);
+                continue;
+            }
+
+            let step = &self.steps[self.batteries[index].progress.step as usize];
+            let too_hot = step
+                .limits
+                .max_temperature_c
+                .is_some_and(|limit| log.battery_temperature > limit);
+            let reached_voltage =
+                step.limits
+                    .until_voltage_mv
+                    .is_some_and(|target| match step.state {
+                        BatteryState::Charge => log.voltage >= target as f32,
+                        BatteryState::Discharge => log.voltage <= target as f32,
+                        BatteryState::Standby => false,
+                    });
+
+            observer.sample(log);
+
+            if too_hot {
+                println!(
+                    "{}: battery {} is over the temperature limit of {}",
+                    session.port(),
+                    self.batteries[index].progress.battery_id,
+                    step.label
+                );
+                self.fail(session, index).await;
+            } else if reached_voltage {
+                self.advance(session, index).await;
+            }
+        }
+    }
+
+    /// Handles a RequestCompletion frame. Returns whether a battery moved.
+    async fn complete(&mut self, session: &BenchSession, frame: &BatteryCommand) -> bool {
+        let Some(index) = self.batteries.iter().position(|track| {
+            track.progress.battery_id == frame.battery_id
+                && track.progress.outcome == SequenceOutcome::Running
+        }) else {
+            return false;
+        };
+
+        let status = match frame.parse_completion(&frame.payload) {
+            Ok(status) => status,
+            Err(error) => {
+                println!("{}: {}", session.port(), error);
+                return false;
+            }
+        };
+
+        let progress = &self.batteries[index].progress;
+        let expected = match self.steps[progress.step as usize].state {
+            BatteryState::Charge => CompletionPhase::Charge,
+            BatteryState::Discharge => CompletionPhase::Discharge,
+            BatteryState::Standby => return false,
+        };
+        // a late completion of an earlier step
+        if status.phase != expected {
+            return false;
+        }
+
+        match status.outcome {
+            CompletionOutcome::InProgress => return false,
+            CompletionOutcome::Failed => self.fail(session, index).await,
+            CompletionOutcome::Success => self.advance(session, index).await,
+        }
+        true
+    }
+
+    /// Moves a battery to its next step, or completes it after the last one.
+    async fn advance(&mut self, session: &BenchSession, index: usize) {
+        let progress = &mut self.batteries[index].progress;
+        if progress.step as usize + 1 == self.steps.len() {
+            progress.outcome = SequenceOutcome::Complete;
+            self.apply_state(session, index).await;
+        } else {
+            progress.step += 1;
+            self.enter_step(session, index).await;
+        }
+    }
+
+    /// Gives up on a battery, keeping where it stopped.
+    async fn fail(&mut self, session: &BenchSession, index: usize) {
+        self.batteries[index].progress.outcome = SequenceOutcome::Failed;
+        self.apply_state(session, index).await;
+    }
+
+    async fn stop(&mut self, session: &BenchSession) {
+        for index in 0..self.batteries.len() {
+            if self.batteries[index].progress.outcome == SequenceOutcome::Running {
+                self.batteries[index].progress.outcome = SequenceOutcome::Stopped;
+                self.apply_state(session, index).await;
+            }
+        }
+    }
+}
+
+/// A sequence running on a bench, kept in [`AppState`] by port.
+pub struct RunningSequence {
+    pub test_id: i32,
+    stop: watch::Sender<bool>,
+    progress: Arc<Mutex<Vec<BatteryProgress>>>,
+}
+
+/// Stores samples under the test and streams them to the frontend, and
+/// saves the progress so the sequence can be resumed after a restart.
+struct AppObserver {
+    app: AppHandle,
+    /// Stores samples and progress without holding up the bench. Without one
+    /// they are written right away.
+    writer: Option<LogWriter>,
+    port: String,
+    test_id: i32,
+    on_event: Channel<BatteryLog>,
+    progress: Arc<Mutex<Vec<BatteryProgress>>>,
+}
+
+impl SequenceObserver for AppObserver {
+    fn sample(&mut self, log: BatteryLog) {
+        let log = match &self.writer {
+            Some(writer) => {
+                writer.submit(log.clone());
+                log
+            }
+            None => {
+                let state = self.app.state::<Mutex<AppState>>();
+                insert_battery_log(state, log.clone()).unwrap_or_else(|error| {
+                    println!("Failed to store battery log: {}", error);
+                    log
+                })
+            }
+        };
+
+        let _ = self.on_event.send(log);
+    }
+
+    fn progress(&mut self, progress: &[BatteryProgress]) {
+        if let Ok(mut current) = self.progress.lock() {
+            *current = progress.to_vec();
+        }
+
+        let states: Vec<SequenceState> = progress
+            .iter()
+            .map(|progress| progress.to_state(self.test_id, &self.port))
+            .collect();
+        if let Some(writer) = &self.writer {
+            writer.save_progress(states);
+            return;
+        }
+
+        let state = self.app.state::<Mutex<AppState>>();
+        let saved = match state.lock() {
+            Ok(mut state) => connection(&mut state)
+                .map_err(|e| e.to_string())
+                .and_then(|conn| save_sequence_states(conn, &states).map_err(|e| e.to_string())),
+            Err(error) => Err(error.to_string()),
+        };
+        if let Err(error) = saved {
+            println!("Failed to save sequence progress: {}", error);
+        }
+    }
+
+    fn alarm(&mut self, alarm: &Alarm) {
+        report_alarm(&self.app, alarm);
+    }
+}
+
+/// Starts the qualification sequence on `port` for `battery_ids`, or for
+/// every battery currently pinging on it when the list is empty.
+#[tauri::command]
+#[specta::specta]
+pub fn start_sequence(
+    app: AppHandle,
+    state: State<'_, Mutex<AppState>>,
+    port: String,
+    battery_ids: Vec<u8>,
+    test_id: i32,
+    on_event: Channel<BatteryLog>,
+) -> Result<Vec<BatteryProgress>, BenchError> {
+    let session = session_for(&state, &port)?;
+
+    let mut battery_ids = match battery_ids.is_empty() {
+        true => session.known_ids(),
+        false => battery_ids,
+    };
+    battery_ids.sort_unstable();
+    if battery_ids.is_empty() {
+        return Err(BenchError::NoBatteries { port });
+    }
+
+    let mut state = state.lock().map_err(|e| BenchError::Io {
+        message: e.to_string(),
+    })?;
+    if state.sequences.contains_key(&port) {
+        return Err(BenchError::SequenceRunning { port });
+    }
+
+    let conn = connection(&mut state)?;
+    let profile =
+        profile_for_test(conn, test_id).map_err(|message| BenchError::Database { message })?;
+    let limits = limits_for_test(conn, test_id)?;
+    let clock = clock_for_test(conn, test_id)?;
+    clear_sequence_states(conn, test_id)?;
+    let sequencer = Sequencer::new(&battery_ids, test_id, &profile)
+        .with_limits(limits)
+        .with_clock(clock);
+
+    Ok(spawn_sequence(
+        app, &mut state, port, session, sequencer, on_event,
+    ))
+}
+
+/// Registers `sequencer` as the sequence of `port` and runs it in the
+/// background. Returns the progress it starts from.
+fn spawn_sequence(
+    app: AppHandle,
+    state: &mut AppState,
+    port: String,
+    session: Arc<BenchSession>,
+    mut sequencer: Sequencer,
+    on_event: Channel<BatteryLog>,
+) -> Vec<BatteryProgress> {
+    let test_id = sequencer.test_id;
+    let initial = sequencer.progress();
+    let progress = Arc::new(Mutex::new(initial.clone()));
+    let (stop, stop_rx) = watch::channel(false);
+
+    state.sequences.insert(
+        port.clone(),
+        RunningSequence {
+            test_id,
+            stop,
+            progress: progress.clone(),
+        },
+    );
+
+    let writer = state.log_writer.clone();
+    let mut observer = AppObserver {
+        app: app.clone(),
+        writer: writer.clone(),
+        port: port.clone(),
+        test_id,
+        on_event,
+        progress,
+    };
+
+    tauri::async_runtime::spawn(async move {
+        if let Err(error) = sequencer.run(&session, stop_rx, &mut observer).await {
+            println!("{}: sequence aborted: {}", port, error);
+        }
+
+        // the analysis needs the last samples stored
+        if let Some(writer) = writer {
+            let flushed =
+                tauri::async_runtime::spawn_blocking(move || writer.flush(WRITER_FLUSH_TIMEOUT))
+                    .await
+                    .unwrap_or(false);
+            if !flushed {
+                println!("{}: samples of test {} still being stored", port, test_id);
+            }
+        }
+
+        let state = app.state::<Mutex<AppState>>();
+        let mut state = match state.lock() {
+            Ok(state) => state,
+            Err(_) => return,
+        };
+        state.sequences.remove(&port);
+
+        // results are there without the operator asking for them
+        let analyzed = connection(&mut state)
+            .map_err(|e| e.to_string())
+            .and_then(|conn| analyze_test(conn, test_id).map_err(|e| e.to_string()));
+        if let Err(error) = analyzed {
+            println!("{}: failed to analyze test {}: {}", port, test_id, error);
+        }
+    });
+
+    initial
+}
+
+/// Sequences left running by a previous run of the application, except the
+/// ones running again.
+#[tauri::command]
+#[specta::specta]
+pub fn get_interrupted_sequences(
+    state: State<'_, Mutex<AppState>>,
+) -> Result<Vec<InterruptedSequence>, BenchError> {
+    let mut state = state.lock().map_err(|e| BenchError::Io {
+        message: e.to_string(),
+    })?;
+
+    let mut interrupted: Vec<InterruptedSequence> = Vec::new();
+    for saved in interrupted_sequence_states(connection(&mut state)?)? {
+        let running = state
+            .sequences
+            .get(&saved.port)
+            .is_some_and(|sequence| sequence.test_id == saved.test_id);
+        if running {
+            continue;
+        }
+
+        let (port, test_id) = (saved.port.clone(), saved.test_id);
+        let progress = BatteryProgress::try_from(saved)
+            .map_err(|message| BenchError::CannotResume { test_id, message })?;
+        match interrupted.last_mut() {
+            Some(last) if last.port == port && last.test_id == test_id => {
+                last.batteries.push(progress)
+            }
+            _ => interrupted.push(InterruptedSequence {
+                port,
+                test_id,
+                batteries: vec![progress],
+            }),
+        }
+    }
+
+    Ok(interrupted)
+}
+
+/// Reconnects to the bench on `port`, gives the batteries of the interrupted
+/// sequence of `test_id` their IDs back and carries on from where each one
+/// was, following `policy`.
+#[tauri::command]
+#[specta::specta]
+pub async fn resume_sequence(
+    app: AppHandle,
+    state: State<'_, Mutex<AppState>>,
+    port: String,
+    test_id: i32,
+    policy: ResumePolicy,
+    on_event: Channel<BatteryLog>,
+) -> Result<Vec<BatteryProgress>, BenchError> {
+    let (saved, profile, limits, clock, auto_assign) = {
+        let mut state = state.lock().map_err(|e| BenchError::Io {
+            message: e.to_string(),
+        })?;
+        if state.sequences.contains_key(&port) {
+            return Err(BenchError::SequenceRunning { port });
+        }
+
+        let conn = connection(&mut state)?;
+        let saved = sequence_states_for(conn, test_id, &port)?;
+        let profile =
+            profile_for_test(conn, test_id).map_err(|message| BenchError::Database { message })?;
+        let limits = limits_for_test(conn, test_id)?;
+        let clock = clock_for_test(conn, test_id)?;
+
+        // the benches have to wait for their old IDs instead of new ones
+        let auto_assign = state
+            .sessions
+            .get(&port)
+            .is_none_or(|session| session.auto_assign());
+        (saved, profile, limits, clock, auto_assign)
+    };
+    let session = open_session(&app, &state, &port, false)?;
+    session.set_auto_assign(false);
+
+    let resumed = async {
+        let saved = saved
+            .into_iter()
+            .map(BatteryProgress::try_from)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|message| BenchError::CannotResume { test_id, message })?;
+        let battery_ids: Vec<u8> = saved
+            .iter()
+            .filter(|progress| progress.outcome == SequenceOutcome::Running)
+            .map(|progress| progress.battery_id)
+            .collect();
+        if battery_ids.is_empty() {
+            return Err(BenchError::CannotResume {
+                test_id,
+                message: format!("no battery was interrupted on {}", port),
+            });
+        }
+
+        let sequencer = Sequencer::resume(&saved, test_id, &profile, policy)
+            .map_err(|message| BenchError::CannotResume { test_id, message })?
+            .with_limits(limits)
+            .with_clock(clock);
+        reclaim_battery_ids(&state, &session, &battery_ids).await?;
+        Ok(sequencer)
+    }
+    .await;
+    session.set_auto_assign(auto_assign);
+    let sequencer = resumed?;
+
+    let mut state = state.lock().map_err(|e| BenchError::Io {
+        message: e.to_string(),
+    })?;
+    if state.sequences.contains_key(&port) {
+        return Err(BenchError::SequenceRunning { port });
+    }
+
+    Ok(spawn_sequence(
+        app, &mut state, port, session, sequencer, on_event,
+    ))
+}
+
+/// Gives up on an interrupted sequence, leaving its batteries as stopped.
+#[tauri::command]
+#[specta::specta]
+pub fn discard_interrupted_sequence(
+    state: State<'_, Mutex<AppState>>,
+    port: String,
+    test_id: i32,
+) -> Result<(), BenchError> {
+    let mut state = state.lock().map_err(|e| BenchError::Io {
+        message: e.to_string(),
+    })?;
+
+    stop_sequence_states(connection(&mut state)?, test_id, &port)?;
+    Ok(())
+}
+
+#[tauri::command]
+#[specta::specta]
+pub fn stop_sequence(state: State<'_, Mutex<AppState>>, port: String) -> Result<(), BenchError> {
+    let state = state.lock().map_err(|e| BenchError::Io {
+        message: e.to_string(),
+    })?;
+
+    match state.sequences.get(&port) {
+        Some(sequence) => {
+            let _ = sequence.stop.send(true);
+            Ok(())
+        }
+        None => Err(BenchError::NoSequence { port }),
+    }
+}
+
+#[tauri::command]
+#[specta::specta]
+pub fn get_sequence_progress(
+    state: State<'_, Mutex<AppState>>,
+    port: String,
+) -> Result<Vec<BatteryProgress>, BenchError> {
+    let state = state.lock().map_err(|e| BenchError::Io {
+        message: e.to_string(),
+    })?;
+
+    let sequence = state
+        .sequences
+        .get(&port)
+        .ok_or(BenchError::NoSequence { port })?;
+    let progress = sequence.progress.lock().map_err(|e| BenchError::Io {
+        message: e.to_string(),
+    })?;
+
+    Ok(progress.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        database::profile::ProfileFormat,
+        serial::{
+            interlock::Quantity,
+            simulator::{SimulatorConfig, SimulatorHandle},
+            transport::memory_pipe,
+        },
+    };
+
+    #[derive(Default)]
+    struct Recorder {
+        samples: Vec<BatteryLog>,
+        progress: Vec<BatteryProgress>,
+        alarms: Vec<Alarm>,
+    }
+
+    impl SequenceObserver for Recorder {
+        fn sample(&mut self, log: BatteryLog) {
+            self.samples.push(log);
+        }
+
+        fn progress(&mut self, progress: &[BatteryProgress]) {
+            self.progress = progress.to_vec();
+        }
+
+        fn alarm(&mut self, alarm: &Alarm) {
+            self.alarms.push(alarm.clone());
+        }
+    }
+
+    async fn assign(session: &BenchSession, battery_id: u8) {
+        let mut pings = session.subscribe();
+        session
+            .send(&BatteryCommand {
+                command: Command::AssignId,
+                battery_id,
+                payload: vec![],
+            })
+            .await
+            .unwrap();
+        while pings.recv().await.unwrap().battery_id != battery_id {}
+    }
+
+    #[tokio::test]
+    async fn test_full_sequence() {
+        let (gui, bench) = memory_pipe();
+        let _simulator = SimulatorHandle::spawn(
+            SimulatorConfig {
+                cells: 2,
+                time_scale: 20_000.0,
+                ping_interval: Duration::from_millis(100),
+                heartbeat_timeout: None,
+                ..SimulatorConfig::default()
+            },
+            Box::new(bench),
+        );
+        let session = BenchSession::from_transport("simulator", Box::new(gui.clone())).unwrap();
+        assign(&session, 4).await;
+        assign(&session, 9).await;
+
+        let mut sequencer = Sequencer::new(&[4, 9], 12, &TestProfile::qualification())
+            .with_sample_interval(Duration::from_millis(20));
+        let (_stop, stop_rx) = watch::channel(false);
+        let mut recorder = Recorder::default();
+
+        tokio::time::timeout(
+            Duration::from_secs(30),
+            sequencer.run(&session, stop_rx, &mut recorder),
+        )
+        .await
+        .expect("sequence did not finish")
+        .unwrap();
+
+        for progress in &recorder.progress {
+            assert_eq!(progress.outcome, SequenceOutcome::Complete);
+            assert_eq!(progress.state, BatteryState::Standby);
+        }
+        for battery_id in [4, 9] {
+            let samples: Vec<_> = recorder
+                .samples
+                .iter()
+                .filter(|log| log.id == battery_id)
+                .collect();
+            assert!(samples.iter().all(|log| log.test_id == 12));
+            assert!(samples.iter().all(|log| !log.recorded_at.is_empty()));
+            assert!(samples
+                .windows(2)
+                .all(|pair| pair[0].elapsed_s <= pair[1].elapsed_s));
+            assert!(samples.iter().any(|log| log.state == "Discharge"));
+            assert!(samples.iter().any(|log| log.status == "Top-up"));
+        }
+
+        session.close();
+        gui.close();
+    }
+
+    #[tokio::test]
+    async fn test_stop_sequence() {
+        let (gui, bench) = memory_pipe();
+        let _simulator = SimulatorHandle::spawn(
+            SimulatorConfig {
+                heartbeat_timeout: None,
+                ..SimulatorConfig::default()
+            },
+            Box::new(bench),
+        );
+        let session = BenchSession::from_transport("simulator", Box::new(gui.clone())).unwrap();
+        assign(&session, 2).await;
+
+        let mut sequencer = Sequencer::new(&[2], 1, &TestProfile::qualification())
+            .with_sample_interval(Duration::from_millis(20));
+        let (stop, stop_rx) = watch::channel(false);
+        let mut recorder = Recorder::default();
+
+        let stopper = async {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            stop.send(true).unwrap();
+        };
+        let (result, _) = tokio::join!(sequencer.run(&session, stop_rx, &mut recorder), stopper);
+        result.unwrap();
+
+        assert_eq!(recorder.progress[0].outcome, SequenceOutcome::Stopped);
+        assert_eq!(recorder.progress[0].state, BatteryState::Standby);
+        assert!(!recorder.samples.is_empty());
+
+        session.close();
+        gui.close();
+    }
+
+    #[tokio::test]
+    async fn test_profile_limits() {
+        let (gui, bench) = memory_pipe();
+        let _simulator = SimulatorHandle::spawn(
+            SimulatorConfig {
+                cells: 2,
+                time_scale: 20_000.0,
+                ping_interval: Duration::from_millis(100),
+                heartbeat_timeout: None,
+                ..SimulatorConfig::default()
+            },
+            Box::new(bench),
+        );
+        let session = BenchSession::from_transport("simulator", Box::new(gui.clone())).unwrap();
+        assign(&session, 1).await;
+        assign(&session, 2).await;
+
+        // battery 1 rests then charges, battery 2 is over a limit no cell
+        // can stay under
+        let rest_then_charge = TestProfile::parse(
+            r#"{"name": "rest", "steps": [
+                {"kind": "rest", "duration_s": 1},
+                {"kind": "charge"}
+            ]}"#,
+            ProfileFormat::Json,
+        )
+        .unwrap();
+        let too_hot = TestProfile::parse(
+            r#"{"name": "hot", "steps": [{"kind": "discharge", "max_temperature_c": 0}]}"#,
+            ProfileFormat::Json,
+        )
+        .unwrap();
+
+        for (battery_id, profile, outcome) in [
+            (1, rest_then_charge, SequenceOutcome::Complete),
+            (2, too_hot, SequenceOutcome::Failed),
+        ] {
+            let mut sequencer = Sequencer::new(&[battery_id], 1, &profile)
+                .with_sample_interval(Duration::from_millis(20));
+            let (_stop, stop_rx) = watch::channel(false);
+            let mut recorder = Recorder::default();
+
+            tokio::time::timeout(
+                Duration::from_secs(10),
+                sequencer.run(&session, stop_rx, &mut recorder),
+            )
+            .await
+            .expect("sequence did not finish")
+            .unwrap();
+
+            assert_eq!(recorder.progress[0].outcome, outcome);
+            assert_eq!(recorder.progress[0].state, BatteryState::Standby);
+        }
+
+        session.close();
+        gui.close();
+    }
+
+    #[tokio::test]
+    async fn test_interlock_aborts() {
+        let (gui, bench) = memory_pipe();
+        let simulator = SimulatorHandle::spawn(
+            SimulatorConfig {
+                heartbeat_timeout: None,
+                ..SimulatorConfig::default()
+            },
+            Box::new(bench),
+        );
+        let session = BenchSession::from_transport("simulator", Box::new(gui.clone())).unwrap();
+        assign(&session, 5).await;
+
+        let limits = SafetyLimits {
+            max_mosfet_temperature_c: Some(0.0),
+            ..SafetyLimits::defaults(3)
+        };
+        let mut sequencer = Sequencer::new(&[5], 3, &TestProfile::qualification())
+            .with_sample_interval(Duration::from_millis(20))
+            .with_limits(limits);
+        let (_stop, stop_rx) = watch::channel(false);
+        let mut recorder = Recorder::default();
+
+        tokio::time::timeout(
+            Duration::from_secs(5),
+            sequencer.run(&session, stop_rx, &mut recorder),
+        )
+        .await
+        .expect("interlock did not trip")
+        .unwrap();
+
+        assert_eq!(recorder.progress[0].outcome, SequenceOutcome::Aborted);
+        assert_eq!(recorder.progress[0].state, BatteryState::Standby);
+        assert_eq!(recorder.alarms.len(), 1);
+        assert_eq!(recorder.alarms[0].quantity, Quantity::MosfetTemperature);
+        assert_eq!(recorder.alarms[0].test_id, Some(3));
+        assert!(recorder.alarms[0].standby);
+
+        session.close();
+        gui.close();
+        drop(simulator);
+    }
+
+    #[tokio::test]
+    async fn test_resume_sequence() {
+        let (gui, bench) = memory_pipe();
+        let _simulator = SimulatorHandle::spawn(
+            SimulatorConfig {
+                time_scale: 20_000.0,
+                heartbeat_timeout: None,
+                ..SimulatorConfig::default()
+            },
+            Box::new(bench),
+        );
+        let session = BenchSession::from_transport("simulator", Box::new(gui.clone())).unwrap();
+        assign(&session, 6).await;
+
+        // interrupted in the last discharge
+        let profile = TestProfile::qualification();
+        let mut saved = Sequencer::new(&[6], 8, &profile).progress();
+        saved[0].step = 5;
+        saved[0].step_label = "Cycle 3".to_string();
+        saved[0].state = BatteryState::Discharge;
+        saved[0].charge_mah = 250.0;
+
+        let mut sequencer = Sequencer::resume(&saved, 8, &profile, ResumePolicy::Continue)
+            .unwrap()
+            .with_sample_interval(Duration::from_millis(20));
+        assert_eq!(sequencer.progress()[0].charge_mah, 250.0);
+        let (_stop, stop_rx) = watch::channel(false);
+        let mut recorder = Recorder::default();
+
+        tokio::time::timeout(
+            Duration::from_secs(10),
+            sequencer.run(&session, stop_rx, &mut recorder),
+        )
+        .await
+        .expect("sequence did not finish")
+        .unwrap();
+
+        assert_eq!(recorder.progress[0].outcome, SequenceOutcome::Complete);
+        assert_eq!(recorder.progress[0].step, 6);
+        assert!(!recorder.samples.is_empty());
+        assert!(recorder
+            .samples
+            .iter()
+            .all(|log| log.status == "Cycle 3" || log.status == "Top-up"));
+
+        session.close();
+        gui.close();
+    }
+
+    #[test]
+    fn test_resume_policy() {
+        let profile = TestProfile::qualification();
+        let mut saved = Sequencer::new(&[1], 2, &profile).progress();
+        saved[0].step = 2;
+        saved[0].step_started_at = "2025-07-26T09:00:00+00:00".to_string();
+        saved[0].charge_mah = 100.0;
+
+        let restarted = Sequencer::resume(&saved, 2, &profile, ResumePolicy::RestartStep)
+            .unwrap()
+            .progress();
+        assert_eq!(restarted[0].step, 2);
+        assert_eq!(restarted[0].charge_mah, 0.0);
+        assert_ne!(restarted[0].step_started_at, saved[0].step_started_at);
+
+        // the profile changed since
+        let shorter = TestProfile::parse(
+            r#"{"name": "short", "steps": [{"kind": "charge"}]}"#,
+            ProfileFormat::Json,
+        )
+        .unwrap();
+        assert!(Sequencer::resume(&saved, 2, &shorter, ResumePolicy::Continue).is_err());
+    }
+
+    #[test]
+    fn test_saved_progress() {
+        let mut progress = Sequencer::new(&[7], 3, &TestProfile::qualification())
+            .progress()
+            .remove(0);
+        progress.state = BatteryState::Charge;
+        progress.charge_mah = 12.5;
+
+        let saved = progress.to_state(3, "COM4");
+        assert_eq!(saved.state, "Charge");
+        assert_eq!(saved.outcome, "Running");
+        assert_eq!(BatteryProgress::try_from(saved.clone()), Ok(progress));
+
+        let corrupt = SequenceState {
+            outcome: "Paused".to_string(),
+            ..saved
+        };
+        assert!(BatteryProgress::try_from(corrupt).is_err());
+    }
+
+    #[test]
+    fn test_progress_label() {
+        let mut progress = Sequencer::new(&[1], 1, &TestProfile::qualification())
+            .progress()
+            .remove(0);
+        assert_eq!(progress.label(), "Cycle 1");
+        progress.step_label = "Top-up".to_string();
+        assert_eq!(progress.label(), "Top-up");
+        progress.outcome = SequenceOutcome::Failed;
+        assert_eq!(progress.label(), "Failed");
+    }
+}
@@ -1,17 +1,127 @@
+use chrono::Utc;
 use crc::Crc;
 use serde::{Deserialize, Serialize};
 use serialport::available_ports;
 use specta::Type;
-use std::io::{Read, Write};
-use std::time::Duration;
+use std::io::Read;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
 use std::{thread, vec};
+use tauri::State;
+use thiserror::Error;
 
 use crate::database::models::BatteryLog;
+use crate::database::sqlite::DatabaseError;
+use crate::serial::session::session_for;
+use crate::state::AppState;
 
 const DELIMITER: u8 = 0xB3;
 
 const CRC8_AUTOSAR: Crc<u8> = Crc::<u8>::new(&crc::CRC_8_AUTOSAR);
 
+pub const RESPONSE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Battery ID a bench pings with before it has been assigned one.
+pub const UNASSIGNED_ID: u8 = 0xFF;
+
+/// Errors in the content of a single frame.
+#[derive(Error, Debug, Clone, PartialEq, Eq, Type, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "details")]
+pub enum ProtocolError {
+    #[error("Packet too short: {length} bytes")]
+    ShortFrame { length: u32 },
+    #[error("Invalid CRC: expected {expected}, got {received}")]
+    CrcMismatch { expected: u8, received: u8 },
+    #[error("Unknown command ID: {id}")]
+    UnknownCommand { id: u8 },
+    #[error("Invalid {command:?} payload length: expected {expected}, got {received}")]
+    PayloadLength {
+        command: Command,
+        expected: u32,
+        received: u32,
+    },
+    #[error("{received:?} frame parsed as {expected:?}")]
+    WrongCommand {
+        expected: Command,
+        received: Command,
+    },
+    #[error("Invalid completion status flags: {flags:#04x}")]
+    InvalidCompletion { flags: u8 },
+}
+
+/// Errors of a request/response exchange with a bench.
+#[derive(Error, Debug, Clone, PartialEq, Eq, Type, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "details")]
+pub enum BenchError {
+    #[error(transparent)]
+    Protocol(#[from] ProtocolError),
+    #[error("Unexpected response for battery {received}, expected battery {expected}")]
+    UnexpectedBatteryId { expected: u8, received: u8 },
+    #[error("Unexpected response: got {received:?}, expected {expected:?}")]
+    WrongReply {
+        expected: Command,
+        received: Command,
+    },
+    #[error("No response after {waited_ms} ms")]
+    Timeout { waited_ms: u32 },
+    #[error("{command:?} is sent by the bench on its own and has no reply to wait for")]
+    NoReply { command: Command },
+    #[error("I/O error: {message}")]
+    Io { message: String },
+    #[error("Bench on {port} is not connected")]
+    NotConnected { port: String },
+    #[error("All battery IDs are taken")]
+    NoFreeId,
+    #[error("Bench did not claim battery ID {battery_id}")]
+    UnclaimedId { battery_id: u8 },
+    #[error("Battery ID {battery_id} is not reserved")]
+    IdNotReserved { battery_id: u8 },
+    #[error("Battery ID {battery_id} is already used by a connected bench")]
+    IdInUse { battery_id: u8 },
+    #[error("No batteries to run on {port}")]
+    NoBatteries { port: String },
+    #[error("A sequence is already running on {port}")]
+    SequenceRunning { port: String },
+    #[error("No sequence is running on {port}")]
+    NoSequence { port: String },
+    #[error("Database error: {message}")]
+    Database { message: String },
+    #[error("Cannot resume test {test_id}: {message}")]
+    CannotResume { test_id: i32, message: String },
+}
+
+impl From<std::io::Error> for BenchError {
+    fn from(error: std::io::Error) -> Self {
+        BenchError::Io {
+            message: error.to_string(),
+        }
+    }
+}
+
+impl From<diesel::result::Error> for BenchError {
+    fn from(error: diesel::result::Error) -> Self {
+        BenchError::Database {
+            message: error.to_string(),
+        }
+    }
+}
+
+impl From<DatabaseError> for BenchError {
+    fn from(error: DatabaseError) -> Self {
+        BenchError::Database {
+            message: error.to_string(),
+        }
+    }
+}
+
+impl From<serialport::Error> for BenchError {
+    fn from(error: serialport::Error) -> Self {
+        BenchError::Io {
+            message: error.to_string(),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Type, Serialize, Deserialize)]
 pub enum Command {
     Ping = 0x00,
@@ -27,6 +137,22 @@ impl Command {
     fn id(&self) -> u8 {
         *self as u8
     }
+    pub fn from_id(id: u8) -> Option<Command> {
+        match id {
+            0x00 => Some(Command::Ping),
+            0x01 => Some(Command::AssignId),
+            0x02 => Some(Command::RequestData),
+            0x04 => Some(Command::SetCharge),
+            0x05 => Some(Command::SetDischarge),
+            0x06 => Some(Command::SetStandBy),
+            0x07 => Some(Command::RequestCompletion),
+            _ => None,
+        }
+    }
+    /// Frames the bench sends on its own rather than as a reply to a request.
+    pub fn is_unsolicited(&self) -> bool {
+        matches!(self, Command::Ping | Command::RequestCompletion)
+    }
     pub fn response_lenght(&self) -> usize {
         match self {
             Command::RequestData => 16,
@@ -37,14 +163,8 @@ impl Command {
 }
 
 #[derive(Debug)]
-struct PingPayload {
-    bench_status: u8,
-}
-
-#[derive(Debug)]
-struct AnnounceCompletionPayload {
-    bench_status: u8,
-    experiment_status: u8,
+pub struct PingPayload {
+    pub bench_status: u8,
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -74,62 +194,70 @@ impl BatteryCommand {
         buffer
     }
 
-    pub fn decode(packet: &[u8]) -> Result<BatteryCommand, String> {
-        if packet.len() < 3 {
-            return Err("Packet too short".to_string());
+    pub fn decode(packet: &[u8]) -> Result<BatteryCommand, ProtocolError> {
+        if packet.len() < 4 {
+            return Err(ProtocolError::ShortFrame {
+                length: packet.len() as u32,
+            });
         }
 
         let command_id = packet[1];
         let battery_id = packet[2];
         let payload = &packet[3..packet.len() - 1];
 
-        let received_crc = *packet.last().ok_or("Missing CRC")?;
+        let received_crc = packet[packet.len() - 1];
         let calculated_crc = Self::checksum(&packet[0..packet.len() - 1]);
 
         if calculated_crc != received_crc {
-            return Err(format!(
-                "Invalid CRC: expected {calculated_crc}, got {received_crc}"
-            ));
+            return Err(ProtocolError::CrcMismatch {
+                expected: calculated_crc,
+                received: received_crc,
+            });
         }
 
-        let command = match command_id {
-            0x00 => Command::Ping,
-            0x01 => Command::AssignId,
-            0x02 => Command::RequestData,
-            0x04 => Command::SetCharge,
-            0x05 => Command::SetDischarge,
-            0x06 => Command::SetStandBy,
-            0x07 => Command::RequestCompletion,
-            _ => return Err(format!("Unknown command ID: {command_id}")),
-        };
+        let command =
+            Command::from_id(command_id).ok_or(ProtocolError::UnknownCommand { id: command_id })?;
 
         Ok(BatteryCommand {
-            command: command,
-            battery_id: battery_id,
+            command,
+            battery_id,
             payload: payload.to_vec(),
         })
     }
 
-    pub fn parse_ping_payload(&self, payload: &[u8]) -> Result<PingPayload, String> {
-        if self.command != Command::Ping {
-            return Err("parse_ping_payload called on wrong command".into());
+    fn expect_payload(
+        &self,
+        command: Command,
+        payload: &[u8],
+        expected: usize,
+    ) -> Result<(), ProtocolError> {
+        if self.command != command {
+            return Err(ProtocolError::WrongCommand {
+                expected: command,
+                received: self.command,
+            });
         }
-        if payload.len() != 1 {
-            return Err("Invalid Ping payload length".into());
+        if payload.len() != expected {
+            return Err(ProtocolError::PayloadLength {
+                command,
+                expected: expected as u32,
+                received: payload.len() as u32,
+            });
         }
+        Ok(())
+    }
+
+    pub fn parse_ping_payload(&self, payload: &[u8]) -> Result<PingPayload, ProtocolError> {
+        self.expect_payload(Command::Ping, payload, 1)?;
         Ok(PingPayload {
             bench_status: payload[0],
         })
     }
 
-    pub fn parse_assign_id(&self, payload: &[u8]) -> Result<u8, String> {
-        if self.command != Command::AssignId {
-            return Err("parse_assign_id called on wrong command".into());
-        }
-        if payload.len() != 1 {
-            return Err("Invalid AssignId payload length".into());
-        }
-        Ok(payload[3])
+    /// The ID travels in the battery ID byte, the frame has no payload.
+    pub fn parse_assign_id(&self) -> Result<u8, ProtocolError> {
+        self.expect_payload(Command::AssignId, &self.payload, 0)?;
+        Ok(self.battery_id)
     }
 
     pub fn parse_request_data(
@@ -137,82 +265,322 @@ impl BatteryCommand {
         payload: &[u8],
         id: u8,
         port: String,
-    ) -> Result<BatteryLog, String> {
-        if self.command != Command::RequestData {
-            return Err("parse_request_data called on wrong command".into());
-        }
-        if payload.len() != 12 {
-            return Err("Invalid RequestData payload length".into());
-        }
-
-        let battery_temperature = i16::from_be_bytes([payload[0], payload[1]]) as f32 / 100.0;
-        let bench_temperature_1 = i16::from_be_bytes([payload[2], payload[3]]) as f32 / 100.0;
-        let bench_temperature_2 = i16::from_be_bytes([payload[4], payload[5]]) as f32 / 100.0;
-        let load = i16::from_be_bytes([payload[6], payload[7]]) as i32;
-        let voltage = i16::from_be_bytes([payload[6], payload[7]]) as i32;
-        let current = i16::from_be_bytes([payload[8], payload[9]]) as i32;
+    ) -> Result<BatteryLog, ProtocolError> {
+        self.expect_payload(Command::RequestData, payload, RequestDataPayload::LEN)?;
+        let data = RequestDataPayload::decode(payload);
 
         Ok(BatteryLog {
             record_id: None,
             id: id as i32, //FIXME:
-            port: port,
-            battery_temperature: battery_temperature as i32,
-            bench_temperature_mosfet: bench_temperature_1 as i32,
-            bench_temperature_resistor: bench_temperature_2 as i32,
-            load: load,
-            voltage,
-            current,
+            port,
+            battery_temperature: data.battery_temperature_c,
+            bench_temperature_mosfet: data.mosfet_temperature_c,
+            bench_temperature_resistor: data.resistor_temperature_c,
+            load: data.load_ohms,
+            voltage: data.voltage_mv,
+            current: data.current_ma,
             state: String::new(),
             status: String::new(),
             start_date: None,
             end_date: None,
             test_id: 0,
+            recorded_at: Utc::now().to_rfc3339(),
+            // only the caller knows when the test started
+            elapsed_s: 0.0,
         })
     }
 
-    pub fn parse_completion(&self, payload: &[u8]) -> Result<AnnounceCompletionPayload, String> {
-        if self.command != Command::RequestCompletion {
-            return Err("parse_completion called on wrong command".into());
+    pub fn parse_completion(&self, payload: &[u8]) -> Result<CompletionStatus, ProtocolError> {
+        self.expect_payload(Command::RequestCompletion, payload, 1)?;
+        CompletionStatus::from_flags(payload[0])
+    }
+}
+
+/// A frame pulled out of the byte stream by [`FrameDecoder`].
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct DecodedFrame {
+    pub command: BatteryCommand,
+    /// Garbage bytes that were discarded right before this frame.
+    pub skipped: usize,
+}
+
+/// Reassembles [`BatteryCommand`]s out of an arbitrary byte stream.
+///
+/// Bytes can be pushed in chunks of any size. The decoder hunts for
+/// `DELIMITER`, uses the frame ID to know how long the frame is and only
+/// emits frames whose CRC matches. Anything else is dropped one byte at a time
+/// so that a `0xB3` inside a corrupted frame can still start the next one.
+#[derive(Debug, Default)]
+pub struct FrameDecoder {
+    buffer: Vec<u8>,
+    pending_skip: usize,
+    total_skipped: usize,
+}
+
+impl FrameDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, bytes: &[u8]) {
+        self.buffer.extend_from_slice(bytes);
+    }
+
+    /// Pushes `bytes` and returns every frame that is now complete.
+    pub fn feed(&mut self, bytes: &[u8]) -> Vec<DecodedFrame> {
+        self.push(bytes);
+
+        let mut frames = Vec::new();
+        while let Some(frame) = self.next_frame() {
+            frames.push(frame);
         }
-        if payload.len() != 1 {
-            return Err("Invalid Completion payload length".into());
+        frames
+    }
+
+    pub fn next_frame(&mut self) -> Option<DecodedFrame> {
+        loop {
+            match self.buffer.iter().position(|&byte| byte == DELIMITER) {
+                Some(start) => self.discard(start),
+                None => {
+                    self.discard(self.buffer.len());
+                    return None;
+                }
+            }
+
+            if self.buffer.len() < 2 {
+                return None;
+            }
+
+            let frame_len = match Command::from_id(self.buffer[1]) {
+                Some(command) => command.response_lenght(),
+                None => {
+                    self.discard(1);
+                    continue;
+                }
+            };
+
+            if self.buffer.len() < frame_len {
+                return None;
+            }
+
+            match BatteryCommand::decode(&self.buffer[..frame_len]) {
+                Ok(command) => {
+                    self.buffer.drain(..frame_len);
+                    let skipped = std::mem::take(&mut self.pending_skip);
+                    return Some(DecodedFrame { command, skipped });
+                }
+                Err(_) => self.discard(1),
+            }
         }
-        let flags = payload[0];
-        Ok(AnnounceCompletionPayload {
-            bench_status: flags,
-            experiment_status: flags,
-        })
+    }
+
+    /// Bytes waiting for the rest of their frame.
+    pub fn buffered(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// Garbage bytes discarded since the decoder was created.
+    pub fn skipped(&self) -> usize {
+        self.total_skipped
+    }
+
+    pub fn reset(&mut self) {
+        self.buffer.clear();
+        self.pending_skip = 0;
+    }
+
+    fn discard(&mut self, count: usize) {
+        self.buffer.drain(..count);
+        self.pending_skip += count;
+        self.total_skipped += count;
     }
 }
 
-struct CompletionStatus {
-    bench_status: u8,
-    experiment_status: u8,
+/// Reads from `port` until `decoder` yields a complete frame or
+/// `RESPONSE_TIMEOUT` elapses.
+pub fn read_frame<R: Read + ?Sized>(
+    port: &mut R,
+    decoder: &mut FrameDecoder,
+) -> Result<BatteryCommand, BenchError> {
+    let mut chunk = [0u8; 64];
+    let started = Instant::now();
+
+    loop {
+        if let Some(frame) = decoder.next_frame() {
+            if frame.skipped > 0 {
+                println!("Skipped {} bytes before frame", frame.skipped);
+            }
+            return Ok(frame.command);
+        }
+
+        if started.elapsed() >= RESPONSE_TIMEOUT {
+            return Err(BenchError::Timeout {
+                waited_ms: RESPONSE_TIMEOUT.as_millis() as u32,
+            });
+        }
+
+        match port.read(&mut chunk) {
+            Ok(0) => return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into()),
+            Ok(count) => decoder.push(&chunk[..count]),
+            Err(e) if e.kind() == std::io::ErrorKind::TimedOut => {
+                println!("data was not ready");
+                thread::sleep(Duration::from_millis(333));
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct RequestDataPayload {
-    battery_temperature: u16,
-    bench_temperature: u16,
-    load_temperature: u16,
-    voltage: u16,
-    current: u16,
+// Bits of the `State and completion status` byte of a RequestCompletion frame.
+// The SDD numbers them from the most significant bit, so 0x41 is a successful
+// charge and 0x82 a failed discharge.
+pub const COMPLETION_DISCHARGE: u8 = 0x80;
+pub const COMPLETION_CHARGE: u8 = 0x40;
+pub const COMPLETION_IN_PROGRESS: u8 = 0x04;
+pub const COMPLETION_FAILED: u8 = 0x02;
+pub const COMPLETION_SUCCESS: u8 = 0x01;
+const COMPLETION_RESERVED: u8 = 0x38;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Type, Serialize, Deserialize)]
+pub enum CompletionPhase {
+    Charge,
+    Discharge,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Type, Serialize, Deserialize)]
+pub enum CompletionOutcome {
+    InProgress,
+    Failed,
+    Success,
+}
+
+/// The decoded `State and completion status` byte.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Type, Serialize, Deserialize)]
+pub struct CompletionStatus {
+    pub phase: CompletionPhase,
+    pub outcome: CompletionOutcome,
+}
+
+impl CompletionStatus {
+    /// Exactly one phase bit and one outcome bit must be set, and none of the
+    /// reserved ones.
+    pub fn from_flags(flags: u8) -> Result<CompletionStatus, ProtocolError> {
+        if flags & COMPLETION_RESERVED != 0 {
+            return Err(ProtocolError::InvalidCompletion { flags });
+        }
+
+        let phase = match flags & (COMPLETION_DISCHARGE | COMPLETION_CHARGE) {
+            COMPLETION_CHARGE => CompletionPhase::Charge,
+            COMPLETION_DISCHARGE => CompletionPhase::Discharge,
+            _ => return Err(ProtocolError::InvalidCompletion { flags }),
+        };
+        let outcome =
+            match flags & (COMPLETION_IN_PROGRESS | COMPLETION_FAILED | COMPLETION_SUCCESS) {
+                COMPLETION_IN_PROGRESS => CompletionOutcome::InProgress,
+                COMPLETION_FAILED => CompletionOutcome::Failed,
+                COMPLETION_SUCCESS => CompletionOutcome::Success,
+                _ => return Err(ProtocolError::InvalidCompletion { flags }),
+            };
+
+        Ok(CompletionStatus { phase, outcome })
+    }
+
+    pub fn flags(&self) -> u8 {
+        let phase = match self.phase {
+            CompletionPhase::Charge => COMPLETION_CHARGE,
+            CompletionPhase::Discharge => COMPLETION_DISCHARGE,
+        };
+        let outcome = match self.outcome {
+            CompletionOutcome::InProgress => COMPLETION_IN_PROGRESS,
+            CompletionOutcome::Failed => COMPLETION_FAILED,
+            CompletionOutcome::Success => COMPLETION_SUCCESS,
+        };
+        phase | outcome
+    }
+}
+
+/// Readings carried by a RequestData reply, in physical units.
+///
+/// The payload is six big-endian 16-bit words, in the order of the table in
+/// docs/sdd.md:
+///
+/// | Bytes | Reading            | Encoding                      |
+/// | ----- | ------------------ | ----------------------------- |
+/// | 0-1   | Battery temp       | signed, 0.01 °C per bit       |
+/// | 2-3   | Bench temp MOSFET  | signed, 0.01 °C per bit       |
+/// | 4-5   | Bench temp resistor| signed, 0.01 °C per bit       |
+/// | 6-7   | Load               | unsigned, 1 Ω per bit         |
+/// | 8-9   | Battery voltage    | unsigned, 1 mV per bit        |
+/// | 10-11 | Bench current      | signed, 1 mA per bit          |
+///
+/// The voltage and current encodings are assumed until the firmware
+/// confirms them: docs/sdd.md still lists them as being figured out.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct RequestDataPayload {
+    pub battery_temperature_c: f32,
+    pub mosfet_temperature_c: f32,
+    pub resistor_temperature_c: f32,
+    pub load_ohms: f32,
+    pub voltage_mv: f32,
+    /// Negative while discharging.
+    pub current_ma: f32,
+}
+
+impl RequestDataPayload {
+    pub const LEN: usize = 12;
+
+    /// Fixed-point scale of the temperature words.
+    const TEMPERATURE_SCALE: f32 = 100.0;
+
+    /// Decodes the first [`Self::LEN`] bytes of `payload`, which the caller
+    /// has checked are there.
+    pub fn decode(payload: &[u8]) -> Self {
+        let word = |index: usize| [payload[index], payload[index + 1]];
+        let temperature = |index| i16::from_be_bytes(word(index)) as f32 / Self::TEMPERATURE_SCALE;
+
+        RequestDataPayload {
+            battery_temperature_c: temperature(0),
+            mosfet_temperature_c: temperature(2),
+            resistor_temperature_c: temperature(4),
+            load_ohms: u16::from_be_bytes(word(6)) as f32,
+            voltage_mv: u16::from_be_bytes(word(8)) as f32,
+            current_ma: i16::from_be_bytes(word(10)) as f32,
+        }
+    }
+
+    /// The payload a bench would send, rounding to the resolution of each
+    /// word and saturating values that don't fit.
+    pub fn encode(&self) -> [u8; Self::LEN] {
+        let temperature =
+            |value: f32| ((value * Self::TEMPERATURE_SCALE).round() as i16).to_be_bytes();
+
+        let mut payload = [0; Self::LEN];
+        payload[0..2].copy_from_slice(&temperature(self.battery_temperature_c));
+        payload[2..4].copy_from_slice(&temperature(self.mosfet_temperature_c));
+        payload[4..6].copy_from_slice(&temperature(self.resistor_temperature_c));
+        payload[6..8].copy_from_slice(&(self.load_ohms.round() as u16).to_be_bytes());
+        payload[8..10].copy_from_slice(&(self.voltage_mv.round() as u16).to_be_bytes());
+        payload[10..12].copy_from_slice(&(self.current_ma.round() as i16).to_be_bytes());
+        payload
+    }
 }
 
 #[tauri::command]
 #[specta::specta]
-pub fn detect_serial_ports() -> Result<Vec<String>, String> {
-    match available_ports() {
-        Ok(ports) => Ok(ports.into_iter().map(|p| p.port_name).collect()),
-        Err(e) => Err(format!("Error listing serial ports: {}", e)),
-    }
+pub fn detect_serial_ports() -> Result<Vec<String>, BenchError> {
+    let ports = available_ports()?;
+    Ok(ports.into_iter().map(|p| p.port_name).collect())
 }
 
 #[tauri::command]
 #[specta::specta]
-pub async fn command_request(command: Command, port_num: &str) -> Result<Vec<u8>, String> {
+pub async fn command_request(
+    state: State<'_, Mutex<AppState>>,
+    command: Command,
+    port_num: &str,
+) -> Result<Vec<u8>, BenchError> {
     let battery_cmd = BatteryCommand {
-        command: command,
+        command,
         battery_id: 0x02,
         payload: vec![0x3B],
     };
@@ -234,33 +602,12 @@ pub async fn command_request(command: Command, port_num: &str) -> Result<Vec<u8>
         }
     }
 
-    let mut response = vec![0u8; expected_bytes];
-
-    let mut port = serialport::new(port_num, 9600)
-        .timeout(Duration::from_millis(100))
-        .open()
-        .map_err(|e| e.to_string())?;
-
-    port.write_all(&encoded_data).map_err(|e| e.to_string())?;
-
-    loop {
-        match port.read_exact(&mut response) {
-            Ok(_) => break,
-            Err(e) if e.kind() == std::io::ErrorKind::TimedOut => {
-                println!("data was not ready");
-                thread::sleep(Duration::from_millis(333));
-            }
-            Err(e) => return Err(e.to_string()),
-        }
-    }
+    let session = session_for(&state, port_num)?;
+    let response = session.exchange(&battery_cmd).await?.encode();
 
     dbg!(&response);
 
-    if response.len() < 3 || response[0] != DELIMITER {
-        Err("invalid response".to_string())
-    } else {
-        Ok(response)
-    }
+    Ok(response)
 }
 
 fn format_hex(bytes: &[u8]) -> String {
@@ -355,8 +702,290 @@ mod tests {
     }
 
     #[test]
-    fn test_decode_invalid_checksum() {}
+    fn test_frame_decoder_split_chunks() {
+        let ping = BatteryCommand {
+            command: Command::Ping,
+            battery_id: 0x23,
+            payload: vec![],
+        };
+        let completion = BatteryCommand {
+            command: Command::RequestCompletion,
+            battery_id: 0x23,
+            payload: vec![0x41],
+        };
+        let mut stream = ping.encode();
+        stream.extend(completion.encode());
+
+        let mut decoder = FrameDecoder::new();
+        let mut frames = Vec::new();
+        for byte in &stream {
+            frames.extend(decoder.feed(&[*byte]));
+        }
+
+        let commands: Vec<_> = frames.into_iter().map(|frame| frame.command).collect();
+        assert_eq!(commands, vec![ping, completion]);
+        assert_eq!(decoder.skipped(), 0);
+        assert_eq!(decoder.buffered(), 0);
+    }
+
+    #[test]
+    fn test_frame_decoder_resync() {
+        let ping = BatteryCommand {
+            command: Command::Ping,
+            battery_id: 0x23,
+            payload: vec![],
+        };
+        let data = BatteryCommand {
+            command: Command::RequestData,
+            battery_id: 0x23,
+            payload: vec![0x07, 0xE4, 0x07, 0xE4, 0x07, 0xE4, 0x07, 0xE4, 0, 0, 0, 0],
+        };
+
+        // leading noise, a stray delimiter, then a frame with a flipped payload byte
+        let mut stream = vec![0x00, 0x11, DELIMITER, 0x42];
+        let mut corrupted = data.encode();
+        corrupted[4] ^= 0xFF;
+        stream.extend(&corrupted);
+        stream.extend(ping.encode());
+        stream.extend(data.encode());
+
+        let mut decoder = FrameDecoder::new();
+        let frames = decoder.feed(&stream);
+
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].command, ping);
+        assert_eq!(frames[0].skipped, 4 + corrupted.len());
+        assert_eq!(frames[1].command, data);
+        assert_eq!(frames[1].skipped, 0);
+        assert_eq!(decoder.skipped(), 4 + corrupted.len());
+    }
+
+    #[test]
+    fn test_frame_decoder_waits_for_full_frame() {
+        let data = BatteryCommand {
+            command: Command::RequestData,
+            battery_id: 0x01,
+            payload: vec![0; 12],
+        };
+        let encoded = data.encode();
+
+        let mut decoder = FrameDecoder::new();
+        assert!(decoder.feed(&encoded[..10]).is_empty());
+        assert_eq!(decoder.buffered(), 10);
+
+        let frames = decoder.feed(&encoded[10..]);
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].command, data);
+    }
+
+    #[test]
+    fn test_read_frame() {
+        let ping = BatteryCommand {
+            command: Command::Ping,
+            battery_id: 0x05,
+            payload: vec![],
+        };
+        let mut stream = vec![0xFF, 0xFF];
+        stream.extend(ping.encode());
+
+        let mut port = std::io::Cursor::new(stream);
+        let frame = read_frame(&mut port, &mut FrameDecoder::new()).expect("read failed");
+        assert_eq!(frame, ping);
+
+        let mut empty = std::io::Cursor::new(Vec::new());
+        assert!(read_frame(&mut empty, &mut FrameDecoder::new()).is_err());
+    }
+
+    #[test]
+    fn test_decode_invalid_checksum() {
+        let battery_cmd = BatteryCommand {
+            command: Command::Ping,
+            battery_id: 0x23,
+            payload: vec![],
+        };
+        let mut encoded_cmd = battery_cmd.encode();
+        let crc = encoded_cmd.pop().unwrap();
+        encoded_cmd.push(crc ^ 0x01);
+
+        assert_eq!(
+            BatteryCommand::decode(&encoded_cmd),
+            Err(ProtocolError::CrcMismatch {
+                expected: crc,
+                received: crc ^ 0x01
+            })
+        );
+    }
+
+    #[test]
+    fn test_decode_too_short() {
+        assert_eq!(
+            BatteryCommand::decode(&[DELIMITER, 0x00, 0x23]),
+            Err(ProtocolError::ShortFrame { length: 3 })
+        );
+    }
+
+    #[test]
+    fn test_decode_unknown_command() {
+        let mut packet = vec![DELIMITER, 0x03, 0x23];
+        packet.push(BatteryCommand::checksum(&packet));
+
+        assert_eq!(
+            BatteryCommand::decode(&packet),
+            Err(ProtocolError::UnknownCommand { id: 0x03 })
+        );
+    }
+
+    #[test]
+    fn test_parse_wrong_command() {
+        let battery_cmd = BatteryCommand {
+            command: Command::Ping,
+            battery_id: 0x23,
+            payload: vec![0x41],
+        };
+
+        assert_eq!(
+            battery_cmd.parse_completion(&battery_cmd.payload).err(),
+            Some(ProtocolError::WrongCommand {
+                expected: Command::RequestCompletion,
+                received: Command::Ping
+            })
+        );
+    }
 
     #[test]
-    fn test_decode_too_short() {}
+    fn test_parse_assign_id() {
+        let assign = BatteryCommand {
+            command: Command::AssignId,
+            battery_id: 0x23,
+            payload: vec![],
+        };
+        let frame = BatteryCommand::decode(&assign.encode()).unwrap();
+        assert_eq!(frame.parse_assign_id(), Ok(0x23));
+
+        let with_payload = BatteryCommand {
+            command: Command::AssignId,
+            battery_id: 0x23,
+            payload: vec![0x23],
+        };
+        assert!(matches!(
+            with_payload.parse_assign_id(),
+            Err(ProtocolError::PayloadLength { .. })
+        ));
+    }
+
+    #[test]
+    fn test_completion_status_flags() {
+        // examples from the SDD
+        assert_eq!(
+            CompletionStatus::from_flags(0x41),
+            Ok(CompletionStatus {
+                phase: CompletionPhase::Charge,
+                outcome: CompletionOutcome::Success,
+            })
+        );
+        assert_eq!(
+            CompletionStatus::from_flags(0x82),
+            Ok(CompletionStatus {
+                phase: CompletionPhase::Discharge,
+                outcome: CompletionOutcome::Failed,
+            })
+        );
+
+        for flags in [0x41, 0x42, 0x44, 0x81, 0x82, 0x84] {
+            assert_eq!(CompletionStatus::from_flags(flags).unwrap().flags(), flags);
+        }
+
+        // reserved bits, no or both phases, no or several outcomes
+        for flags in [0x49, 0x01, 0xC1, 0x40, 0x43] {
+            assert_eq!(
+                CompletionStatus::from_flags(flags),
+                Err(ProtocolError::InvalidCompletion { flags })
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_completion() {
+        let frame = BatteryCommand {
+            command: Command::RequestCompletion,
+            battery_id: 0x23,
+            payload: vec![0x41],
+        };
+        let status = frame.parse_completion(&frame.payload).unwrap();
+        assert_eq!(status.phase, CompletionPhase::Charge);
+        assert_eq!(status.outcome, CompletionOutcome::Success);
+    }
+
+    #[test]
+    fn test_parse_request_data_sdd_example() {
+        // reply from the SDD, every word 0x07E4 = 2020
+        let reply = BatteryCommand {
+            command: Command::RequestData,
+            battery_id: 0x23,
+            payload: vec![0x07, 0xE4, 0x07, 0xE4, 0x07, 0xE4, 0x07, 0xE4, 0, 0, 0, 0],
+        };
+        let encoded = reply.encode();
+        assert_eq!(
+            encoded[..15],
+            [0xB3, 0x02, 0x23, 0x07, 0xE4, 0x07, 0xE4, 0x07, 0xE4, 0x07, 0xE4, 0, 0, 0, 0]
+        );
+
+        let frame = BatteryCommand::decode(&encoded).unwrap();
+        let log = frame
+            .parse_request_data(&frame.payload, 0x23, "COM3".to_string())
+            .unwrap();
+        assert_eq!(log.id, 0x23);
+        assert_eq!(log.battery_temperature, 20.2);
+        assert_eq!(log.bench_temperature_mosfet, 20.2);
+        assert_eq!(log.bench_temperature_resistor, 20.2);
+        assert_eq!(log.load, 2020.0);
+        assert_eq!(log.voltage, 0.0);
+        assert_eq!(log.current, 0.0);
+    }
+
+    #[test]
+    fn test_request_data_payload_layout() {
+        let payload = [
+            0xFF, 0x38, // -2.00 °C
+            0x0B, 0xB9, // 30.01 °C
+            0x1F, 0x40, // 80.00 °C
+            0x00, 0x04, // 4 ohms
+            0x0E, 0x74, // 3700 mV
+            0xFB, 0x50, // -1200 mA
+        ];
+        let data = RequestDataPayload::decode(&payload);
+        assert_eq!(
+            data,
+            RequestDataPayload {
+                battery_temperature_c: -2.0,
+                mosfet_temperature_c: 30.01,
+                resistor_temperature_c: 80.0,
+                load_ohms: 4.0,
+                voltage_mv: 3700.0,
+                current_ma: -1200.0,
+            }
+        );
+        assert_eq!(data.encode(), payload);
+
+        // voltage is unsigned, current is not
+        let data = RequestDataPayload::decode(&[0, 0, 0, 0, 0, 0, 0, 0, 0xFF, 0xFF, 0xFF, 0xFF]);
+        assert_eq!(data.voltage_mv, 65535.0);
+        assert_eq!(data.current_ma, -1.0);
+
+        let short = BatteryCommand {
+            command: Command::RequestData,
+            battery_id: 0x23,
+            payload: vec![0; 10],
+        };
+        assert_eq!(
+            short
+                .parse_request_data(&short.payload, 0x23, "COM3".to_string())
+                .err(),
+            Some(ProtocolError::PayloadLength {
+                command: Command::RequestData,
+                expected: 12,
+                received: 10,
+            })
+        );
+    }
 }
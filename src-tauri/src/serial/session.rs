@@ -0,0 +1,624 @@
+use std::{
+    collections::HashMap,
+    io::{Read, Write},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tauri::{AppHandle, State};
+use tauri_specta::Event;
+use tokio::sync::{broadcast, mpsc};
+
+use crate::{
+    serial::{
+        pilot::{spawn_completion_recorder, spawn_id_assigner},
+        serial::{
+            BatteryCommand, BenchError, Command, FrameDecoder, RESPONSE_TIMEOUT, UNASSIGNED_ID,
+        },
+        transport::{Endpoint, Transport},
+    },
+    state::AppState,
+};
+
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A battery is considered gone once it hasn't pinged for this long. The
+/// bench pings every second, so this allows a couple of lost frames.
+pub const HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(3);
+const HEARTBEAT_CHECK_INTERVAL: Duration = Duration::from_millis(250);
+
+#[derive(Debug, Clone, Type, Serialize, Deserialize)]
+pub struct SessionStatus {
+    pub port: String,
+    pub connected: bool,
+    /// Whether benches pinging `0xFF` get an ID automatically.
+    pub auto_assign: bool,
+}
+
+/// Sent to the frontend when a battery stops pinging.
+#[derive(Debug, Clone, Type, Serialize, Deserialize, Event)]
+pub struct BenchLost {
+    pub port: String,
+    pub battery_id: u8,
+    pub silent_ms: u32,
+}
+
+/// State shared between a [`BenchSession`] and its reader thread.
+struct Shared {
+    port: String,
+    /// `None` for transports that can't be reopened, like an in-memory pipe.
+    endpoint: Option<Endpoint>,
+    writer: Mutex<Option<Box<dyn Transport>>>,
+    connected: AtomicBool,
+    running: AtomicBool,
+    replies: mpsc::UnboundedSender<BatteryCommand>,
+    unsolicited: broadcast::Sender<BatteryCommand>,
+    last_heard: Mutex<HashMap<u8, Instant>>,
+}
+
+impl Shared {
+    fn write(&self, bytes: &[u8]) -> Result<(), BenchError> {
+        let mut writer = self.writer.lock().map_err(|e| BenchError::Io {
+            message: e.to_string(),
+        })?;
+        let port = writer.as_mut().ok_or_else(|| BenchError::NotConnected {
+            port: self.port.clone(),
+        })?;
+
+        port.write_all(bytes)?;
+        port.flush()?;
+        Ok(())
+    }
+
+    /// Frames the bench sends on its own go to subscribers, everything else
+    /// is the answer to the exchange currently in flight.
+    ///
+    /// Pings from batteries with an ID are echoed right here on the reader
+    /// thread: the bench drops its ID if the echo takes more than a second.
+    fn route(&self, frame: BatteryCommand) {
+        if frame.battery_id != UNASSIGNED_ID {
+            if let Ok(mut last_heard) = self.last_heard.lock() {
+                last_heard.insert(frame.battery_id, Instant::now());
+            }
+
+            if frame.command == Command::Ping {
+                if let Err(error) = self.write(&frame.encode()) {
+                    println!("{}: failed to echo ping: {}", self.port, error);
+                }
+            }
+        }
+
+        if frame.command.is_unsolicited() {
+            // no subscriber is not an error
+            let _ = self.unsolicited.send(frame);
+        } else {
+            let _ = self.replies.send(frame);
+        }
+    }
+
+    fn attach(&self, transport: Box<dyn Transport>) -> Result<Box<dyn Transport>, BenchError> {
+        let reader = transport.try_clone()?;
+
+        if let Ok(mut writer) = self.writer.lock() {
+            *writer = Some(transport);
+        }
+        self.connected.store(true, Ordering::SeqCst);
+
+        Ok(reader)
+    }
+
+    fn connect(&self) -> Result<Box<dyn Transport>, BenchError> {
+        match &self.endpoint {
+            Some(endpoint) => self.attach(endpoint.connect()?),
+            None => Err(BenchError::NotConnected {
+                port: self.port.clone(),
+            }),
+        }
+    }
+
+    fn disconnect(&self) {
+        self.connected.store(false, Ordering::SeqCst);
+        if let Ok(mut writer) = self.writer.lock() {
+            *writer = None;
+        }
+    }
+
+    /// Forgets and returns the batteries that have been silent for longer
+    /// than [`HEARTBEAT_TIMEOUT`].
+    fn take_silent(&self, now: Instant) -> Vec<(u8, Duration)> {
+        let Ok(mut last_heard) = self.last_heard.lock() else {
+            return vec![];
+        };
+
+        let silent: Vec<_> = last_heard
+            .iter()
+            .map(|(&battery_id, &heard)| (battery_id, now.saturating_duration_since(heard)))
+            .filter(|(_, silence)| *silence >= HEARTBEAT_TIMEOUT)
+            .collect();
+        for (battery_id, _) in &silent {
+            last_heard.remove(battery_id);
+        }
+
+        silent
+    }
+
+    /// Keeps trying to reopen the port until it succeeds or the session closes.
+    fn reconnect(&self) -> Option<Box<dyn Transport>> {
+        self.endpoint.as_ref()?;
+
+        while self.running.load(Ordering::SeqCst) {
+            thread::sleep(RECONNECT_INTERVAL);
+
+            match self.connect() {
+                Ok(reader) => {
+                    println!("Reconnected to {}", self.port);
+                    return Some(reader);
+                }
+                Err(error) => println!("Waiting for {}: {}", self.port, error),
+            }
+        }
+        None
+    }
+}
+
+/// A long-lived connection to one bench.
+///
+/// The port stays open for the lifetime of the session. A reader thread
+/// decodes everything the bench sends, request/response exchanges are
+/// serialised, and the port is reopened when the device disappears.
+pub struct BenchSession {
+    shared: Arc<Shared>,
+    replies: tokio::sync::Mutex<mpsc::UnboundedReceiver<BatteryCommand>>,
+    reader: Mutex<Option<JoinHandle<()>>>,
+    watchdog: Mutex<Option<JoinHandle<()>>>,
+    auto_assign: AtomicBool,
+}
+
+impl BenchSession {
+    /// Opens `port`, either a serial port name or a `tcp://host:port` address.
+    pub fn open(port: &str) -> Result<Arc<BenchSession>, BenchError> {
+        let endpoint = Endpoint::parse(port);
+        let transport = endpoint.connect()?;
+        Self::start(port, Some(endpoint), transport)
+    }
+
+    /// Runs a session over an already connected transport that is not
+    /// reopened when it goes away.
+    pub fn from_transport(
+        name: &str,
+        transport: Box<dyn Transport>,
+    ) -> Result<Arc<BenchSession>, BenchError> {
+        Self::start(name, None, transport)
+    }
+
+    fn start(
+        port: &str,
+        endpoint: Option<Endpoint>,
+        transport: Box<dyn Transport>,
+    ) -> Result<Arc<BenchSession>, BenchError> {
+        let (replies_tx, replies_rx) = mpsc::unbounded_channel();
+        let (unsolicited, _) = broadcast::channel(64);
+
+        let shared = Arc::new(Shared {
+            port: port.to_string(),
+            endpoint,
+            writer: Mutex::new(None),
+            connected: AtomicBool::new(false),
+            running: AtomicBool::new(true),
+            replies: replies_tx,
+            unsolicited,
+            last_heard: Mutex::new(HashMap::new()),
+        });
+        let reader = shared.attach(transport)?;
+
+        let thread_shared = shared.clone();
+        let handle = thread::spawn(move || read_loop(thread_shared, reader));
+
+        Ok(Arc::new(BenchSession {
+            shared,
+            replies: tokio::sync::Mutex::new(replies_rx),
+            reader: Mutex::new(Some(handle)),
+            watchdog: Mutex::new(None),
+            auto_assign: AtomicBool::new(true),
+        }))
+    }
+
+    pub fn port(&self) -> &str {
+        &self.shared.port
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.shared.connected.load(Ordering::SeqCst)
+    }
+
+    pub fn status(&self) -> SessionStatus {
+        SessionStatus {
+            port: self.port().to_string(),
+            connected: self.is_connected(),
+            auto_assign: self.auto_assign(),
+        }
+    }
+
+    pub fn auto_assign(&self) -> bool {
+        self.auto_assign.load(Ordering::SeqCst)
+    }
+
+    /// Turn off to leave unassigned benches for a reserved ID instead.
+    pub fn set_auto_assign(&self, enabled: bool) {
+        self.auto_assign.store(enabled, Ordering::SeqCst);
+    }
+
+    /// Sends a frame without waiting for an answer.
+    pub async fn send(&self, frame: &BatteryCommand) -> Result<(), BenchError> {
+        self.shared.write(&frame.encode())
+    }
+
+    /// Waits for the next frame the bench sends on its own (Ping, completion).
+    pub fn subscribe(&self) -> broadcast::Receiver<BatteryCommand> {
+        self.shared.unsolicited.subscribe()
+    }
+
+    /// When `battery_id` last sent anything, `None` if it never did or has
+    /// since been reported lost.
+    pub fn last_heard(&self, battery_id: u8) -> Option<Instant> {
+        let last_heard = self.shared.last_heard.lock().ok()?;
+        last_heard.get(&battery_id).copied()
+    }
+
+    /// Batteries currently pinging on this port.
+    pub fn known_ids(&self) -> Vec<u8> {
+        match self.shared.last_heard.lock() {
+            Ok(last_heard) => last_heard.keys().copied().collect(),
+            Err(_) => vec![],
+        }
+    }
+
+    /// Calls `on_lost` from a background thread whenever a battery that was
+    /// pinging goes quiet for [`HEARTBEAT_TIMEOUT`].
+    pub fn watch_heartbeat(&self, on_lost: impl Fn(BenchLost) + Send + 'static) {
+        let Ok(mut watchdog) = self.watchdog.lock() else {
+            return;
+        };
+        if watchdog.is_some() {
+            return;
+        }
+
+        let shared = self.shared.clone();
+        *watchdog = Some(thread::spawn(move || {
+            while shared.running.load(Ordering::SeqCst) {
+                thread::sleep(HEARTBEAT_CHECK_INTERVAL);
+
+                for (battery_id, silence) in shared.take_silent(Instant::now()) {
+                    println!("{}: lost battery {}", shared.port, battery_id);
+                    on_lost(BenchLost {
+                        port: shared.port.clone(),
+                        battery_id,
+                        silent_ms: silence.as_millis() as u32,
+                    });
+                }
+            }
+        }));
+    }
+
+    /// Sends `request` and waits for the matching reply.
+    ///
+    /// Only one exchange runs at a time per session so replies can't be
+    /// handed to the wrong caller. Unsolicited frames are refused, their
+    /// answers only ever go to [`subscribe`](Self::subscribe).
+    pub async fn exchange(&self, request: &BatteryCommand) -> Result<BatteryCommand, BenchError> {
+        if request.command.is_unsolicited() {
+            return Err(BenchError::NoReply {
+                command: request.command,
+            });
+        }
+        let mut replies = self.replies.lock().await;
+
+        // replies nobody waited for (late answers to a timed out request)
+        while replies.try_recv().is_ok() {}
+
+        self.send(request).await?;
+
+        let reply = match tokio::time::timeout(RESPONSE_TIMEOUT, replies.recv()).await {
+            Ok(Some(reply)) => reply,
+            Ok(None) => {
+                return Err(BenchError::NotConnected {
+                    port: self.port().to_string(),
+                })
+            }
+            Err(_) => {
+                return Err(BenchError::Timeout {
+                    waited_ms: RESPONSE_TIMEOUT.as_millis() as u32,
+                })
+            }
+        };
+
+        if reply.command != request.command {
+            return Err(BenchError::WrongReply {
+                expected: request.command,
+                received: reply.command,
+            });
+        }
+        if reply.battery_id != request.battery_id {
+            return Err(BenchError::UnexpectedBatteryId {
+                expected: request.battery_id,
+                received: reply.battery_id,
+            });
+        }
+
+        Ok(reply)
+    }
+
+    pub fn close(&self) {
+        self.shared.running.store(false, Ordering::SeqCst);
+        self.shared.disconnect();
+
+        for thread in [&self.reader, &self.watchdog] {
+            let handle = thread.lock().ok().and_then(|mut handle| handle.take());
+            if let Some(handle) = handle {
+                let _ = handle.join();
+            }
+        }
+    }
+}
+
+impl Drop for BenchSession {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+fn read_loop(shared: Arc<Shared>, mut reader: Box<dyn Transport>) {
+    let mut decoder = FrameDecoder::new();
+    let mut chunk = [0u8; 256];
+
+    while shared.running.load(Ordering::SeqCst) {
+        let error = match reader.read(&mut chunk) {
+            Ok(0) => std::io::Error::from(std::io::ErrorKind::UnexpectedEof),
+            Ok(count) => {
+                for frame in decoder.feed(&chunk[..count]) {
+                    if frame.skipped > 0 {
+                        println!("{}: skipped {} bytes", shared.port, frame.skipped);
+                    }
+                    shared.route(frame.command);
+                }
+                continue;
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::TimedOut => continue,
+            Err(e) => e,
+        };
+
+        if !shared.running.load(Ordering::SeqCst) {
+            break;
+        }
+
+        println!("Lost {}: {}", shared.port, error);
+        shared.disconnect();
+        decoder.reset();
+
+        match shared.reconnect() {
+            Some(new_reader) => reader = new_reader,
+            None => break,
+        }
+    }
+}
+
+/// Returns the open session for `port`.
+pub fn session_for(
+    state: &State<'_, Mutex<AppState>>,
+    port: &str,
+) -> Result<Arc<BenchSession>, BenchError> {
+    let state = state.lock().map_err(|e| BenchError::Io {
+        message: e.to_string(),
+    })?;
+
+    state
+        .sessions
+        .get(port)
+        .cloned()
+        .ok_or_else(|| BenchError::NotConnected {
+            port: port.to_string(),
+        })
+}
+
+/// The session on `port`, opened if it isn't yet. `auto_assign` only applies
+/// to a newly opened session.
+///
+/// The port is opened without holding the lock, which can take a while on a
+/// network endpoint.
+pub fn open_session(
+    app: &AppHandle,
+    state: &Mutex<AppState>,
+    port: &str,
+    auto_assign: bool,
+) -> Result<Arc<BenchSession>, BenchError> {
+    let lock = || {
+        state.lock().map_err(|e| BenchError::Io {
+            message: e.to_string(),
+        })
+    };
+    if let Some(session) = lock()?.sessions.get(port) {
+        return Ok(session.clone());
+    }
+
+    let session = BenchSession::open(port)?;
+    let mut state = lock()?;
+    // opened by someone else in the meantime
+    if let Some(opened) = state.sessions.get(port).cloned() {
+        drop(state);
+        session.close();
+        return Ok(opened);
+    }
+    // before the assigner starts, so it can't hand out an ID first
+    session.set_auto_assign(auto_assign);
+    spawn_id_assigner(app.clone(), &session);
+    spawn_completion_recorder(app.clone(), &session);
+    let app = app.clone();
+    session.watch_heartbeat(move |lost| {
+        if let Err(error) = lost.emit(&app) {
+            println!("Failed to emit bench lost event: {}", error);
+        }
+    });
+    state.sessions.insert(port.to_string(), session.clone());
+
+    Ok(session)
+}
+
+#[tauri::command]
+#[specta::specta]
+pub fn open_bench(
+    app: AppHandle,
+    state: State<'_, Mutex<AppState>>,
+    port: String,
+) -> Result<SessionStatus, BenchError> {
+    Ok(open_session(&app, &state, &port, true)?.status())
+}
+
+#[tauri::command]
+#[specta::specta]
+pub fn close_bench(state: State<'_, Mutex<AppState>>, port: String) -> Result<(), BenchError> {
+    let session = {
+        let mut state = state.lock().map_err(|e| BenchError::Io {
+            message: e.to_string(),
+        })?;
+        state.sessions.remove(&port)
+    };
+
+    match session {
+        Some(session) => {
+            session.close();
+            Ok(())
+        }
+        None => Err(BenchError::NotConnected { port }),
+    }
+}
+
+#[tauri::command]
+#[specta::specta]
+pub fn set_auto_assign(
+    state: State<'_, Mutex<AppState>>,
+    port: String,
+    enabled: bool,
+) -> Result<SessionStatus, BenchError> {
+    let session = session_for(&state, &port)?;
+    session.set_auto_assign(enabled);
+    Ok(session.status())
+}
+
+#[tauri::command]
+#[specta::specta]
+pub fn list_benches(state: State<'_, Mutex<AppState>>) -> Result<Vec<SessionStatus>, BenchError> {
+    let state = state.lock().map_err(|e| BenchError::Io {
+        message: e.to_string(),
+    })?;
+
+    Ok(state
+        .sessions
+        .values()
+        .map(|session| session.status())
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::serial::{
+        simulator::{SimulatorConfig, SimulatorHandle},
+        transport::memory_pipe,
+    };
+
+    fn ping(battery_id: u8) -> BatteryCommand {
+        BatteryCommand {
+            command: Command::Ping,
+            battery_id,
+            payload: vec![],
+        }
+    }
+
+    #[test]
+    fn test_ping_echoed_and_tracked() {
+        let (gui, mut bench) = memory_pipe();
+        let session = BenchSession::from_transport("memory", Box::new(gui.clone())).unwrap();
+
+        bench.write_all(&ping(UNASSIGNED_ID).encode()).unwrap();
+        bench.write_all(&ping(0x23).encode()).unwrap();
+
+        let mut echo = [0u8; 4];
+        bench.read_exact(&mut echo).unwrap();
+        assert_eq!(echo.to_vec(), ping(0x23).encode());
+        assert!(session.last_heard(0x23).is_some());
+        assert!(session.last_heard(UNASSIGNED_ID).is_none());
+
+        let later = Instant::now() + HEARTBEAT_TIMEOUT;
+        let silent = session.shared.take_silent(later);
+        assert_eq!(silent.len(), 1);
+        assert_eq!(silent[0].0, 0x23);
+        assert!(session.shared.take_silent(later).is_empty());
+
+        session.close();
+        gui.close();
+    }
+
+    #[tokio::test]
+    async fn test_exchange_refuses_unsolicited() {
+        let (gui, mut bench) = memory_pipe();
+        let session = BenchSession::from_transport("memory", Box::new(gui.clone())).unwrap();
+
+        for command in [Command::Ping, Command::RequestCompletion] {
+            let request = BatteryCommand {
+                command,
+                battery_id: 0x23,
+                payload: vec![],
+            };
+            assert_eq!(
+                session.exchange(&request).await,
+                Err(BenchError::NoReply { command })
+            );
+        }
+
+        // nothing reached the bench
+        session.close();
+        gui.close();
+        let mut sent = Vec::new();
+        bench.read_to_end(&mut sent).unwrap();
+        assert!(sent.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_simulator_keeps_id_while_echoed() {
+        let (gui, bench) = memory_pipe();
+        let _simulator = SimulatorHandle::spawn(
+            SimulatorConfig {
+                ping_interval: Duration::from_millis(100),
+                heartbeat_timeout: Some(Duration::from_millis(300)),
+                ..SimulatorConfig::default()
+            },
+            Box::new(bench),
+        );
+        let session = BenchSession::from_transport("simulator", Box::new(gui.clone())).unwrap();
+        let mut pings = session.subscribe();
+
+        session
+            .send(&BatteryCommand {
+                command: Command::AssignId,
+                battery_id: 0x11,
+                payload: vec![],
+            })
+            .await
+            .unwrap();
+
+        // well past the simulator's heartbeat timeout
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        while pings.try_recv().is_ok() {}
+        let next = pings.recv().await.unwrap();
+        assert_eq!(next, ping(0x11));
+
+        session.close();
+        gui.close();
+    }
+}
@@ -0,0 +1,622 @@
+//! A simulated bench firmware, for development and tests without hardware.
+//!
+//! It follows the command IDs of [`Command`], which the GUI sends, and not
+//! the table in `docs/sdd.md`: charge is 0x04 and standby 0x06 here, the
+//! other way round in the SDD. Discharge is 0x05 in both. Until the firmware
+//! settles which is right, the simulator has to agree with the GUI.
+
+use std::{
+    io,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use chrono::Utc;
+
+use crate::{
+    database::models::BatteryLog,
+    serial::{
+        pilot::BatteryState,
+        serial::{
+            BatteryCommand, Command, CompletionOutcome, CompletionPhase, CompletionStatus,
+            FrameDecoder, RequestDataPayload, UNASSIGNED_ID,
+        },
+        transport::Transport,
+    },
+};
+
+const AMBIENT_TEMPERATURE: f32 = 22.0;
+const MAX_TEMPERATURE: f32 = 60.0;
+const THERMAL_TIME_CONSTANT: f32 = 300.0;
+const INTERNAL_RESISTANCE: f32 = 0.05;
+const EMPTY_VOLTAGE_MV: f32 = 3000.0;
+const FULL_VOLTAGE_MV: f32 = 4200.0;
+const MAX_STEP_SECONDS: f32 = 1.0;
+
+/// A very small lithium cell model: linear open circuit voltage, a fixed
+/// internal resistance and first order heating of the three sensors.
+#[derive(Debug, Clone)]
+pub struct CellModel {
+    capacity_mah: f32,
+    charge_mah: f32,
+    state: BatteryState,
+    charge_current_ma: f32,
+    discharge_current_ma: f32,
+    battery_temperature: f32,
+    mosfet_temperature: f32,
+    resistor_temperature: f32,
+    /// Simulated seconds since the model was created.
+    elapsed_s: f64,
+}
+
+impl CellModel {
+    /// A half charged cell at ambient temperature.
+    pub fn new(capacity_mah: f32) -> Self {
+        CellModel {
+            capacity_mah,
+            charge_mah: capacity_mah / 2.0,
+            state: BatteryState::Standby,
+            charge_current_ma: capacity_mah / 2.0,
+            discharge_current_ma: capacity_mah / 2.0,
+            battery_temperature: AMBIENT_TEMPERATURE,
+            mosfet_temperature: AMBIENT_TEMPERATURE,
+            resistor_temperature: AMBIENT_TEMPERATURE,
+            elapsed_s: 0.0,
+        }
+    }
+
+    pub fn state(&self) -> BatteryState {
+        self.state
+    }
+
+    pub fn set_state(&mut self, state: BatteryState) {
+        self.state = state;
+    }
+
+    pub fn state_of_charge(&self) -> f32 {
+        (self.charge_mah / self.capacity_mah).clamp(0.0, 1.0)
+    }
+
+    /// Positive while charging, negative while discharging.
+    pub fn current_ma(&self) -> f32 {
+        match self.state {
+            BatteryState::Standby => 0.0,
+            BatteryState::Charge => self.charge_current_ma,
+            BatteryState::Discharge => -self.discharge_current_ma,
+        }
+    }
+
+    pub fn voltage_mv(&self) -> f32 {
+        let open_circuit =
+            EMPTY_VOLTAGE_MV + (FULL_VOLTAGE_MV - EMPTY_VOLTAGE_MV) * self.state_of_charge();
+        open_circuit + self.current_ma() * INTERNAL_RESISTANCE
+    }
+
+    /// Resistance of the electronic load, only used while discharging.
+    pub fn load_ohms(&self) -> f32 {
+        match self.state {
+            BatteryState::Discharge => self.voltage_mv() / self.discharge_current_ma,
+            _ => 0.0,
+        }
+    }
+
+    pub fn battery_temperature(&self) -> f32 {
+        self.battery_temperature
+    }
+
+    pub fn mosfet_temperature(&self) -> f32 {
+        self.mosfet_temperature
+    }
+
+    pub fn resistor_temperature(&self) -> f32 {
+        self.resistor_temperature
+    }
+
+    /// Advances the model by `seconds`.
+    ///
+    /// Returns how the current phase ended if it did, after which the cell is
+    /// back in standby.
+    pub fn step(&mut self, mut seconds: f32) -> Option<CompletionStatus> {
+        while seconds > 0.0 {
+            let dt = seconds.min(MAX_STEP_SECONDS);
+            seconds -= dt;
+
+            if let Some(status) = self.step_once(dt) {
+                return Some(status);
+            }
+        }
+        None
+    }
+
+    fn step_once(&mut self, seconds: f32) -> Option<CompletionStatus> {
+        let current = self.current_ma();
+        let amps = current.abs() / 1000.0;
+        self.elapsed_s += seconds as f64;
+
+        self.charge_mah =
+            (self.charge_mah + current * seconds / 3600.0).clamp(0.0, self.capacity_mah);
+
+        let resistor_target = match self.state {
+            BatteryState::Discharge => AMBIENT_TEMPERATURE + 30.0 * amps,
+            _ => AMBIENT_TEMPERATURE,
+        };
+        let alpha = 1.0 - (-seconds / THERMAL_TIME_CONSTANT).exp();
+        self.battery_temperature +=
+            (AMBIENT_TEMPERATURE + 6.0 * amps - self.battery_temperature) * alpha;
+        self.mosfet_temperature +=
+            (AMBIENT_TEMPERATURE + 12.0 * amps - self.mosfet_temperature) * alpha;
+        self.resistor_temperature += (resistor_target - self.resistor_temperature) * alpha;
+
+        let phase = match self.state {
+            BatteryState::Standby => return None,
+            BatteryState::Charge => CompletionPhase::Charge,
+            BatteryState::Discharge => CompletionPhase::Discharge,
+        };
+
+        let finished = match self.state {
+            BatteryState::Charge => self.voltage_mv() >= FULL_VOLTAGE_MV,
+            _ => self.voltage_mv() <= EMPTY_VOLTAGE_MV,
+        };
+        let outcome = if self.battery_temperature > MAX_TEMPERATURE {
+            CompletionOutcome::Failed
+        } else if finished {
+            CompletionOutcome::Success
+        } else {
+            return None;
+        };
+
+        self.state = BatteryState::Standby;
+        Some(CompletionStatus { phase, outcome })
+    }
+
+    /// The current readings as a log row, for development data that doesn't
+    /// need a bench.
+    pub fn sample(&self, id: i32, port: &str, test_id: i32) -> BatteryLog {
+        let state = match self.state {
+            BatteryState::Standby => "idle",
+            BatteryState::Charge => "charging",
+            BatteryState::Discharge => "discharging",
+        };
+
+        BatteryLog {
+            record_id: None,
+            id,
+            port: port.to_string(),
+            battery_temperature: self.battery_temperature,
+            bench_temperature_mosfet: self.mosfet_temperature,
+            bench_temperature_resistor: self.resistor_temperature,
+            load: self.load_ohms(),
+            voltage: self.voltage_mv(),
+            current: self.current_ma(),
+            state: state.to_string(),
+            status: "ok".to_string(),
+            start_date: Some(Utc::now().to_rfc3339()),
+            end_date: None,
+            test_id,
+            recorded_at: Utc::now().to_rfc3339(),
+            elapsed_s: self.elapsed_s,
+        }
+    }
+
+    /// The 12 byte RequestData payload laid out as in docs/sdd.md.
+    pub fn request_data_payload(&self) -> Vec<u8> {
+        RequestDataPayload {
+            battery_temperature_c: self.battery_temperature,
+            mosfet_temperature_c: self.mosfet_temperature,
+            resistor_temperature_c: self.resistor_temperature,
+            load_ohms: self.load_ohms(),
+            voltage_mv: self.voltage_mv(),
+            current_ma: self.current_ma(),
+        }
+        .encode()
+        .to_vec()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SimulatorConfig {
+    pub cells: usize,
+    pub capacity_mah: f32,
+    /// Simulated seconds per real second, to run a full sequence quickly.
+    pub time_scale: f32,
+    pub ping_interval: Duration,
+    /// How long a cell waits for the GUI to echo its Ping before it gives up
+    /// its ID and goes back to standby. `None` never gives up.
+    pub heartbeat_timeout: Option<Duration>,
+}
+
+impl Default for SimulatorConfig {
+    fn default() -> Self {
+        SimulatorConfig {
+            cells: 1,
+            capacity_mah: 2500.0,
+            time_scale: 1.0,
+            ping_interval: Duration::from_secs(1),
+            heartbeat_timeout: Some(Duration::from_millis(1500)),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct SimulatedCell {
+    id: Option<u8>,
+    model: CellModel,
+    waiting_echo_since: Option<Instant>,
+}
+
+/// The firmware side of the Battery Cell Bench Protocol for one or more cells
+/// sharing a port.
+///
+/// `receive` and `tick` take the current time so tests can drive the clock.
+#[derive(Debug)]
+pub struct BenchSimulator {
+    config: SimulatorConfig,
+    cells: Vec<SimulatedCell>,
+    decoder: FrameDecoder,
+    last_ping: Option<Instant>,
+    last_step: Option<Instant>,
+}
+
+impl BenchSimulator {
+    pub fn new(config: SimulatorConfig) -> Self {
+        let cells = (0..config.cells)
+            .map(|_| SimulatedCell {
+                id: None,
+                model: CellModel::new(config.capacity_mah),
+                waiting_echo_since: None,
+            })
+            .collect();
+
+        BenchSimulator {
+            config,
+            cells,
+            decoder: FrameDecoder::new(),
+            last_ping: None,
+            last_step: None,
+        }
+    }
+
+    /// IDs currently claimed by the simulated cells.
+    pub fn ids(&self) -> Vec<Option<u8>> {
+        self.cells.iter().map(|cell| cell.id).collect()
+    }
+
+    pub fn cell(&self, id: u8) -> Option<&CellModel> {
+        self.cells
+            .iter()
+            .find(|cell| cell.id == Some(id))
+            .map(|cell| &cell.model)
+    }
+
+    /// Handles bytes sent by the GUI and returns the replies.
+    pub fn receive(&mut self, bytes: &[u8], now: Instant) -> Vec<BatteryCommand> {
+        let frames = self.decoder.feed(bytes);
+        // bring the cells up to date first so data replies are current, and
+        // keep any completion that produces
+        let mut replies = self.step(now);
+        replies.extend(
+            frames
+                .into_iter()
+                .filter_map(|frame| self.handle(frame.command)),
+        );
+        replies
+    }
+
+    fn handle(&mut self, frame: BatteryCommand) -> Option<BatteryCommand> {
+        let battery_id = frame.battery_id;
+
+        // claimed silently, the GUI sees the new ID on the next Ping
+        if frame.command == Command::AssignId {
+            let cell = self.cells.iter_mut().find(|cell| cell.id.is_none())?;
+            cell.id = Some(battery_id);
+            cell.waiting_echo_since = None;
+            return None;
+        }
+
+        let cell = self
+            .cells
+            .iter_mut()
+            .find(|cell| cell.id == Some(battery_id))?;
+
+        match frame.command {
+            Command::Ping => {
+                cell.waiting_echo_since = None;
+                None
+            }
+            Command::RequestData => Some(BatteryCommand {
+                command: Command::RequestData,
+                battery_id,
+                payload: cell.model.request_data_payload(),
+            }),
+            Command::SetCharge => {
+                cell.model.set_state(BatteryState::Charge);
+                Some(frame)
+            }
+            Command::SetDischarge => {
+                cell.model.set_state(BatteryState::Discharge);
+                Some(frame)
+            }
+            Command::SetStandBy => {
+                cell.model.set_state(BatteryState::Standby);
+                Some(frame)
+            }
+            _ => None,
+        }
+    }
+
+    /// Advances the simulation and returns the frames the bench sends on its
+    /// own: completions, and a Ping per cell every `ping_interval`.
+    pub fn tick(&mut self, now: Instant) -> Vec<BatteryCommand> {
+        let mut frames = self.step(now);
+
+        if let Some(timeout) = self.config.heartbeat_timeout {
+            for cell in &mut self.cells {
+                let missed = cell
+                    .waiting_echo_since
+                    .is_some_and(|since| now.duration_since(since) >= timeout);
+                if missed {
+                    cell.id = None;
+                    cell.waiting_echo_since = None;
+                    cell.model.set_state(BatteryState::Standby);
+                }
+            }
+        }
+
+        let ping_due = self
+            .last_ping
+            .is_none_or(|last| now.duration_since(last) >= self.config.ping_interval);
+        if ping_due {
+            self.last_ping = Some(now);
+
+            for cell in &mut self.cells {
+                if cell.id.is_some() && cell.waiting_echo_since.is_none() {
+                    cell.waiting_echo_since = Some(now);
+                }
+                frames.push(BatteryCommand {
+                    command: Command::Ping,
+                    battery_id: cell.id.unwrap_or(UNASSIGNED_ID),
+                    payload: vec![],
+                });
+            }
+        }
+
+        frames
+    }
+
+    fn step(&mut self, now: Instant) -> Vec<BatteryCommand> {
+        let elapsed = match self.last_step {
+            Some(last) => now.saturating_duration_since(last).as_secs_f32(),
+            None => 0.0,
+        };
+        self.last_step = Some(now);
+
+        let seconds = elapsed * self.config.time_scale;
+        let mut frames = Vec::new();
+
+        for cell in &mut self.cells {
+            let status = cell.model.step(seconds);
+            if let (Some(status), Some(battery_id)) = (status, cell.id) {
+                frames.push(BatteryCommand {
+                    command: Command::RequestCompletion,
+                    battery_id,
+                    payload: vec![status.flags()],
+                });
+            }
+        }
+
+        frames
+    }
+
+    /// Serves `transport` until `running` is cleared or the other end hangs up.
+    pub fn run(
+        mut self,
+        mut transport: Box<dyn Transport>,
+        running: &AtomicBool,
+    ) -> io::Result<()> {
+        let mut chunk = [0u8; 256];
+
+        while running.load(Ordering::SeqCst) {
+            let mut frames = self.tick(Instant::now());
+
+            match transport.read(&mut chunk) {
+                Ok(0) => return Ok(()),
+                Ok(count) => frames.extend(self.receive(&chunk[..count], Instant::now())),
+                Err(e) if e.kind() == io::ErrorKind::TimedOut => {}
+                Err(e) => return Err(e),
+            }
+
+            for frame in frames {
+                transport.write_all(&frame.encode())?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// A simulator running on its own thread, stopped when dropped.
+pub struct SimulatorHandle {
+    running: Arc<AtomicBool>,
+    thread: Option<JoinHandle<io::Result<()>>>,
+}
+
+impl SimulatorHandle {
+    pub fn spawn(config: SimulatorConfig, transport: Box<dyn Transport>) -> SimulatorHandle {
+        let running = Arc::new(AtomicBool::new(true));
+        let thread_running = running.clone();
+        let thread =
+            thread::spawn(move || BenchSimulator::new(config).run(transport, &thread_running));
+
+        SimulatorHandle {
+            running,
+            thread: Some(thread),
+        }
+    }
+
+    pub fn stop(&mut self) -> io::Result<()> {
+        self.running.store(false, Ordering::SeqCst);
+        match self.thread.take() {
+            Some(thread) => thread
+                .join()
+                .unwrap_or_else(|_| Err(io::Error::other("simulator thread panicked"))),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for SimulatorHandle {
+    fn drop(&mut self) {
+        let _ = self.stop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::serial::{session::BenchSession, transport::memory_pipe};
+
+    fn frame(command: Command, battery_id: u8) -> BatteryCommand {
+        BatteryCommand {
+            command,
+            battery_id,
+            payload: vec![],
+        }
+    }
+
+    #[test]
+    fn test_assign_id_after_unassigned_ping() {
+        let start = Instant::now();
+        let mut bench = BenchSimulator::new(SimulatorConfig::default());
+
+        assert_eq!(bench.tick(start), vec![frame(Command::Ping, UNASSIGNED_ID)]);
+        assert!(bench.tick(start + Duration::from_millis(500)).is_empty());
+
+        let replies = bench.receive(&frame(Command::AssignId, 0x23).encode(), start);
+        assert!(replies.is_empty());
+        assert_eq!(bench.ids(), vec![Some(0x23)]);
+
+        let pings = bench.tick(start + Duration::from_secs(1));
+        assert_eq!(pings, vec![frame(Command::Ping, 0x23)]);
+    }
+
+    #[test]
+    fn test_command_ids() {
+        // deliberately not the SDD's, see the module documentation
+        let start = Instant::now();
+        let mut bench = BenchSimulator::new(SimulatorConfig::default());
+        bench.receive(&frame(Command::AssignId, 0x05).encode(), start);
+
+        for (command, id, state) in [
+            (Command::SetCharge, 0x04, BatteryState::Charge),
+            (Command::SetDischarge, 0x05, BatteryState::Discharge),
+            (Command::SetStandBy, 0x06, BatteryState::Standby),
+        ] {
+            let bytes = frame(command, 0x05).encode();
+            assert_eq!(bytes[1], id);
+            bench.receive(&bytes, start);
+            assert_eq!(bench.cell(0x05).unwrap().state(), state);
+        }
+    }
+
+    #[test]
+    fn test_missed_heartbeat_forgets_id() {
+        let start = Instant::now();
+        let mut bench = BenchSimulator::new(SimulatorConfig::default());
+        bench.receive(&frame(Command::AssignId, 0x05).encode(), start);
+        bench.receive(&frame(Command::SetCharge, 0x05).encode(), start);
+
+        // echoed ping keeps the ID
+        bench.tick(start);
+        bench.receive(&frame(Command::Ping, 0x05).encode(), start);
+        bench.tick(start + Duration::from_secs(1));
+        assert_eq!(bench.ids(), vec![Some(0x05)]);
+
+        // unanswered ping does not
+        let pings = bench.tick(start + Duration::from_secs(3));
+        assert_eq!(bench.ids(), vec![None]);
+        assert_eq!(pings, vec![frame(Command::Ping, UNASSIGNED_ID)]);
+    }
+
+    #[test]
+    fn test_cell_model_phases_complete() {
+        let mut cell = CellModel::new(2500.0);
+
+        cell.set_state(BatteryState::Charge);
+        assert!(cell.current_ma() > 0.0);
+        let status = (0..20_000).find_map(|_| cell.step(1.0)).unwrap();
+        assert_eq!(status.flags(), 0x41);
+        assert_eq!(cell.state(), BatteryState::Standby);
+        assert!(cell.state_of_charge() > 0.9);
+
+        cell.set_state(BatteryState::Discharge);
+        assert!(cell.current_ma() < 0.0);
+        assert!(cell.load_ohms() > 0.0);
+        let status = (0..20_000).find_map(|_| cell.step(1.0)).unwrap();
+        assert_eq!(status.phase, CompletionPhase::Discharge);
+        assert_eq!(status.outcome, CompletionOutcome::Success);
+        assert!(cell.state_of_charge() < 0.1);
+        assert!(cell.resistor_temperature() > AMBIENT_TEMPERATURE);
+    }
+
+    #[test]
+    fn test_completion_frame_sent_on_phase_end() {
+        let start = Instant::now();
+        let mut bench = BenchSimulator::new(SimulatorConfig {
+            time_scale: 3600.0,
+            heartbeat_timeout: None,
+            ..SimulatorConfig::default()
+        });
+        bench.tick(start);
+        bench.receive(&frame(Command::AssignId, 0x07).encode(), start);
+        bench.receive(&frame(Command::SetDischarge, 0x07).encode(), start);
+
+        let frames = bench.tick(start + Duration::from_secs(2));
+        let completion = BatteryCommand {
+            command: Command::RequestCompletion,
+            battery_id: 0x07,
+            payload: vec![0x81],
+        };
+        assert!(frames.contains(&completion));
+    }
+
+    #[tokio::test]
+    async fn test_session_against_simulator() {
+        let (gui, bench) = memory_pipe();
+        let mut simulator = SimulatorHandle::spawn(
+            SimulatorConfig {
+                heartbeat_timeout: None,
+                ..SimulatorConfig::default()
+            },
+            Box::new(bench),
+        );
+        let session = BenchSession::from_transport("simulator", Box::new(gui.clone())).unwrap();
+        let mut unsolicited = session.subscribe();
+
+        let ping = unsolicited.recv().await.unwrap();
+        assert_eq!(ping, frame(Command::Ping, UNASSIGNED_ID));
+
+        session.send(&frame(Command::AssignId, 0x11)).await.unwrap();
+        let ping = unsolicited.recv().await.unwrap();
+        assert_eq!(ping, frame(Command::Ping, 0x11));
+
+        let request = BatteryCommand {
+            command: Command::RequestData,
+            battery_id: 0x11,
+            payload: vec![0; 12],
+        };
+        let reply = session.exchange(&request).await.unwrap();
+        let log = request
+            .parse_request_data(&reply.payload, 0x11, "simulator".to_string())
+            .unwrap();
+        assert_eq!(log.id, 0x11);
+        assert_eq!(log.battery_temperature, AMBIENT_TEMPERATURE);
+
+        session.close();
+        gui.close();
+        simulator.stop().unwrap();
+    }
+}
@@ -0,0 +1,340 @@
+use std::{
+    collections::VecDeque,
+    fmt,
+    io::{self, Read, Write},
+    net::TcpStream,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Condvar, Mutex,
+    },
+    time::Duration,
+};
+
+use serialport::SerialPort;
+
+use crate::serial::serial::BenchError;
+
+const BAUD_RATE: u32 = 9600;
+pub const READ_TIMEOUT: Duration = Duration::from_millis(100);
+
+const TCP_PREFIX: &str = "tcp://";
+
+/// A byte stream the Battery Cell Bench Protocol can run over.
+///
+/// Reads must give up after roughly [`READ_TIMEOUT`] with
+/// `io::ErrorKind::TimedOut` and return `Ok(0)` once the other end is gone.
+pub trait Transport: Read + Write + Send {
+    /// Another handle on the same connection, so one thread can read while
+    /// another writes.
+    fn try_clone(&self) -> io::Result<Box<dyn Transport>>;
+}
+
+/// Where a bench can be reached.
+///
+/// Written as a plain serial port name (`COM3`, `/dev/ttyUSB0`) or as
+/// `tcp://host:port` for a bench exposed through ser2net.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Endpoint {
+    Serial(String),
+    Tcp(String),
+}
+
+impl Endpoint {
+    pub fn parse(address: &str) -> Endpoint {
+        match address.strip_prefix(TCP_PREFIX) {
+            Some(host) => Endpoint::Tcp(host.to_string()),
+            None => Endpoint::Serial(address.to_string()),
+        }
+    }
+
+    pub fn connect(&self) -> Result<Box<dyn Transport>, BenchError> {
+        match self {
+            Endpoint::Serial(port) => Ok(Box::new(SerialTransport::open(port)?)),
+            Endpoint::Tcp(address) => Ok(Box::new(TcpTransport::connect(address)?)),
+        }
+    }
+}
+
+impl fmt::Display for Endpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Endpoint::Serial(port) => write!(f, "{port}"),
+            Endpoint::Tcp(address) => write!(f, "{TCP_PREFIX}{address}"),
+        }
+    }
+}
+
+pub struct SerialTransport {
+    port: Box<dyn SerialPort>,
+}
+
+impl SerialTransport {
+    pub fn open(port: &str) -> Result<SerialTransport, BenchError> {
+        let port = serialport::new(port, BAUD_RATE)
+            .timeout(READ_TIMEOUT)
+            .open()?;
+        Ok(SerialTransport { port })
+    }
+}
+
+impl From<Box<dyn SerialPort>> for SerialTransport {
+    fn from(port: Box<dyn SerialPort>) -> Self {
+        SerialTransport { port }
+    }
+}
+
+impl Read for SerialTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.port.read(buf)
+    }
+}
+
+impl Write for SerialTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.port.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.port.flush()
+    }
+}
+
+impl Transport for SerialTransport {
+    fn try_clone(&self) -> io::Result<Box<dyn Transport>> {
+        let port = self.port.try_clone().map_err(io::Error::from)?;
+        Ok(Box::new(SerialTransport { port }))
+    }
+}
+
+pub struct TcpTransport {
+    stream: TcpStream,
+}
+
+impl TcpTransport {
+    pub fn connect(address: &str) -> Result<TcpTransport, BenchError> {
+        let stream = TcpStream::connect(address)?;
+        stream.set_read_timeout(Some(READ_TIMEOUT))?;
+        Ok(TcpTransport::from(stream))
+    }
+}
+
+impl From<TcpStream> for TcpTransport {
+    /// Wraps an accepted connection, setting the same options as `connect`.
+    fn from(stream: TcpStream) -> Self {
+        let _ = stream.set_read_timeout(Some(READ_TIMEOUT));
+        let _ = stream.set_nodelay(true);
+        TcpTransport { stream }
+    }
+}
+
+impl Read for TcpTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.stream.read(buf) {
+            // sockets report an expired read timeout as WouldBlock on unix
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                Err(io::Error::from(io::ErrorKind::TimedOut))
+            }
+            result => result,
+        }
+    }
+}
+
+impl Write for TcpTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.stream.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+impl Transport for TcpTransport {
+    fn try_clone(&self) -> io::Result<Box<dyn Transport>> {
+        Ok(Box::new(TcpTransport {
+            stream: self.stream.try_clone()?,
+        }))
+    }
+}
+
+/// One direction of a [`MemoryTransport`] pair.
+#[derive(Default)]
+struct Channel {
+    bytes: Mutex<VecDeque<u8>>,
+    ready: Condvar,
+    closed: AtomicBool,
+}
+
+impl Channel {
+    fn close(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+        self.ready.notify_all();
+    }
+}
+
+/// One end of an in-memory duplex pipe, see [`memory_pipe`].
+#[derive(Clone)]
+pub struct MemoryTransport {
+    incoming: Arc<Channel>,
+    outgoing: Arc<Channel>,
+}
+
+/// Two connected ends of an in-memory duplex pipe: bytes written to one end
+/// are read from the other.
+pub fn memory_pipe() -> (MemoryTransport, MemoryTransport) {
+    let left = Arc::new(Channel::default());
+    let right = Arc::new(Channel::default());
+
+    (
+        MemoryTransport {
+            incoming: left.clone(),
+            outgoing: right.clone(),
+        },
+        MemoryTransport {
+            incoming: right,
+            outgoing: left,
+        },
+    )
+}
+
+impl MemoryTransport {
+    /// Hangs up both directions, the other end reads `Ok(0)` from now on.
+    pub fn close(&self) {
+        self.incoming.close();
+        self.outgoing.close();
+    }
+}
+
+impl Read for MemoryTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let bytes = self
+            .incoming
+            .bytes
+            .lock()
+            .map_err(|e| io::Error::other(e.to_string()))?;
+        let (mut bytes, _) = self
+            .incoming
+            .ready
+            .wait_timeout_while(bytes, READ_TIMEOUT, |bytes| {
+                bytes.is_empty() && !self.incoming.closed.load(Ordering::SeqCst)
+            })
+            .map_err(|e| io::Error::other(e.to_string()))?;
+
+        if bytes.is_empty() {
+            if self.incoming.closed.load(Ordering::SeqCst) {
+                return Ok(0);
+            }
+            return Err(io::Error::from(io::ErrorKind::TimedOut));
+        }
+
+        let count = buf.len().min(bytes.len());
+        for (slot, byte) in buf.iter_mut().zip(bytes.drain(..count)) {
+            *slot = byte;
+        }
+        Ok(count)
+    }
+}
+
+impl Write for MemoryTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.outgoing.closed.load(Ordering::SeqCst) {
+            return Err(io::Error::from(io::ErrorKind::BrokenPipe));
+        }
+
+        let mut bytes = self
+            .outgoing
+            .bytes
+            .lock()
+            .map_err(|e| io::Error::other(e.to_string()))?;
+        bytes.extend(buf);
+        self.outgoing.ready.notify_all();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Transport for MemoryTransport {
+    fn try_clone(&self) -> io::Result<Box<dyn Transport>> {
+        Ok(Box::new(self.clone()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_endpoint_parse() {
+        assert_eq!(
+            Endpoint::parse("/dev/ttyUSB0"),
+            Endpoint::Serial("/dev/ttyUSB0".to_string())
+        );
+        assert_eq!(
+            Endpoint::parse("tcp://10.0.0.12:4001"),
+            Endpoint::Tcp("10.0.0.12:4001".to_string())
+        );
+        assert_eq!(
+            Endpoint::parse("tcp://10.0.0.12:4001").to_string(),
+            "tcp://10.0.0.12:4001"
+        );
+    }
+
+    #[test]
+    fn test_memory_pipe_roundtrip() {
+        let (mut gui, mut bench) = memory_pipe();
+        gui.write_all(&[0xB3, 0x00, 0x23]).unwrap();
+
+        let mut buf = [0u8; 8];
+        let count = bench.read(&mut buf).unwrap();
+        assert_eq!(&buf[..count], &[0xB3, 0x00, 0x23]);
+
+        let error = bench.read(&mut buf).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::TimedOut);
+    }
+
+    #[test]
+    fn test_memory_pipe_close() {
+        let (gui, mut bench) = memory_pipe();
+        let mut writer = gui.try_clone().unwrap();
+        writer.write_all(&[0x01]).unwrap();
+        gui.close();
+
+        let mut buf = [0u8; 8];
+        assert_eq!(bench.read(&mut buf).unwrap(), 1);
+        assert_eq!(bench.read(&mut buf).unwrap(), 0);
+        assert!(bench.write(&[0x02]).is_err());
+    }
+
+    #[test]
+    fn test_tcp_transport() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buf = [0u8; 4];
+            stream.read_exact(&mut buf).unwrap();
+            stream.write_all(&buf).unwrap();
+        });
+
+        let mut transport = Endpoint::parse(&format!("tcp://{address}"))
+            .connect()
+            .unwrap();
+        transport.write_all(&[0xB3, 0x00, 0x23, 0x42]).unwrap();
+
+        let mut buf = [0u8; 4];
+        let mut read = 0;
+        while read < buf.len() {
+            match transport.read(&mut buf[read..]) {
+                Ok(count) => read += count,
+                Err(e) => assert_eq!(e.kind(), io::ErrorKind::TimedOut),
+            }
+        }
+        assert_eq!(buf, [0xB3, 0x00, 0x23, 0x42]);
+
+        server.join().unwrap();
+    }
+}
@@ -1,10 +1,24 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
 use diesel::SqliteConnection;
 
-use crate::database::models::Test;
+use crate::{
+    database::{models::Test, writer::LogWriter},
+    serial::{sequencer::RunningSequence, session::BenchSession},
+};
 
 #[derive(Default)]
 pub struct AppState {
     pub db_path: String,
     pub db_connection: Option<SqliteConnection>,
+    /// Stores samples of running sequences, once the database is open.
+    pub log_writer: Option<LogWriter>,
     pub tests: Vec<Test>,
+    pub sessions: HashMap<String, Arc<BenchSession>>,
+    /// IDs sent to a bench that has not claimed them yet.
+    pub pending_battery_ids: HashSet<u8>,
+    pub sequences: HashMap<String, RunningSequence>,
 }
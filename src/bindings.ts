@@ -21,6 +21,93 @@ async exportCsv(basePath: string) : Promise<Result<null, string>> {
     else return { status: "error", error: e  as any };
 }
 },
+/**
+ * Exports the selected samples of each test to one file per battery, and
+ * its verdicts if it was evaluated, returning the files written.
+ */
+async exportTestCsv(basePath: string, options: ExportOptions) : Promise<Result<string[], string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("export_test_csv", { basePath, options }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Exports a test as typed columns for pandas, polars and the like.
+ */
+async exportTestColumnar(basePath: string, targetTestId: number, format: ColumnarFormat) : Promise<Result<string[], string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("export_test_columnar", { basePath, targetTestId, format }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Writes a test as a JSON bundle to `base_path`, returning the file written.
+ */
+async exportTestBundle(basePath: string, targetTestId: number) : Promise<Result<string, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("export_test_bundle", { basePath, targetTestId }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Imports the JSON bundles and CSV exports in `dir` as new tests.
+ */
+async importTests(dir: string) : Promise<Result<ImportReport, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("import_tests", { dir }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Writes a test and everything recorded with it to a single archive.
+ */
+async exportTestArchive(filePath: string, targetTestId: number, notes: string | null) : Promise<Result<Manifest, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("export_test_archive", { filePath, targetTestId, notes }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async importTestArchive(filePath: string) : Promise<Result<ArchiveImport, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("import_test_archive", { filePath }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * A page of the filtered samples, for tables and exports that need every
+ * row without holding them all.
+ */
+async queryBatteryLogs(filter: LogFilter, after: LogCursor | null, limit: number) : Promise<Result<LogPage, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("query_battery_logs", { filter, after, limit }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * The filtered samples reduced to about `points` per battery, for charts.
+ */
+async getDownsampledLogs(filter: LogFilter, points: number, method: Downsampling, quantity: LogQuantity) : Promise<Result<BatteryLog[], string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("get_downsampled_logs", { filter, points, method, quantity }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
 async parseLog(onEvent: TAURI_CHANNEL<BatteryLog>) : Promise<void> {
     await TAURI_INVOKE("parse_log", { onEvent });
 },
@@ -32,7 +119,7 @@ async getAllBatteryLogs() : Promise<Result<BatteryLog[], string>> {
     else return { status: "error", error: e  as any };
 }
 },
-async commandRequest(command: Command, portNum: string) : Promise<Result<number[], string>> {
+async commandRequest(command: Command, portNum: string) : Promise<Result<number[], BenchError>> {
     try {
     return { status: "ok", data: await TAURI_INVOKE("command_request", { command, portNum }) };
 } catch (e) {
@@ -40,7 +127,7 @@ async commandRequest(command: Command, portNum: string) : Promise<Result<number[
     else return { status: "error", error: e  as any };
 }
 },
-async detectSerialPorts() : Promise<Result<string[], string>> {
+async detectSerialPorts() : Promise<Result<string[], BenchError>> {
     try {
     return { status: "ok", data: await TAURI_INVOKE("detect_serial_ports") };
 } catch (e) {
@@ -96,7 +183,7 @@ async insertNewTest() : Promise<Result<Test, string>> {
     else return { status: "error", error: e  as any };
 }
 },
-async dataRequest(bench: Bench, battery: Battery) : Promise<Result<BatteryLog, string>> {
+async dataRequest(bench: Bench, battery: Battery) : Promise<Result<BatteryLog, BenchError>> {
     try {
     return { status: "ok", data: await TAURI_INVOKE("data_request", { bench, battery }) };
 } catch (e) {
@@ -104,7 +191,7 @@ async dataRequest(bench: Bench, battery: Battery) : Promise<Result<BatteryLog, s
     else return { status: "error", error: e  as any };
 }
 },
-async assignId(bench: Bench) : Promise<Result<number, string>> {
+async assignId(bench: Bench) : Promise<Result<number, BenchError>> {
     try {
     return { status: "ok", data: await TAURI_INVOKE("assign_id", { bench }) };
 } catch (e) {
@@ -112,19 +199,313 @@ async assignId(bench: Bench) : Promise<Result<number, string>> {
     else return { status: "error", error: e  as any };
 }
 },
-async setState(bench: Bench, battery: Battery, newState: BatteryState) : Promise<Result<string, string>> {
+async setState(bench: Bench, battery: Battery, newState: BatteryState) : Promise<Result<string, BenchError>> {
     try {
     return { status: "ok", data: await TAURI_INVOKE("set_state", { bench, battery, newState }) };
 } catch (e) {
     if(e instanceof Error) throw e;
     else return { status: "error", error: e  as any };
 }
+},
+async openBench(port: string) : Promise<Result<SessionStatus, BenchError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("open_bench", { port }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async closeBench(port: string) : Promise<Result<null, BenchError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("close_bench", { port }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async listBenches() : Promise<Result<SessionStatus[], BenchError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("list_benches") };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async getBatteryIds() : Promise<Result<BatteryIdAssignment[], string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("get_battery_ids") };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async getReservedIds() : Promise<Result<ReservedId[], string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("get_reserved_ids") };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async reserveBatteryId(cellLabel: string, batteryId: number, testId: number | null) : Promise<Result<ReservedId, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("reserve_battery_id", { cellLabel, batteryId, testId }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async releaseReservedId(batteryId: number) : Promise<Result<null, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("release_reserved_id", { batteryId }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async assignReservedId(port: string, batteryId: number) : Promise<Result<number, BenchError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("assign_reserved_id", { port, batteryId }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async setAutoAssign(port: string, enabled: boolean) : Promise<Result<SessionStatus, BenchError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("set_auto_assign", { port, enabled }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async getCompletionEvents(targetTestId: number | null) : Promise<Result<CompletionEvent[], string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("get_completion_events", { targetTestId }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Starts the qualification sequence on `port` for `battery_ids`, or for
+ * every battery currently pinging on it when the list is empty.
+ */
+async startSequence(port: string, batteryIds: number[], testId: number, onEvent: TAURI_CHANNEL<BatteryLog>) : Promise<Result<BatteryProgress[], BenchError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("start_sequence", { port, batteryIds, testId, onEvent }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async stopSequence(port: string) : Promise<Result<null, BenchError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("stop_sequence", { port }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async getSequenceProgress(port: string) : Promise<Result<BatteryProgress[], BenchError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("get_sequence_progress", { port }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Sequences left running by a previous run of the application, except the
+ * ones running again.
+ */
+async getInterruptedSequences() : Promise<Result<InterruptedSequence[], BenchError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("get_interrupted_sequences") };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Reconnects to the bench on `port`, gives the batteries of the interrupted
+ * sequence of `test_id` their IDs back and carries on from where each one
+ * was, following `policy`.
+ */
+async resumeSequence(port: string, testId: number, policy: ResumePolicy, onEvent: TAURI_CHANNEL<BatteryLog>) : Promise<Result<BatteryProgress[], BenchError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("resume_sequence", { port, testId, policy, onEvent }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Gives up on an interrupted sequence, leaving its batteries as stopped.
+ */
+async discardInterruptedSequence(port: string, testId: number) : Promise<Result<null, BenchError>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("discard_interrupted_sequence", { port, testId }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async getProfiles() : Promise<Result<StoredProfile[], string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("get_profiles") };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Reads a profile from a `.json` or `.toml` file and stores it.
+ */
+async importProfile(path: string) : Promise<Result<StoredProfile, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("import_profile", { path }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Writes a stored profile to a `.json` or `.toml` file.
+ */
+async exportProfile(profileId: number, path: string) : Promise<Result<null, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("export_profile", { profileId, path }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async deleteProfile(profileId: number) : Promise<Result<null, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("delete_profile", { profileId }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Sets the profile a test runs, `None` for the qualification profile.
+ */
+async setTestProfile(targetTestId: number, profileId: number | null) : Promise<Result<Test, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("set_test_profile", { targetTestId, profileId }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async getSafetyLimits(targetTestId: number) : Promise<Result<SafetyLimits, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("get_safety_limits", { targetTestId }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Sets the interlock limits of `limits.test_id`. Sequences already running
+ * keep the limits they started with.
+ */
+async setSafetyLimits(limits: SafetyLimits) : Promise<Result<SafetyLimits, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("set_safety_limits", { limits }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async getAlarms(targetTestId: number | null) : Promise<Result<AlarmRecord[], string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("get_alarms", { targetTestId }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Segments the test into phases and computes their capacity and energy.
+ */
+async analyzePhases(targetTestId: number) : Promise<Result<TestAnalysis, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("analyze_phases", { targetTestId }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async getPhaseResults(targetTestId: number) : Promise<Result<TestAnalysis, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("get_phase_results", { targetTestId }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async getAcceptanceCriteria(targetTestId: number) : Promise<Result<AcceptanceCriteria, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("get_acceptance_criteria", { targetTestId }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async setAcceptanceCriteria(criteria: AcceptanceCriteria) : Promise<Result<AcceptanceCriteria, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("set_acceptance_criteria", { criteria }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Evaluates every cell of the test against its acceptance criteria.
+ */
+async evaluateAcceptance(targetTestId: number) : Promise<Result<BatteryVerdict[], string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("evaluate_acceptance", { targetTestId }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async getVerdicts(targetTestId: number) : Promise<Result<BatteryVerdict[], string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("get_verdicts", { targetTestId }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Writes the qualification report of a test to `file_path` as HTML. There is
+ * no PDF output; a PDF is had by printing the HTML from a browser.
+ */
+async exportReport(targetTestId: number, filePath: string) : Promise<Result<null, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("export_report", { targetTestId, filePath }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
 }
 }
 
 /** user-defined events **/
 
 
+export const events = __makeEvents__<{
+alarm: Alarm,
+benchLost: BenchLost
+}>({
+alarm: "alarm",
+benchLost: "bench-lost"
+})
 
 /** user-defined constants **/
 
@@ -132,13 +513,319 @@ async setState(bench: Bench, battery: Battery, newState: BatteryState) : Promise
 
 /** user-defined types **/
 
+/**
+ * What a cell has to meet to be accepted. Criteria left `None` are not
+ * evaluated.
+ */
+export type AcceptanceCriteria = { test_id: number; 
+/**
+ * Capacity of the last discharge.
+ */
+min_discharge_capacity_mah: number | null; 
+/**
+ * Loss of discharge capacity from the first to the last cycle.
+ */
+max_capacity_fade_pct: number | null; 
+/**
+ * Battery temperature above the first sample of the test.
+ */
+max_temperature_rise_c: number | null; min_voltage_mv: number | null; max_voltage_mv: number | null; 
+/**
+ * Estimated from the voltage step when the current changes.
+ */
+max_internal_resistance_mohm: number | null }
+/**
+ * Sent to the frontend when the interlock puts a battery in standby.
+ */
+export type Alarm = { port: string; battery_id: number; test_id: number | null; quantity: Quantity; value: number; threshold: number; 
+/**
+ * What the battery was doing when the limit was crossed.
+ */
+state: string; raised_at: string; 
+/**
+ * Whether the bench acknowledged the SetStandBy frame.
+ */
+standby: boolean }
+export type AlarmRecord = { alarm_id: number | null; battery_id: number; port: string; quantity: string; value: number; threshold: number; state: string; raised_at: string; test_id: number | null }
+export type ArchiveEntry = { name: string; bytes: number; 
+/**
+ * SHA-256 of the entry, hex encoded.
+ */
+sha256: string }
+export type ArchiveImport = { test: ImportedTest; notes: string | null }
 export type Battery = { id: number; state: BatteryState }
-export type BatteryLog = { record_id: number | null; id: number; port: string; battery_temperature: number; bench_temperature_mosfet: number; bench_temperature_resistor: number; load: number; voltage: number; current: number; state: string; status: string; start_date: string | null; end_date: string | null; test_id: number }
+export type BatteryIdAssignment = { battery_id: number; port: string; assigned_at: string }
+export type BatteryLog = { record_id: number | null; id: number; port: string; 
+/**
+ * Temperatures in °C.
+ */
+battery_temperature: number; bench_temperature_mosfet: number; bench_temperature_resistor: number; 
+/**
+ * Electronic load resistance in ohms.
+ */
+load: number; 
+/**
+ * Battery voltage in mV.
+ */
+voltage: number; 
+/**
+ * Bench current in mA, negative while discharging.
+ */
+current: number; state: string; status: string; start_date: string | null; end_date: string | null; test_id: number; 
+/**
+ * When the sample was taken, RFC 3339 UTC.
+ */
+recorded_at: string; 
+/**
+ * Seconds since the test started, on a clock that ignores wall-clock
+ * adjustments.
+ */
+elapsed_s: number }
+/**
+ * Where one battery is in its sequence.
+ */
+export type BatteryProgress = { battery_id: number; 
+/**
+ * Index of the current step.
+ */
+step: number; steps: number; 
+/**
+ * Label of the current step in the profile.
+ */
+step_label: string; state: BatteryState; outcome: SequenceOutcome; 
+/**
+ * When the current step started, RFC 3339.
+ */
+step_started_at: string; 
+/**
+ * Charge moved in or out of the battery during the current step.
+ */
+charge_mah: number }
 export type BatteryState = "Standby" | "Charge" | "Discharge"
+/**
+ * Verdict of one cell with the evidence for it.
+ */
+export type BatteryVerdict = { battery_id: number; verdict: Verdict; evaluated_at: string; criteria: CriterionResult[] }
 export type Bench = { batteries: Battery[]; port: string }
+/**
+ * Errors of a request/response exchange with a bench.
+ */
+export type BenchError = { kind: "Protocol"; details: ProtocolError } | { kind: "UnexpectedBatteryId"; details: { expected: number; received: number } } | { kind: "WrongReply"; details: { expected: Command; received: Command } } | { kind: "Timeout"; details: { waited_ms: number } } | { kind: "NoReply"; details: { command: Command } } | { kind: "Io"; details: { message: string } } | { kind: "NotConnected"; details: { port: string } } | { kind: "NoFreeId" } | { kind: "UnclaimedId"; details: { battery_id: number } } | { kind: "IdNotReserved"; details: { battery_id: number } } | { kind: "IdInUse"; details: { battery_id: number } } | { kind: "NoBatteries"; details: { port: string } } | { kind: "SequenceRunning"; details: { port: string } } | { kind: "NoSequence"; details: { port: string } } | { kind: "Database"; details: { message: string } } | { kind: "CannotResume"; details: { test_id: number; message: string } }
+/**
+ * Sent to the frontend when a battery stops pinging.
+ */
+export type BenchLost = { port: string; battery_id: number; silent_ms: number }
+export type ColumnarFormat = "Parquet" | 
+/**
+ * Arrow IPC file format, also known as Feather v2.
+ */
+"ArrowIpc"
 export type Command = "Ping" | "AssignId" | "RequestData" | "SetCharge" | "SetDischarge" | "SetStandBy" | "RequestCompletion"
-export type TAURI_CHANNEL<TSend> = null
-export type Test = { test_id: number | null; test_name: string; start_date: string }
+export type CompletionEvent = { record_id: number | null; battery_id: number; port: string; phase: string; outcome: string; flags: number; received_at: string; test_id: number | null }
+export type CompletionOutcome = "InProgress" | "Failed" | "Success"
+export type CompletionPhase = "Charge" | "Discharge"
+/**
+ * The decoded `State and completion status` byte.
+ */
+export type CompletionStatus = { phase: CompletionPhase; outcome: CompletionOutcome }
+export type CriterionResult = { test_id: number; battery_id: number; criterion: string; measured: number | null; threshold: number; outcome: string; 
+/**
+ * Where the measured value comes from.
+ */
+evidence: string }
+export type CurrentUnit = "Milliampere" | "Ampere"
+/**
+ * Efficiency of one charge followed by a discharge.
+ */
+export type CycleResult = { battery_id: number; cycle: number; charge_mah: number; discharge_mah: number; charge_wh: number; discharge_wh: number; 
+/**
+ * Discharged over charged mAh, once the cycle has both phases.
+ */
+coulombic_efficiency: number | null; 
+/**
+ * Discharged over charged Wh, once the cycle has both phases.
+ */
+energy_efficiency: number | null }
+export type Downsampling = 
+/**
+ * The lowest and highest sample of each time bucket, which keeps spikes.
+ */
+"MinMax" | 
+/**
+ * One sample per time bucket with every reading averaged.
+ */
+"Average" | 
+/**
+ * Largest-Triangle-Three-Buckets, which keeps the shape of the curve.
+ */
+"Lttb"
+export type ExportColumn = "RecordedAt" | "Elapsed" | "BatteryId" | "Port" | "State" | "Status" | "Voltage" | "Current" | "Load" | "BatteryTemperature" | "MosfetTemperature" | "ResistorTemperature"
+/**
+ * What [`export_test_csv`] writes.
+ */
+export type ExportOptions = { test_ids: number[]; 
+/**
+ * Every battery of the tests when `None`.
+ */
+battery_ids: number[] | null; 
+/**
+ * Seconds since the start of the test, inclusive.
+ */
+from_s: number | null; to_s: number | null; 
+/**
+ * [`DEFAULT_COLUMNS`] when empty.
+ */
+columns: ExportColumn[]; units: ExportUnits }
+export type ExportUnits = { voltage: VoltageUnit; current: CurrentUnit; 
+/**
+ * Unit of the elapsed time column.
+ */
+time: TimeUnit }
+export type ImportReport = { tests: ImportedTest[]; rejected: RejectedRow[]; 
+/**
+ * Files that hold no samples, such as verdicts and reports.
+ */
+skipped: string[] }
+export type ImportedTest = { 
+/**
+ * The id the test had where it was exported, if the files tell.
+ */
+source_test_id: number | null; 
+/**
+ * The id it got here, different when the source id was taken.
+ */
+test_id: number; test_name: string; rows: number }
+/**
+ * A sequence that was still running when the application stopped.
+ */
+export type InterruptedSequence = { port: string; test_id: number; batteries: BatteryProgress[] }
+/**
+ * Where the next page starts: after this sample in time order.
+ */
+export type LogCursor = { elapsed_s: number; record_id: number }
+/**
+ * Which samples of a test to read. Unset fields don't filter.
+ */
+export type LogFilter = { test_id: number; battery_ids: number[] | null; 
+/**
+ * Seconds since the start of the test, inclusive.
+ */
+from_s: number | null; to_s: number | null; states: string[] | null; statuses: string[] | null }
+export type LogPage = { logs: BatteryLog[]; 
+/**
+ * `None` on the last page.
+ */
+next: LogCursor | null }
+/**
+ * The reading [`Downsampling::MinMax`] and [`Downsampling::Lttb`] pick
+ * samples by.
+ */
+export type LogQuantity = "Voltage" | "Current" | "Load" | "BatteryTemperature" | "MosfetTemperature" | "ResistorTemperature"
+/**
+ * Describes the archive, so it can be checked and understood without the
+ * application.
+ */
+export type Manifest = { format: string; format_version: number; created_at: string; app_version: string; test_id: number; test_name: string; battery_ids: number[]; samples: number; 
+/**
+ * Written by the operator when exporting.
+ */
+notes: string | null; entries: ArchiveEntry[] }
+/**
+ * Charge and energy of one charge or discharge phase of a battery.
+ */
+export type PhaseResult = { test_id: number; battery_id: number; 
+/**
+ * Position of the phase among the battery's phases, from 0.
+ */
+phase_index: number; 
+/**
+ * Charge/discharge cycle the phase belongs to, from 1.
+ */
+cycle: number; state: string; 
+/**
+ * Step label the samples were logged with.
+ */
+label: string; 
+/**
+ * How the bench announced the end of the phase, if it did.
+ */
+outcome: string | null; started_at: string; ended_at: string; duration_s: number; samples: number; charge_mah: number; energy_wh: number; computed_at: string }
+/**
+ * A charge or discharge step.
+ */
+export type PhaseStep = ({ 
+/**
+ * Fails the step when the bench hasn't completed it by then.
+ */
+max_duration_s?: number | null; 
+/**
+ * Fails the step when the battery gets hotter, in °C.
+ */
+max_temperature_c?: number | null; 
+/**
+ * Ends the step before the bench does once the voltage gets there, e.g.
+ * for a storage charge.
+ */
+until_voltage_mv?: number | null }) & { label?: string | null }
+export type ProfileStep = ({ kind: "charge" } & PhaseStep) | ({ kind: "discharge" } & PhaseStep) | 
+/**
+ * Standby for a fixed time, to let the cell settle.
+ */
+{ kind: "rest"; label?: string | null; duration_s: number } | { kind: "repeat"; count: number; steps: ProfileStep[] }
+/**
+ * Errors in the content of a single frame.
+ */
+export type ProtocolError = { kind: "ShortFrame"; details: { length: number } } | { kind: "CrcMismatch"; details: { expected: number; received: number } } | { kind: "UnknownCommand"; details: { id: number } } | { kind: "PayloadLength"; details: { command: Command; expected: number; received: number } } | { kind: "WrongCommand"; details: { expected: Command; received: Command } } | { kind: "InvalidCompletion"; details: { flags: number } }
+export type Quantity = "BatteryTemperature" | "MosfetTemperature" | "ResistorTemperature" | "Voltage" | "Current"
+/**
+ * A row, or a whole file when `line` is `None`, that wasn't imported.
+ */
+export type RejectedRow = { file: string; line: number | null; reason: string }
+export type ReservedId = { battery_id: number; cell_label: string; test_id: number | null; reserved_at: string }
+/**
+ * What to do with the step a sequence was interrupted in.
+ */
+export type ResumePolicy = 
+/**
+ * Carry on with the time and charge the step already had.
+ */
+"Continue" | 
+/**
+ * Start the step over.
+ */
+"RestartStep"
+/**
+ * Interlock limits of a test. Temperatures are in °C, voltage in mV and
+ * current in mA, either direction.
+ */
+export type SafetyLimits = { test_id: number; max_battery_temperature_c: number | null; max_mosfet_temperature_c: number | null; max_resistor_temperature_c: number | null; min_voltage_mv: number | null; max_voltage_mv: number | null; max_current_ma: number | null }
+export type SequenceOutcome = "Running" | "Complete" | "Failed" | "Stopped" | 
+/**
+ * Put in standby by the safety interlock.
+ */
+"Aborted"
+export type SessionStatus = { port: string; connected: boolean; 
+/**
+ * Whether benches pinging `0xFF` get an ID automatically.
+ */
+auto_assign: boolean }
+/**
+ * A profile as stored in the database.
+ */
+export type StoredProfile = { profile_id: number; created_at: string; profile: TestProfile }
+export type Test = { test_id: number | null; test_name: string; start_date: string; profile_id: number | null }
+export type TestAnalysis = { test_id: number; phases: PhaseResult[]; cycles: CycleResult[] }
+/**
+ * An ordered list of steps run on every battery of a test.
+ */
+export type TestProfile = { name: string; description?: string | null; steps: ProfileStep[] }
+export type TimeUnit = "Second" | "Minute" | "Hour"
+export type Verdict = "Pass" | "Fail" | 
+/**
+ * Nothing failed, but a criterion could not be measured or none are set.
+ */
+"Inconclusive"
+export type VoltageUnit = "Millivolt" | "Volt"
 
 /** tauri-specta globals **/
 
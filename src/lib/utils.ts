@@ -1,6 +1,57 @@
 import { type ClassValue, clsx } from 'clsx'
 import { twMerge } from 'tailwind-merge'
+import type { BenchError } from '@/bindings'
 
 export function cn(...inputs: ClassValue[]) {
   return twMerge(clsx(inputs))
 }
+
+export function describeBenchError(error: BenchError): string {
+  switch (error.kind) {
+    case 'Protocol':
+      switch (error.details.kind) {
+        case 'ShortFrame':
+          return `Frame too short (${error.details.details.length} bytes)`
+        case 'CrcMismatch':
+          return `Invalid CRC: expected ${error.details.details.expected}, got ${error.details.details.received}`
+        case 'UnknownCommand':
+          return `Unknown command ID ${error.details.details.id}`
+        case 'PayloadLength':
+          return `Invalid ${error.details.details.command} payload length`
+        case 'WrongCommand':
+          return `Got ${error.details.details.received}, expected ${error.details.details.expected}`
+        case 'InvalidCompletion':
+          return `Invalid completion status flags 0x${error.details.details.flags.toString(16)}`
+      }
+      break
+    case 'UnexpectedBatteryId':
+      return `Response from battery ${error.details.received}, expected battery ${error.details.expected}`
+    case 'WrongReply':
+      return `Got ${error.details.received} reply, expected ${error.details.expected}`
+    case 'Timeout':
+      return `No response after ${error.details.waited_ms} ms`
+    case 'Io':
+      return error.details.message
+    case 'NotConnected':
+      return `Bench on ${error.details.port} is not connected`
+    case 'NoFreeId':
+      return 'All battery IDs are taken'
+    case 'UnclaimedId':
+      return `Bench did not claim battery ID ${error.details.battery_id}`
+    case 'IdNotReserved':
+      return `Battery ID ${error.details.battery_id} is not reserved`
+    case 'IdInUse':
+      return `Battery ID ${error.details.battery_id} is already used by a connected bench`
+    case 'Database':
+      return `Database error: ${error.details.message}`
+    case 'NoBatteries':
+      return `No batteries found on ${error.details.port}`
+    case 'SequenceRunning':
+      return `A sequence is already running on ${error.details.port}`
+    case 'NoSequence':
+      return `No sequence is running on ${error.details.port}`
+    case 'CannotResume':
+      return `Cannot resume test ${error.details.test_id}: ${error.details.message}`
+  }
+  return 'Unknown error occurred'
+}